@@ -2,16 +2,36 @@ pub mod ethernet_frame;
 pub mod ethernet_log;
 pub mod ethernet_device;
 pub mod ethernet_host;
+pub mod ethernet_app;
+pub mod arq;
 pub mod ethernet_switch;
+pub mod stp;
+pub mod mac_table;
+pub mod multicast;
+pub mod lacp;
+pub mod pause;
+pub mod qos;
+pub mod lldp;
+pub mod ppp;
 
 pub use ethernet_frame::*;
 pub use ethernet_log::*;
 pub use ethernet_device::*;
 pub use ethernet_host::*;
+pub use ethernet_app::*;
+pub use arq::*;
 pub use ethernet_switch::*;
+pub use stp::*;
+pub use mac_table::*;
+pub use multicast::*;
+pub use lacp::*;
+pub use pause::*;
+pub use qos::*;
+pub use lldp::*;
+pub use ppp::*;
 
 use super::types::{Port, Mac, Res};
-use super::physl::Network;
+use super::physl::{ByteHost, ByteLog, Network, PhyStats, PortConfig};
 
 
 pub fn run_sample() -> Res<EthernetLog> {
@@ -80,9 +100,462 @@ pub fn run_sample_3host() -> Res<EthernetLog> {
     Ok(log.clone())
 }
 
+// a small mac address table overflows, then its entries age out
+pub fn run_sample_mac_table() -> Res<()> {
+    println!("run experimental linkl mac table sample");
+    crate::output::set_level(crate::output::Level::Frame);
+    let mac_a = Mac::new(21);
+    let mac_b = Mac::new(22);
+    let mac_c = Mac::new(23);
+    let mac_s = Mac::new(30);
+
+    let mut host_a = EthernetHost::build_consumer(mac_a, "host_a");
+    let host_b = EthernetHost::build_echo(mac_b, "host_b");
+    let mut host_c = EthernetHost::build_consumer(mac_c, "host_c");
+    let mut switch = EthernetSwitch::build_switch(mac_s, "switch", 3);
+    let table = switch.mac_table_mut();
+    table.set_aging_time(Some(500));
+    table.set_capacity(Some(2), EvictionPolicy::NoLearning);
+    table.add_static(mac_b, Port::new(1));
+
+    host_a.add_schedule(0, EthernetFrame::new(mac_b, mac_a, 3, vec![11, 12, 13]));
+    host_c.add_schedule(100, EthernetFrame::new(mac_a, mac_c, 3, vec![14, 15, 16]));
+    host_a.add_schedule(200, EthernetFrame::new(mac_c, mac_a, 3, vec![17, 18, 19]));
+
+    let mut nw = Network::new(
+        vec![host_a, host_b, host_c, switch],
+        vec![]
+    );
+    nw.connect_both(mac_s, Port::new(0), mac_a, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(1), mac_b, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(2), mac_c, Port::new(0))?;
+    nw.run(300)?;
+
+    // host_c could not be learned, so the frame for it is flooded to host_b too
+    let d = nw.get_device(mac_b)?;
+    let d = d.as_any().downcast_ref::<EthernetHost>().unwrap();
+    assert_eq!(1, d.get_rlog().iter().filter(|log| log.frame.dst == mac_c).count());
+
+    let t = nw.get_t();
+    let d = nw.get_device(mac_s)?;
+    let table = d.as_any().downcast_ref::<EthernetSwitch>().unwrap().get_mac_table();
+    println!("{}", table.dump(t));
+    assert_eq!(None, table.get(mac_c, t));
+    assert_eq!(Some(Port::new(0)), table.get(mac_a, t));
+
+    nw.run(1000)?;
+    let t = nw.get_t();
+    let d = nw.get_device(mac_s)?;
+    let table = d.as_any().downcast_ref::<EthernetSwitch>().unwrap().get_mac_table();
+    println!("{}", table.dump(t));
+    assert_eq!(None, table.get(mac_a, t));
+    assert_eq!(Some(Port::new(1)), table.get(mac_b, t));
+    Ok(())
+}
+
+// traffic of host_a is copied to an analyzer
+pub fn run_sample_port_mirror(direction: MirrorDirection) -> Res<Vec<EthernetLog>> {
+    println!("run experimental linkl port mirror sample");
+    crate::output::set_level(crate::output::Level::Frame);
+    let mac_a = Mac::new(21);
+    let mac_b = Mac::new(22);
+    let mac_c = Mac::new(23);
+    let mac_m = Mac::new(29);
+    let mac_s = Mac::new(30);
+
+    let mut host_a = EthernetHost::build_consumer(mac_a, "host_a");
+    let host_b = EthernetHost::build_echo(mac_b, "host_b");
+    let mut host_c = EthernetHost::build_consumer(mac_c, "host_c");
+    let analyzer = EthernetHost::build_consumer(mac_m, "analyzer");
+    let mut switch = EthernetSwitch::build_switch(mac_s, "switch", 4);
+    switch.add_mirror_session(MirrorSession {
+        sources: vec![(Port::new(0), direction)],
+        destination: Port::new(3),
+    });
+
+    host_a.add_schedule(0, EthernetFrame::new(mac_b, mac_a, 3, vec![11, 12, 13]));
+    host_c.add_schedule(150, EthernetFrame::new(mac_b, mac_c, 3, vec![14, 15, 16]));
+
+    let mut nw = Network::new(
+        vec![host_a, host_b, host_c, analyzer, switch],
+        vec![]
+    );
+    nw.connect_both(mac_s, Port::new(0), mac_a, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(1), mac_b, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(2), mac_c, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(3), mac_m, Port::new(0))?;
+    nw.run(300)?;
+
+    let d = nw.get_device(mac_m)?;
+    let d = d.as_any().downcast_ref::<EthernetHost>().unwrap();
+    Ok(d.get_rlog().clone())
+}
+
+// three switches connected in a loop. returns the reconvergence time after a link failure
+pub fn run_sample_stp() -> Res<usize> {
+    println!("run experimental linkl stp sample");
+    crate::output::set_level(crate::output::Level::Frame);
+    let mac_a = Mac::new(21);
+    let mac_b = Mac::new(22);
+    let mac_1 = Mac::new(31);
+    let mac_2 = Mac::new(32);
+    let mac_3 = Mac::new(33);
+
+    let config = StpConfig::default();
+    let root_config = StpConfig { priority: 0x1000, ..config };
+    let mut host_a = EthernetHost::build_consumer(mac_a, "host_a");
+    let host_b = EthernetHost::build_echo(mac_b, "host_b");
+    let switch_1 = EthernetSwitch::build_stp_switch(mac_1, "switch1", 2, root_config);
+    let switch_2 = EthernetSwitch::build_stp_switch(mac_2, "switch2", 3, config);
+    let switch_3 = EthernetSwitch::build_stp_switch(mac_3, "switch3", 3, config);
+
+    host_a.add_schedule(2000, EthernetFrame::new(mac_b, mac_a, 3, vec![11, 12, 13]));
+    host_a.add_schedule(7500, EthernetFrame::new(mac_b, mac_a, 3, vec![14, 15, 16]));
+
+    let mut nw = Network::new(
+        vec![host_a, host_b, switch_1, switch_2, switch_3],
+        vec![]
+    );
+    nw.connect_both(mac_1, Port::new(0), mac_2, Port::new(0))?;
+    nw.connect_both(mac_1, Port::new(1), mac_3, Port::new(0))?;
+    nw.connect_both(mac_2, Port::new(1), mac_3, Port::new(1))?;
+    nw.connect_both(mac_2, Port::new(2), mac_a, Port::new(0))?;
+    nw.connect_both(mac_3, Port::new(2), mac_b, Port::new(0))?;
+    nw.run(3000)?;
+
+    let d = nw.get_device(mac_3)?;
+    let stp = d.as_any().downcast_ref::<EthernetSwitch>().unwrap().get_stp().unwrap();
+    assert_eq!(BridgeId::new(0x1000, mac_1), stp.get_root_id());
+    assert_eq!(Some(Port::new(0)), stp.get_root_port());
+    assert_eq!(PortRole::Alternate, stp.get_port_role(Port::new(1)));
+    assert_eq!(PortState::Blocking, stp.get_port_state(Port::new(1)));
+    assert_eq!(0, stp.get_invalid_bpdus());
+
+    // the loop is broken, so the frame arrives only once
+    let d = nw.get_device(mac_b)?;
+    let d = d.as_any().downcast_ref::<EthernetHost>().unwrap();
+    assert_eq!(1, d.get_rlog().iter().filter(|log| log.frame.dst == mac_b).count());
+
+    let t_fail = nw.get_t();
+    nw.set_link_up(mac_1, Port::new(1), false)?;
+    nw.run(8500)?;
+
+    let d = nw.get_device(mac_3)?;
+    let stp = d.as_any().downcast_ref::<EthernetSwitch>().unwrap().get_stp().unwrap();
+    assert_eq!(Some(Port::new(1)), stp.get_root_port());
+    let t_conv = stp.get_log().iter()
+        .find(|log| log.t > t_fail && log.port == Port::new(1) && log.state == PortState::Forwarding)
+        .map(|log| log.t)
+        .unwrap();
+    println!("reconverged in {} ticks", t_conv - t_fail);
+
+    let d = nw.get_device(mac_b)?;
+    let d = d.as_any().downcast_ref::<EthernetHost>().unwrap();
+    assert_eq!(2, d.get_rlog().iter().filter(|log| log.frame.dst == mac_b).count());
+    Ok(t_conv - t_fail)
+}
+
+// two switches bundle two links. flows are spread over the members and survive a member failure
+pub fn run_sample_lacp() -> Res<()> {
+    println!("run experimental linkl lacp sample");
+    crate::output::set_level(crate::output::Level::Frame);
+    let mac_a = Mac::new(21);
+    let mac_b = Mac::new(22);
+    let mac_c = Mac::new(23);
+    let mac_1 = Mac::new(31);
+    let mac_2 = Mac::new(32);
+
+    let mut host_a = EthernetHost::build_consumer(mac_a, "host_a");
+    let host_b = EthernetHost::build_echo(mac_b, "host_b");
+    let host_c = EthernetHost::build_echo(mac_c, "host_c");
+    let mut switch_1 = EthernetSwitch::build_switch(mac_1, "switch1", 3);
+    let mut switch_2 = EthernetSwitch::build_switch(mac_2, "switch2", 4);
+    switch_1.add_link_aggregation(vec![Port::new(1), Port::new(2)], LagConfig::default());
+    let passive = LagConfig { mode: LacpMode::Passive, ..LagConfig::default() };
+    switch_2.add_link_aggregation(vec![Port::new(2), Port::new(3)], passive);
+
+    host_a.add_schedule(1000, EthernetFrame::new(mac_b, mac_a, 3, vec![11, 12, 13]));
+    host_a.add_schedule(1100, EthernetFrame::new(mac_c, mac_a, 3, vec![14, 15, 16]));
+    host_a.add_schedule(3000, EthernetFrame::new(mac_b, mac_a, 3, vec![17, 18, 19]));
+    host_a.add_schedule(3100, EthernetFrame::new(mac_c, mac_a, 3, vec![20, 21, 22]));
+
+    let mut nw = Network::new(
+        vec![host_a, host_b, host_c, switch_1, switch_2],
+        vec![]
+    );
+    nw.connect_both(mac_1, Port::new(0), mac_a, Port::new(0))?;
+    nw.connect_both(mac_2, Port::new(0), mac_b, Port::new(0))?;
+    nw.connect_both(mac_2, Port::new(1), mac_c, Port::new(0))?;
+    nw.connect_both(mac_1, Port::new(1), mac_2, Port::new(2))?;
+    nw.connect_both(mac_1, Port::new(2), mac_2, Port::new(3))?;
+    nw.run(1500)?;
+
+    // the two flows took different members
+    let d = nw.get_device(mac_1)?;
+    let lag = d.as_any().downcast_ref::<EthernetSwitch>().unwrap().get_link_aggregation(Port::new(1)).unwrap();
+    for port in lag.get_ports() {
+        println!("port {}: {} frames", port.value, lag.get_tx_count(*port));
+        assert_eq!(1, lag.get_tx_count(*port));
+    }
+
+    nw.set_link_up(mac_1, Port::new(1), false)?;
+    nw.run(3500)?;
+
+    let d = nw.get_device(mac_1)?;
+    let lag = d.as_any().downcast_ref::<EthernetSwitch>().unwrap().get_link_aggregation(Port::new(1)).unwrap();
+    assert_eq!(vec![Port::new(2)], lag.get_active_ports());
+    let d = nw.get_device(mac_2)?;
+    let lag = d.as_any().downcast_ref::<EthernetSwitch>().unwrap().get_link_aggregation(Port::new(2)).unwrap();
+    assert_eq!(vec![Port::new(3)], lag.get_active_ports());
+
+    // both flows failed over to the remaining member
+    for mac in [mac_b, mac_c] {
+        let d = nw.get_device(mac)?;
+        let d = d.as_any().downcast_ref::<EthernetHost>().unwrap();
+        assert_eq!(2, d.get_rlog().iter().filter(|log| log.frame.dst == mac).count());
+    }
+    let d = nw.get_device(mac_a)?;
+    let d = d.as_any().downcast_ref::<EthernetHost>().unwrap();
+    assert_eq!(4, d.get_rlog().iter().filter(|log| log.frame.dst == mac_a).count());
+    Ok(())
+}
+
+// host_a and host_b send bursts to host_c through a switch with a small buffer.
+// returns the frames dropped by the switch, the frames host_c received and when host_d got its frame
+pub fn run_sample_pause(flow_control: bool) -> Res<(usize, usize, usize)> {
+    println!("run experimental linkl pause sample");
+    crate::output::set_level(crate::output::Level::Frame);
+    let mac_a = Mac::new(21);
+    let mac_b = Mac::new(22);
+    let mac_c = Mac::new(23);
+    let mac_d = Mac::new(24);
+    let mac_s = Mac::new(30);
+
+    let mut host_a = EthernetHost::build_consumer(mac_a, "host_a");
+    let mut host_b = EthernetHost::build_consumer(mac_b, "host_b");
+    let host_c = EthernetHost::build_consumer(mac_c, "host_c");
+    let host_d = EthernetHost::build_consumer(mac_d, "host_d");
+    let mut switch = EthernetSwitch::build_switch(mac_s, "switch", 4);
+    switch.set_buffer_size(Some(800));
+    if flow_control {
+        switch.set_flow_control(Some(FlowControl::default()));
+    }
+    switch.mac_table_mut().add_static(mac_c, Port::new(2));
+    switch.mac_table_mut().add_static(mac_d, Port::new(3));
+
+    for i in 0..10 {
+        host_a.add_schedule(i, EthernetFrame::new(mac_c, mac_a, 100, vec![i as u8; 100]));
+        host_b.add_schedule(i, EthernetFrame::new(mac_c, mac_b, 100, vec![i as u8; 100]));
+    }
+    // queued behind the frames for host_c
+    host_a.add_schedule(10, EthernetFrame::new(mac_d, mac_a, 3, vec![11, 12, 13]));
+
+    let mut nw = Network::new(
+        vec![host_a, host_b, host_c, host_d, switch],
+        vec![]
+    );
+    nw.connect_both(mac_s, Port::new(0), mac_a, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(1), mac_b, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(2), mac_c, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(3), mac_d, Port::new(0))?;
+    nw.run(5000)?;
+
+    let d = nw.get_device(mac_s)?;
+    let drops = d.as_any().downcast_ref::<EthernetSwitch>().unwrap().get_drop_count();
+    let d = nw.get_device(mac_c)?;
+    let num_c = d.as_any().downcast_ref::<EthernetHost>().unwrap().get_rlog().len();
+    let d = nw.get_device(mac_d)?;
+    let t_d = d.as_any().downcast_ref::<EthernetHost>().unwrap().get_rlog()[0].t;
+    println!("drops: {}, received: {}, head-of-line blocked frame arrived at {}", drops, num_c, t_d);
+    Ok((drops, num_c, t_d))
+}
+
+// host_b sends voice frames with priority 5 while host_a and host_d flood host_c with bulk frames.
+// returns the worst latency of the voice frames and how many arrived
+pub fn run_sample_qos(qos: Option<QosConfig>) -> Res<(usize, usize)> {
+    println!("run experimental linkl qos sample");
+    crate::output::set_level(crate::output::Level::Frame);
+    let mac_a = Mac::new(21);
+    let mac_b = Mac::new(22);
+    let mac_c = Mac::new(23);
+    let mac_d = Mac::new(24);
+    let mac_s = Mac::new(30);
+
+    let mut host_a = EthernetHost::build_consumer(mac_a, "host_a");
+    let mut host_b = EthernetHost::build_consumer(mac_b, "host_b");
+    let host_c = EthernetHost::build_consumer(mac_c, "host_c");
+    let mut host_d = EthernetHost::build_consumer(mac_d, "host_d");
+    let mut switch = EthernetSwitch::build_switch(mac_s, "switch", 4);
+    switch.set_qos(qos)?;
+    switch.mac_table_mut().add_static(mac_c, Port::new(2));
+
+    for i in 0..10 {
+        host_a.add_schedule(i, EthernetFrame::new(mac_c, mac_a, 100, vec![0; 100]));
+        host_d.add_schedule(i, EthernetFrame::new(mac_c, mac_d, 100, vec![0; 100]));
+    }
+    let voice = VlanTag { pcp: 5, dei: false, vid: 1 };
+    for i in 0..10 {
+        // the payload carries the sending time
+        let t = 100 + 200 * i;
+        let mut payload = crate::utils::split_2bytes(t as u16).to_vec();
+        payload.resize(10, 0);
+        host_b.add_schedule(t, EthernetFrame::new_tagged(mac_c, mac_b, voice, 10, payload));
+    }
+
+    let mut nw = Network::new(
+        vec![host_a, host_b, host_c, host_d, switch],
+        vec![]
+    );
+    nw.connect_both(mac_s, Port::new(0), mac_a, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(1), mac_b, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(2), mac_c, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(3), mac_d, Port::new(0))?;
+    nw.run(3000)?;
+
+    let d = nw.get_device(mac_s)?;
+    if let Some(queues) = d.as_any().downcast_ref::<EthernetSwitch>().unwrap().get_egress_queues() {
+        println!("frames dropped by the lowest queue: {}", queues.get_drop_count(0));
+    }
+    let d = nw.get_device(mac_c)?;
+    let d = d.as_any().downcast_ref::<EthernetHost>().unwrap();
+    let latencies: Vec<usize> = d.get_rlog()
+        .iter()
+        .filter(|log| log.frame.src == mac_b)
+        .map(|log| log.t - crate::utils::read_2bytes(&log.frame.payload, 0) as usize)
+        .collect();
+    let max_latency = latencies.iter().copied().max().unwrap_or(0);
+    println!("voice frames: {}, max latency: {}", latencies.len(), max_latency);
+    Ok((max_latency, latencies.len()))
+}
+
+// host_a sends three frames to host_b, then a noisy station sends a frame whose type field was hit by a bit error.
+// returns the forwarding latencies of the switch and the broken frames which reached host_b
+pub fn run_sample_cut_through(mode: SwitchingMode) -> Res<(Vec<ForwardLog>, usize)> {
+    println!("run experimental linkl cut-through sample");
+    crate::output::set_level(crate::output::Level::Frame);
+    let mac_a = Mac::new(21);
+    let mac_b = Mac::new(22);
+    let mac_n = Mac::new(29);
+    let mac_s = Mac::new(30);
+
+    let mut host_a = EthernetHost::build_consumer(mac_a, "host_a");
+    let host_b = EthernetHost::build_consumer(mac_b, "host_b");
+    let mut switch = EthernetSwitch::build_switch(mac_s, "switch", 3);
+    switch.set_switching_mode(mode);
+    switch.mac_table_mut().add_static(mac_b, Port::new(1));
+
+    for i in 0..3 {
+        host_a.add_schedule(200 * i, EthernetFrame::new(mac_b, mac_a, 100, vec![i as u8; 100]));
+    }
+    let mut xs = EthernetFrame::encode(&EthernetFrame::new(mac_b, mac_n, 3, vec![1, 2, 3]));
+    xs[8 + 6 + 6] ^= 0x80;
+    let schedules = xs.iter()
+        .enumerate()
+        .map(|(i, x)| ByteLog::new(600 + i, Port::new(0), *x))
+        .collect();
+    let noisy = Box::new(ByteHost::new(mac_n, "noisy", schedules));
+
+    let mut nw = Network::new(
+        vec![host_a, host_b, noisy, switch],
+        vec![]
+    );
+    nw.connect_both(mac_s, Port::new(0), mac_a, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(1), mac_b, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(2), mac_n, Port::new(0))?;
+    nw.run(800)?;
+
+    let d = nw.get_device(mac_s)?;
+    let switch = d.as_any().downcast_ref::<EthernetSwitch>().unwrap();
+    let flog = switch.get_forward_log().clone();
+    println!("decode errors at the switch: {}", switch.get_rx_error_count());
+    let d = nw.get_device(mac_b)?;
+    let d = d.as_any().downcast_ref::<EthernetHost>().unwrap();
+    assert_eq!(3, d.get_rlog().len());
+    let errors = d.base.get_rx_error_count();
+    let max = flog.iter().map(|log| log.latency).max().unwrap_or(0);
+    let avg = flog.iter().map(|log| log.latency).sum::<usize>() as f64 / flog.len() as f64;
+    println!("{:?}: {} frames, forwarding latency avg {:.1}, max {}. broken frames at host_b: {}",
+             mode, flog.len(), avg, max, errors);
+    Ok((flog, errors))
+}
+
+// stop-and-wait transfer between two hosts running apps. the link is down at first,
+// so the first chunk is lost and retransmitted by the timer
+pub fn run_sample_app() -> Res<(Vec<Vec<u8>>, usize)> {
+    println!("run experimental linkl app sample");
+    crate::output::set_level(crate::output::Level::Frame);
+    let mac_a = Mac::new(21);
+    let mac_b = Mac::new(22);
+    let chunks: Vec<Vec<u8>> = (0..3).map(|i| vec![i as u8; 20]).collect();
+
+    let sender = EthernetHost::build_app(mac_a, "sender", Box::new(StopAndWaitSender::new(mac_b, chunks.clone(), 100)));
+    let receiver = EthernetHost::build_app(mac_b, "receiver", Box::new(StopAndWaitReceiver::new()));
+    let mut nw = Network::new(vec![sender, receiver], vec![]);
+    nw.connect_both(mac_a, Port::new(0), mac_b, Port::new(0))?;
+    nw.set_link_up(mac_a, Port::new(0), false)?;
+    nw.run(60)?;
+    nw.set_link_up(mac_a, Port::new(0), true)?;
+    nw.run(1000)?;
+
+    let d = nw.get_device(mac_a)?;
+    let host = d.as_any().downcast_ref::<EthernetHost>().unwrap();
+    let sender = host.get_app().as_any().downcast_ref::<StopAndWaitSender>().unwrap();
+    assert!(sender.is_done());
+    let retransmissions = sender.get_retransmissions();
+    println!("retransmissions: {}, done at t={:?}", retransmissions, sender.get_t_done());
+    let d = nw.get_device(mac_b)?;
+    let host = d.as_any().downcast_ref::<EthernetHost>().unwrap();
+    let receiver = host.get_app().as_any().downcast_ref::<StopAndWaitReceiver>().unwrap();
+    let delivered = receiver.get_delivered().clone();
+    assert_eq!(chunks, delivered);
+    Ok((delivered, retransmissions))
+}
+
+// host_a and host_b send frames to each other on a cable whose ends are configured separately.
+// returns the statistics of host_b's port, the frames delivered and the broken frames received
+pub fn run_sample_duplex(config_a: PortConfig, config_b: PortConfig) -> Res<(PhyStats, usize, usize)> {
+    println!("run experimental linkl duplex sample. a: {:?}, b: {:?}", config_a, config_b);
+    crate::output::set_level(crate::output::Level::Frame);
+    let mac_a = Mac::new(21);
+    let mac_b = Mac::new(22);
+
+    let mut host_a = EthernetHost::build_consumer(mac_a, "host_a");
+    let mut host_b = EthernetHost::build_consumer(mac_b, "host_b");
+    for i in 0..4 {
+        host_a.add_schedule(300 * i, EthernetFrame::new(mac_b, mac_a, 200, vec![i as u8; 200]));
+        host_b.add_schedule(300 * i + 100, EthernetFrame::new(mac_a, mac_b, 200, vec![i as u8; 200]));
+    }
+    // when the wire is idle, both start at once
+    host_a.add_schedule(2500, EthernetFrame::new(mac_b, mac_a, 100, vec![0xAA; 100]));
+    host_b.add_schedule(2500, EthernetFrame::new(mac_a, mac_b, 100, vec![0xBB; 100]));
+
+    let mut nw = Network::new(vec![host_a, host_b], vec![]);
+    nw.connect_both(mac_a, Port::new(0), mac_b, Port::new(0))?;
+    nw.set_port_config(mac_a, Port::new(0), config_a)?;
+    nw.set_port_config(mac_b, Port::new(0), config_b)?;
+    nw.run(3000)?;
+
+    for mac in [mac_a, mac_b] {
+        if let Some(phy) = nw.get_phy(mac, Port::new(0))? {
+            println!("{}: {:?} {:?}", mac.value, phy.get_link(), phy.get_stats());
+        }
+    }
+    let stats = nw.get_phy(mac_b, Port::new(0))?.map(|phy| phy.get_stats()).unwrap_or_default();
+    let (mut delivered, mut errors) = (0, 0);
+    for mac in [mac_a, mac_b] {
+        let d = nw.get_device(mac)?;
+        let d = d.as_any().downcast_ref::<EthernetHost>().unwrap();
+        delivered += d.get_rlog().len();
+        errors += d.base.get_rx_error_count();
+    }
+    println!("delivered: {}, broken: {}", delivered, errors);
+    Ok((stats, delivered, errors))
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::netwl::{ip::IP, ip_addr::IpAddr};
+    use super::super::physl::Duplex;
 
     use super::*;
 
@@ -115,4 +588,164 @@ mod tests {
         let frame = EthernetFrame::new(mac0, mac1, 3, vec![11, 12, 13]);
         assert_eq!(frame, log.frame);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_mac_table() {
+        run_sample_mac_table().unwrap();
+    }
+
+    #[test]
+    fn test_port_mirror() {
+        let mac_a = Mac::new(21);
+        let mac_b = Mac::new(22);
+        let frame_ab = EthernetFrame::new(mac_b, mac_a, 3, vec![11, 12, 13]);
+        let frame_ba = EthernetFrame::new(mac_a, mac_b, 3, vec![11, 12, 13]);
+
+        let log = run_sample_port_mirror(MirrorDirection::Ingress).unwrap();
+        assert_eq!(1, log.len());
+        assert_eq!(frame_ab, log[0].frame);
+
+        let log = run_sample_port_mirror(MirrorDirection::Egress).unwrap();
+        assert_eq!(1, log.len());
+        assert_eq!(frame_ba, log[0].frame);
+
+        // ingress and egress of host_a, but not the traffic of host_c
+        let log = run_sample_port_mirror(MirrorDirection::Both).unwrap();
+        assert_eq!(2, log.len());
+        assert_eq!(frame_ab, log[0].frame);
+        assert_eq!(frame_ba, log[1].frame);
+    }
+
+    #[test]
+    fn test_stp_reconvergence() {
+        let t = run_sample_stp().unwrap();
+        let config = StpConfig::default();
+        assert!(t >= 2 * config.forward_delay as usize);
+        assert!(t <= (config.max_age + 2 * config.forward_delay + config.hello_time) as usize);
+    }
+
+    #[test]
+    fn test_pause() {
+        let (drops, num_c, t_d) = run_sample_pause(false).unwrap();
+        assert!(drops > 0);
+        assert_eq!(20 - drops, num_c);
+
+        // no loss, but the frame for host_d waits behind the paused frames for host_c
+        let (drops, num_c, t_d_paused) = run_sample_pause(true).unwrap();
+        assert_eq!(0, drops);
+        assert_eq!(20, num_c);
+        assert!(t_d_paused > t_d);
+    }
+
+    #[test]
+    fn test_vlan_tag() {
+        let tag = VlanTag { pcp: 5, dei: true, vid: 100 };
+        let frame = EthernetFrame::new_tagged(Mac::new(1), Mac::new(2), tag, 3, vec![1, 2, 3]);
+        let xs = EthernetFrame::encode(&frame);
+        assert_eq!(8 + 6 + 6 + 4 + 2 + 3, xs.len());
+        assert_eq!(frame, EthernetFrame::decode(&xs).unwrap());
+        assert_eq!(5, frame.get_priority());
+    }
+
+    #[test]
+    fn test_qos() {
+        // a single queue. the voice frames wait behind the bulk frames
+        let (latency_fifo, num) = run_sample_qos(None).unwrap();
+        assert_eq!(10, num);
+
+        let (latency_strict, num) = run_sample_qos(Some(QosConfig::default())).unwrap();
+        assert_eq!(10, num);
+
+        let wrr = QosConfig {
+            num_queues: 2,
+            scheduling: Scheduling::WeightedRoundRobin(vec![1, 1]),
+            queue_size: None,
+        };
+        let (latency_wrr, num) = run_sample_qos(Some(wrr)).unwrap();
+        assert_eq!(10, num);
+
+        // at most one bulk frame is ahead of a voice frame
+        assert!(latency_strict < 250);
+        assert!(latency_wrr < 250);
+        assert!(latency_fifo > 2 * latency_strict);
+    }
+
+    #[test]
+    fn test_cut_through() {
+        // the whole frame of 8 + 14 + 100 bytes is read before forwarding
+        let (flog, errors) = run_sample_cut_through(SwitchingMode::StoreAndForward).unwrap();
+        assert_eq!(3, flog.len());
+        assert!(flog.iter().all(|log| log.latency == 8 + 14 + 100 - 1));
+        assert_eq!(0, errors);
+
+        // only the preamble and the destination
+        let (flog, errors) = run_sample_cut_through(SwitchingMode::CutThrough).unwrap();
+        assert_eq!(4, flog.len());
+        assert!(flog.iter().all(|log| log.latency == 8 + 6 - 1));
+        assert_eq!(1, errors);
+    }
+
+    #[test]
+    fn test_lacp() {
+        run_sample_lacp().unwrap();
+    }
+
+    #[test]
+    fn test_lacp_first_member_down() {
+        let mac_a = Mac::new(21);
+        let mac_b = Mac::new(22);
+        let mac_1 = Mac::new(31);
+        let mac_2 = Mac::new(32);
+        let mut host_a = EthernetHost::build_consumer(mac_a, "host_a");
+        let host_b = EthernetHost::build_consumer(mac_b, "host_b");
+        let mut switch_1 = EthernetSwitch::build_switch(mac_1, "switch1", 3);
+        let mut switch_2 = EthernetSwitch::build_switch(mac_2, "switch2", 3);
+        switch_1.add_link_aggregation(vec![Port::new(1), Port::new(2)], LagConfig::default());
+        switch_2.add_link_aggregation(vec![Port::new(1), Port::new(2)], LagConfig::default());
+        host_a.add_schedule(1000, EthernetFrame::new(mac_b, mac_a, 3, vec![11, 12, 13]));
+
+        let mut nw = Network::new(vec![host_a, host_b, switch_1, switch_2], vec![]);
+        nw.connect_both(mac_1, Port::new(0), mac_a, Port::new(0)).unwrap();
+        nw.connect_both(mac_2, Port::new(0), mac_b, Port::new(0)).unwrap();
+        nw.connect_both(mac_1, Port::new(1), mac_2, Port::new(1)).unwrap();
+        nw.connect_both(mac_1, Port::new(2), mac_2, Port::new(2)).unwrap();
+        // port 1 of switch2, the logical port of its bundle, never receives a byte
+        nw.set_link_up(mac_1, Port::new(1), false).unwrap();
+        nw.run(1500).unwrap();
+
+        let d = nw.get_device(mac_b).unwrap();
+        let d = d.as_any().downcast_ref::<EthernetHost>().unwrap();
+        assert_eq!(1, d.get_rlog().iter().filter(|log| log.frame.dst == mac_b).count());
+        // timed from the member the frame arrived on
+        let d = nw.get_device(mac_2).unwrap();
+        let flog = d.as_any().downcast_ref::<EthernetSwitch>().unwrap().get_forward_log();
+        assert!(flog.iter().any(|log| log.t > 1000 && log.latency < 100));
+    }
+
+    #[test]
+    fn test_app() {
+        let (delivered, retransmissions) = run_sample_app().unwrap();
+        assert_eq!(3, delivered.len());
+        assert_eq!(1, retransmissions);
+    }
+
+    #[test]
+    fn test_duplex() {
+        let auto = PortConfig::default();
+        let (stats, delivered, errors) = run_sample_duplex(auto, auto).unwrap();
+        assert_eq!((PhyStats::default(), 10, 0), (stats, delivered, errors));
+
+        // carrier sense defers host_b until host_a has finished. the last frames collide and are sent again
+        let half = PortConfig { autoneg: false, duplex: Duplex::Half, ..auto };
+        let (stats, delivered, errors) = run_sample_duplex(half, half).unwrap();
+        assert!(stats.collisions > 0);
+        assert_eq!((0, 10, 0), (stats.late_collisions, delivered, errors));
+
+        // host_b falls back to half duplex and host_a talks over it
+        let full = PortConfig { autoneg: false, ..auto };
+        let (stats, delivered, errors) = run_sample_duplex(full, auto).unwrap();
+        assert!(stats.late_collisions > 0);
+        assert!(delivered < 10);
+        assert!(errors > 0);
+    }
+}
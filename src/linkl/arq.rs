@@ -0,0 +1,137 @@
+use std::any::Any;
+
+use crate::types::{Mac, Res};
+
+use super::{AppContext, EthernetApp, EthernetFrame};
+
+// stop-and-wait ARQ. payload = kind(1 byte) + sequence number(1 byte) + data
+const KIND_DATA: u8 = 0;
+const KIND_ACK: u8 = 1;
+
+fn arq_frame(dst: Mac, kind: u8, seq: u8, data: &[u8], ctx: &AppContext) -> EthernetFrame {
+    let mut payload = vec![kind, seq];
+    payload.extend_from_slice(data);
+    // 802.3 frame. the type field holds the length
+    EthernetFrame::new(dst, ctx.get_mac(), payload.len() as u16, payload)
+}
+
+pub struct StopAndWaitSender {
+    dst: Mac,
+    chunks: Vec<Vec<u8>>,
+    timeout: usize,
+    seq: usize, // the chunk on the wire
+    waiting: bool,
+    retransmissions: usize,
+    t_done: Option<usize>,
+}
+
+impl StopAndWaitSender {
+    pub fn new(dst: Mac, chunks: Vec<Vec<u8>>, timeout: usize) -> StopAndWaitSender {
+        StopAndWaitSender {
+            dst,
+            chunks,
+            timeout,
+            seq: 0,
+            waiting: false,
+            retransmissions: 0,
+            t_done: None,
+        }
+    }
+
+    fn transmit(&mut self, ctx: &mut AppContext) {
+        let frame = arq_frame(self.dst, KIND_DATA, self.seq as u8, &self.chunks[self.seq], ctx);
+        ctx.send(frame);
+        ctx.set_timer(self.timeout, self.seq as u32);
+        self.waiting = true;
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.seq == self.chunks.len()
+    }
+
+    pub fn get_retransmissions(&self) -> usize {
+        self.retransmissions
+    }
+
+    // the time the last chunk was acknowledged
+    pub fn get_t_done(&self) -> Option<usize> {
+        self.t_done
+    }
+}
+
+impl EthernetApp for StopAndWaitSender {
+    fn on_frame(&mut self, frame: &EthernetFrame, ctx: &mut AppContext) -> Res<()> {
+        let xs = &frame.payload;
+        if xs.len() >= 2 && xs[0] == KIND_ACK && self.waiting && xs[1] == self.seq as u8 {
+            ctx.cancel_timer(self.seq as u32);
+            self.seq += 1;
+            self.waiting = false;
+            if self.is_done() {
+                self.t_done = Some(ctx.get_t());
+            }
+        }
+        Ok(())
+    }
+
+    fn on_tick(&mut self, ctx: &mut AppContext) -> Res<()> {
+        if !self.waiting && !self.is_done() {
+            self.transmit(ctx);
+        }
+        Ok(())
+    }
+
+    fn on_timer(&mut self, id: u32, ctx: &mut AppContext) -> Res<()> {
+        if self.waiting && id == self.seq as u32 {
+            self.retransmissions += 1;
+            self.transmit(ctx);
+        }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+pub struct StopAndWaitReceiver {
+    expected: u8,
+    delivered: Vec<Vec<u8>>,
+}
+
+impl Default for StopAndWaitReceiver {
+    fn default() -> StopAndWaitReceiver {
+        StopAndWaitReceiver::new()
+    }
+}
+
+impl StopAndWaitReceiver {
+    pub fn new() -> StopAndWaitReceiver {
+        StopAndWaitReceiver { expected: 0, delivered: Vec::new() }
+    }
+
+    // chunks in order, without duplicates
+    pub fn get_delivered(&self) -> &Vec<Vec<u8>> {
+        &self.delivered
+    }
+}
+
+impl EthernetApp for StopAndWaitReceiver {
+    fn on_frame(&mut self, frame: &EthernetFrame, ctx: &mut AppContext) -> Res<()> {
+        let xs = &frame.payload;
+        if xs.len() < 2 || xs[0] != KIND_DATA {
+            return Ok(());
+        }
+        if xs[1] == self.expected {
+            self.delivered.push(xs[2..].to_vec());
+            self.expected = self.expected.wrapping_add(1);
+        }
+        // a duplicate means our ack was lost. ack it again
+        let ack = arq_frame(frame.src, KIND_ACK, xs[1], &[], ctx);
+        ctx.send(ack);
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,111 @@
+use std::any::Any;
+
+use crate::types::{Mac, Res};
+
+use super::EthernetFrame;
+
+// what an application may do while it is called
+pub struct AppContext {
+    t: usize,
+    mac: Mac,
+    outbox: Vec<EthernetFrame>,
+    timers: Vec<(usize, u32)>, // deadline, id
+}
+
+impl AppContext {
+    pub fn new(mac: Mac) -> AppContext {
+        AppContext {
+            t: 0,
+            mac,
+            outbox: Vec::new(),
+            timers: Vec::new(),
+        }
+    }
+
+    pub fn get_t(&self) -> usize {
+        self.t
+    }
+
+    pub fn get_mac(&self) -> Mac {
+        self.mac
+    }
+
+    // frames are sent at the end of the current update
+    pub fn send(&mut self, frame: EthernetFrame) {
+        self.outbox.push(frame);
+    }
+
+    pub fn send_to(&mut self, dst: Mac, ethertype: u16, payload: Vec<u8>) {
+        let frame = EthernetFrame::new(dst, self.mac, ethertype, payload);
+        self.send(frame);
+    }
+
+    // on_timer is called with the id after delay ticks. a timer with the same id is replaced
+    pub fn set_timer(&mut self, delay: usize, id: u32) {
+        self.cancel_timer(id);
+        self.timers.push((self.t + delay, id));
+    }
+
+    pub fn cancel_timer(&mut self, id: u32) {
+        self.timers.retain(|(_, i)| *i != id);
+    }
+
+    pub(super) fn set_t(&mut self, t: usize) {
+        self.t = t;
+    }
+
+    pub(super) fn pop_expired_timers(&mut self) -> Vec<u32> {
+        let t = self.t;
+        let mut expired: Vec<(usize, u32)> = self.timers.iter().copied().filter(|(deadline, _)| *deadline <= t).collect();
+        expired.sort();
+        self.timers.retain(|(deadline, _)| *deadline > t);
+        expired.into_iter().map(|(_, id)| id).collect()
+    }
+
+    pub(super) fn take_outbox(&mut self) -> Vec<EthernetFrame> {
+        std::mem::take(&mut self.outbox)
+    }
+}
+
+// a program running on an EthernetHost
+pub trait EthernetApp {
+    // a frame accepted by the NIC
+    fn on_frame(&mut self, frame: &EthernetFrame, ctx: &mut AppContext) -> Res<()>;
+
+    // called every tick
+    fn on_tick(&mut self, _ctx: &mut AppContext) -> Res<()> {
+        Ok(())
+    }
+
+    fn on_timer(&mut self, _id: u32, _ctx: &mut AppContext) -> Res<()> {
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+pub struct ConsumerApp;
+
+impl EthernetApp for ConsumerApp {
+    fn on_frame(&mut self, _frame: &EthernetFrame, _ctx: &mut AppContext) -> Res<()> {
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// reply the payload to the sender
+pub struct EchoApp;
+
+impl EthernetApp for EchoApp {
+    fn on_frame(&mut self, frame: &EthernetFrame, ctx: &mut AppContext) -> Res<()> {
+        ctx.send_to(frame.src, frame.ethertype, frame.payload.clone());
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -1,14 +1,53 @@
-use std::collections::{VecDeque, HashMap};
+use std::collections::{VecDeque, HashMap, HashSet};
 
-use crate::{types::{Mac, Port, Error, UpdateContext}, physl::BaseDevice};
+use crate::{types::{Mac, Port, Res, Error, UpdateContext}, physl::BaseDevice, utils::read_6bytes};
 
-use super::{EthernetFrame, EthernetLog};
+use super::{PppConfig, PppFrame, PppLink, LcpPacket, PPP_IPV4, PPP_LCP, Lldp, LldpConfig, Lldpdu, ETHERTYPE_LLDP, EgressQueues, EthernetFrame, EthernetLog, FlowControl, QosConfig, PauseFrame, ETHERTYPE_MAC_CONTROL, LagConfig, Lacpdu, LinkAggregation, MacTable, MAX_FRAME_LEN, PortState, SLOW_PROTOCOLS_MULTICAST, STP_MULTICAST};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwitchingMode {
+    StoreAndForward,
+    CutThrough, // relay as soon as the destination is read
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForwardLog {
+    pub t: usize,       // the first byte arrived
+    pub latency: usize, // until the frame was handed to the egress
+    pub mode: SwitchingMode,
+}
+
+// a frame being relayed byte by byte
+struct CutThrough {
+    ingress: Port,
+    ports: Vec<Port>,
+}
 
 pub struct BaseEthernetDevice {
-    pub recv_buf: VecDeque<EthernetFrame>,
+    pub recv_buf: VecDeque<(Port, EthernetFrame, usize, Option<Vec<Port>>)>, // when its first byte arrived and where it was relayed
     pub send_buf: VecDeque<EthernetFrame>,
-    forward_table: HashMap<Mac, Port>,
+    forward_table: MacTable,
     bufs: HashMap<Port, Vec<u8>>,
+    port_states: HashMap<Port, PortState>,
+    mirror_destinations: HashSet<Port>, // monitor ports. left out of switching and STP
+    groups: HashSet<Mac>, // subscribed multicast groups
+    lags: Vec<LinkAggregation>,
+    buffer_size: Option<usize>, // bytes queued for transmission
+    drops: usize,
+    flow_control: Option<FlowControl>,
+    pausing: HashMap<Port, usize>, // ports paused by us, and when
+    paused_until: HashMap<Port, usize>, // ports paused by the peer
+    egress: Option<EgressQueues>,
+    mode: SwitchingMode,
+    cut_through: Option<CutThrough>,
+    held: VecDeque<(Port, Vec<u8>)>, // frames waiting for the cut-through frame
+    rx_start: HashMap<Port, usize>,
+    t_rx: usize, // the first byte of the last received frame arrived
+    rx_relayed: Option<Vec<Port>>, // the ports the last received frame was relayed to by cut-through
+    rx_errors: usize,
+    flog: Vec<ForwardLog>,
+    lldp: Option<Lldp>,
+    ppp: HashMap<Port, PppLink>, // serial ports framed by PPP instead of Ethernet
     pub base: BaseDevice,
 
     pub rlog: Vec<EthernetLog>,
@@ -20,8 +59,28 @@ impl BaseEthernetDevice {
         BaseEthernetDevice {
             recv_buf: VecDeque::new(),
             send_buf: VecDeque::new(), // FIXME: sbuf isn't used
-            forward_table: HashMap::new(),
+            forward_table: MacTable::new(),
             bufs: HashMap::new(),
+            port_states: HashMap::new(),
+            mirror_destinations: HashSet::new(),
+            groups: HashSet::new(),
+            lags: Vec::new(),
+            buffer_size: None,
+            drops: 0,
+            flow_control: None,
+            pausing: HashMap::new(),
+            paused_until: HashMap::new(),
+            egress: None,
+            mode: SwitchingMode::StoreAndForward,
+            cut_through: None,
+            held: VecDeque::new(),
+            rx_start: HashMap::new(),
+            t_rx: 0,
+            rx_relayed: None,
+            rx_errors: 0,
+            flog: Vec::new(),
+            lldp: None,
+            ppp: HashMap::new(),
             base: BaseDevice::new(mac, name, num_ports),
             rlog: Vec::new(),
             slog: Vec::new(),
@@ -29,73 +88,555 @@ impl BaseEthernetDevice {
     }
 
     pub fn recv(&mut self, ctx: &UpdateContext) -> Option<EthernetFrame> {
+        self.recv_from(ctx).map(|(_, frame)| frame)
+    }
+
+    // receive a frame together with its ingress port
+    pub fn recv_from(&mut self, ctx: &UpdateContext) -> Option<(Port, EthernetFrame)> {
         let disp = crate::output::is_frame_level();
+        self.forward_table.age(ctx.t);
+        self.update_lags(ctx);
+        self.update_pause(ctx);
+        self.update_lldp(ctx);
+        self.update_ppp(ctx);
 
         while let Some((port, x)) = self.base.recv() {
-            if let Some(xs) = self.bufs.get_mut(&port) {
-                xs.push(x);
+            if self.ppp.contains_key(&port) {
+                self.recv_ppp(port, x, ctx);
+                continue;
+            }
+            let xs = self.bufs.entry(port).or_default();
+            if xs.is_empty() {
+                self.rx_start.insert(port, ctx.t);
+            }
+            xs.push(x);
+            let len = xs.len();
+
+            let relaying = self.cut_through.as_ref().is_some_and(|ct| ct.ingress == port);
+            if relaying {
+                for p in self.cut_through.as_ref().unwrap().ports.clone() {
+                    self.base.send((p, x));
+                }
+            } else if len == 8 + 6 && self.mode == SwitchingMode::CutThrough {
+                self.start_cut_through(port, ctx);
+            }
+
+            let xs = self.bufs.get_mut(&port).unwrap();
+            let frame = match EthernetFrame::decode(xs) {
+                Ok(frame) => {
+                    xs.clear();
+                    frame
+                },
+                // a broken length would hold the egress forever
+                Err(Error::NotEnoughBytes) if !(relaying && len > MAX_FRAME_LEN) => continue, // do nothing
+                Err(_) => {
+                    println!("{}({}): invalid frame. clear bytes", self.base.get_name(), self.base.get_mac().value);
+                    xs.clear(); // clear illegal bytes
+                    self.rx_errors += 1;
+                    if relaying {
+                        // too late. the broken frame is already on its way
+                        self.end_cut_through();
+                    }
+                    continue;
+                }
+            };
+            let relayed = if relaying || self.cut_through.as_ref().is_some_and(|ct| ct.ingress == port) {
+                Some(self.end_cut_through())
             } else {
-                self.bufs.insert(port, vec![x]);
-            }
-
-            if let Some(xs) = self.bufs.get_mut(&port) {
-                match EthernetFrame::decode(xs) {
-                    Ok(frame) => {
-                        if disp {
-                            print!("{:>3}: ", ctx.t);
-                            println!("{}({}): receive: {:}", self.base.get_name(), self.base.get_mac().value, frame);
-                        }
-                        self.rlog.push(EthernetLog { t: ctx.t, frame: frame.clone() });
-    
-                        self.forward_table.insert(frame.src, port);
-                        self.recv_buf.push_back(frame);
-                        xs.clear();
-                    },
-                    Err(Error::NotEnoughBytes) => {}, // do nothing
-                    Err(_) => {
-                        println!("{}({}): invalid frame. clear bytes", self.base.get_name(), self.base.get_mac().value);
-                        xs.clear(); // clear illegal bytes
+                None
+            };
+            if disp {
+                print!("{:>3}: ", ctx.t);
+                println!("{}({}): receive: {:}", self.base.get_name(), self.base.get_mac().value, frame);
+            }
+            self.rlog.push(EthernetLog { t: ctx.t, frame: frame.clone() });
+
+            // slow protocols end at the port and are never relayed
+            if frame.dst == SLOW_PROTOCOLS_MULTICAST {
+                self.recv_lacpdu(port, &frame, ctx);
+                continue;
+            }
+            if frame.ethertype == ETHERTYPE_MAC_CONTROL {
+                self.recv_pause(port, &frame, ctx);
+                continue;
+            }
+            if frame.ethertype == ETHERTYPE_LLDP {
+                self.recv_lldp(port, &frame, ctx);
+                continue;
+            }
+            if let Some(fc) = self.flow_control {
+                if self.queued_len() > fc.high_water && !self.pausing.contains_key(&port) {
+                    self.send_pause(port, fc.quanta, ctx);
+                }
+            }
+
+            // when the first byte arrived at the physical port
+            let t_rx = self.rx_start[&port];
+            let state = self.get_port_state(port);
+            // members of an aggregation are seen as the logical port of the bundle
+            let port = match self.get_link_aggregation(port) {
+                Some(lag) if !lag.is_active(port) => continue,
+                Some(lag) => lag.get_port(),
+                None => port,
+            };
+            if state.is_learning() && !frame.src.is_multicast() {
+                self.forward_table.learn(frame.src, port, ctx.t);
+            }
+            // BPDUs are accepted on every port, data frames only on forwarding ports.
+            // relayed frames are received too, so that they are mirrored
+            if relayed.is_some() || state.is_forwarding() || frame.dst == STP_MULTICAST {
+                self.recv_buf.push_back((port, frame, t_rx, relayed));
+            }
+        }
+
+        let (port, frame, t_rx, relayed) = self.recv_buf.pop_front()?;
+        self.t_rx = t_rx;
+        self.rx_relayed = relayed;
+        Some((port, frame))
+    }
+
+    // the ports the last received frame was already sent to by cut-through. it must not be sent again
+    pub fn take_relayed(&mut self) -> Option<Vec<Port>> {
+        self.rx_relayed.take()
+    }
+
+    // relay the frame from now on if the destination is a known unicast address and the transmitter is idle.
+    // otherwise the frame is stored and forwarded
+    fn start_cut_through(&mut self, port: Port, ctx: &UpdateContext) {
+        let xs = &self.bufs[&port];
+        if xs[0..8] != [0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAB] {
+            return;
+        }
+        let dst = Mac::new(read_6bytes(xs, 8));
+        let busy = self.cut_through.is_some() || self.egress.is_some() || self.base.get_send_len() > 0;
+        // the relayed frame must fit into the buffer
+        let fits = self.buffer_size.is_none_or(|size| size >= MAX_FRAME_LEN);
+        if busy || !fits || dst.is_multicast() || !self.get_port_state(port).is_forwarding() || self.get_link_aggregation(port).is_some() {
+            return;
+        }
+        let ports: Vec<Port> = self.egress_ports(dst, Some(port), ctx.t)
+            .into_iter()
+            .filter(|p| self.get_port_state(*p).is_forwarding())
+            .collect();
+        if ports.is_empty() || ports.iter().any(|p| self.get_link_aggregation(*p).is_some()) {
+            return;
+        }
+        for x in xs.clone() {
+            for p in &ports {
+                self.base.send((*p, x));
+            }
+        }
+        let t = self.rx_start[&port];
+        self.flog.push(ForwardLog { t, latency: ctx.t - t, mode: SwitchingMode::CutThrough });
+        self.cut_through = Some(CutThrough { ingress: port, ports });
+    }
+
+    // returns the ports the frame was relayed to
+    fn end_cut_through(&mut self) -> Vec<Port> {
+        let ports = self.cut_through.take().map(|ct| ct.ports).unwrap_or_default();
+        self.base.end_frame();
+        while let Some((port, bytes)) = self.held.pop_front() {
+            self.base.send_frame(port, &bytes);
+        }
+        ports
+    }
+
+    fn recv_lacpdu(&mut self, port: Port, frame: &EthernetFrame, ctx: &UpdateContext) {
+        match Lacpdu::decode(&frame.payload) {
+            Ok(pdu) => {
+                if let Some(lag) = self.lags.iter_mut().find(|lag| lag.is_member(port)) {
+                    lag.recv(port, &pdu, ctx.t);
+                }
+            }
+            Err(e) => println!("{}({}): invalid LACPDU: {}", self.base.get_name(), self.base.get_mac().value, e),
+        }
+    }
+
+    fn update_lags(&mut self, ctx: &UpdateContext) {
+        let mut pdus = vec![];
+        for lag in &mut self.lags {
+            pdus.append(&mut lag.update(ctx.t));
+        }
+        for (port, pdu) in pdus {
+            let frame = pdu.to_frame(self.base.get_mac());
+            self.send_to(port, frame, ctx);
+        }
+    }
+
+    fn recv_lldp(&mut self, port: Port, frame: &EthernetFrame, ctx: &UpdateContext) {
+        let Some(lldp) = &mut self.lldp else { return };
+        match Lldpdu::decode(&frame.payload) {
+            Ok(pdu) => lldp.recv(port, &pdu, ctx.t),
+            Err(e) => println!("{}({}): invalid LLDPDU: {}", self.base.get_name(), self.base.get_mac().value, e),
+        }
+    }
+
+    fn update_lldp(&mut self, ctx: &UpdateContext) {
+        let num_ports = self.base.get_num_ports();
+        let Some(lldp) = &mut self.lldp else { return };
+        for (port, pdu) in lldp.update(num_ports, ctx.t) {
+            let frame = pdu.to_frame(self.base.get_mac());
+            self.send_to(port, frame, ctx);
+        }
+    }
+
+    fn recv_ppp(&mut self, port: Port, x: u8, ctx: &UpdateContext) {
+        let link = self.ppp.get_mut(&port).unwrap();
+        self.rx_start.entry(port).or_insert(ctx.t);
+        let frame = match link.deframe(x) {
+            None => return,
+            Some(Ok(frame)) => frame,
+            Some(Err(e)) => {
+                println!("{}({}): invalid PPP frame: {}", self.base.get_name(), self.base.get_mac().value, e);
+                self.rx_start.remove(&port);
+                self.rx_errors += 1;
+                return;
+            }
+        };
+        let t_rx = self.rx_start.remove(&port).unwrap_or(ctx.t);
+        if crate::output::is_frame_level() {
+            print!("{:>3}: ", ctx.t);
+            println!("{}({}): receive: {:}", self.base.get_name(), self.base.get_mac().value, frame);
+        }
+        match frame.protocol {
+            PPP_LCP => match LcpPacket::decode(&frame.info) {
+                Ok(packet) => {
+                    for reply in link.recv(&packet, ctx.t) {
+                        self.send_ppp(port, reply.to_frame(), ctx);
                     }
                 }
+                Err(e) => println!("{}({}): invalid LCP packet: {}", self.base.get_name(), self.base.get_mac().value, e),
+            },
+            PPP_IPV4 if link.is_opened() => {
+                // the link has no addresses. hand the datagram up as if it was sent to us
+                let frame = EthernetFrame::new(self.base.get_mac(), Mac::default(), 0x0800, frame.info);
+                self.rlog.push(EthernetLog { t: ctx.t, frame: frame.clone() });
+                self.recv_buf.push_back((port, frame, t_rx, None));
+            }
+            _ => {} // network protocols before LCP is opened are discarded
+        }
+    }
+
+    fn update_ppp(&mut self, ctx: &UpdateContext) {
+        let mut ports: Vec<Port> = self.ppp.keys().copied().collect();
+        ports.sort_by_key(|port| port.value);
+        for port in ports {
+            for packet in self.ppp.get_mut(&port).unwrap().update(ctx.t) {
+                self.send_ppp(port, packet.to_frame(), ctx);
             }
         }
+    }
 
-        self.recv_buf.pop_front()
+    fn send_ppp(&mut self, port: Port, frame: PppFrame, ctx: &UpdateContext) {
+        if crate::output::is_frame_level() {
+            print!("{:>3}: ", ctx.t);
+            println!("{}({}): send:    {:}", self.base.get_name(), self.base.get_mac().value, frame);
+        }
+        self.write_frame(port, &frame.encode());
     }
 
-    pub fn send(&mut self, frame: EthernetFrame, ctx: &UpdateContext) {
+    // the bytes of the frame on the port. a serial port carries only IPv4 once LCP is opened
+    fn encode_for(&mut self, port: Port, frame: &EthernetFrame) -> Option<Vec<u8>> {
+        match self.ppp.get_mut(&port) {
+            Some(link) if frame.ethertype == 0x0800 => link.frame(PPP_IPV4, &frame.payload).map(|f| f.encode()),
+            Some(_) => None,
+            None => Some(EthernetFrame::encode(frame)),
+        }
+    }
+
+    fn recv_pause(&mut self, port: Port, frame: &EthernetFrame, ctx: &UpdateContext) {
+        match PauseFrame::decode(&frame.payload) {
+            Ok(pause) => {
+                self.paused_until.insert(port, ctx.t + pause.get_ticks());
+                self.base.set_paused(port, pause.quanta > 0);
+            }
+            Err(e) => println!("{}({}): invalid MAC control frame: {}", self.base.get_name(), self.base.get_mac().value, e),
+        }
+    }
+
+    fn send_pause(&mut self, port: Port, quanta: u16, ctx: &UpdateContext) {
+        let frame = PauseFrame { quanta }.to_frame(self.base.get_mac());
+        self.add_slog(&frame, ctx);
+        self.base.send_control_frame(port, &EthernetFrame::encode(&frame));
+        if quanta > 0 {
+            self.pausing.insert(port, ctx.t);
+        } else {
+            self.pausing.remove(&port);
+        }
+    }
+
+    fn update_pause(&mut self, ctx: &UpdateContext) {
+        let expired: Vec<Port> = self.paused_until
+            .iter()
+            .filter(|(_, t)| ctx.t >= **t)
+            .map(|(port, _)| *port)
+            .collect();
+        for port in expired {
+            self.paused_until.remove(&port);
+            self.base.set_paused(port, false);
+        }
+
+        let Some(fc) = self.flow_control else { return };
+        let mut ports: Vec<(Port, usize)> = self.pausing.iter().map(|(port, t)| (*port, *t)).collect();
+        ports.sort_by_key(|(port, _)| port.value);
+        let quanta_ticks = PauseFrame { quanta: fc.quanta }.get_ticks();
+        for (port, t) in ports {
+            if self.queued_len() < fc.low_water {
+                self.send_pause(port, 0, ctx);
+            } else if ctx.t >= t + quanta_ticks / 2 {
+                // refresh before the peer resumes on its own
+                self.send_pause(port, fc.quanta, ctx);
+            }
+        }
+    }
+
+    fn add_slog(&mut self, frame: &EthernetFrame, ctx: &UpdateContext) {
         let disp = crate::output::is_frame_level();
         self.slog.push(EthernetLog { t: ctx.t, frame: frame.clone() });
         if disp {
             print!("{:>3}: ", ctx.t);
             println!("{}({}): send:    {:}", self.base.get_name(), self.base.get_mac().value, frame);
         }
+    }
 
-        let mut ports = vec![];
-        if let Some(port) = self.forward_table.get(&frame.dst) {
-            ports.push(*port);
-        } else if let Some(src_port) = self.forward_table.get(&frame.src) {
-            for port in 0..self.base.get_num_ports() {
-                if port != src_port.value as usize {
-                    ports.push(Port::new(port as u32));
-                }
-            }
+    fn write_frame(&mut self, port: Port, bytes: &[u8]) {
+        if self.cut_through.is_some() {
+            self.held.push_back((port, bytes.to_vec()));
+            return;
+        }
+        self.base.send_frame(port, bytes);
+    }
+
+    // send a frame originated by this device
+    pub fn send(&mut self, frame: EthernetFrame, ctx: &UpdateContext) {
+        self.add_slog(&frame, ctx);
+        let ports = self.egress_ports(frame.dst, None, ctx.t);
+        self.write_frame_ports(&frame, ports);
+    }
+
+    // relay a frame received on the ingress port. it is never sent back to the ingress port.
+    // returns the ports the frame was sent to
+    pub fn forward(&mut self, frame: EthernetFrame, ingress: Port, ctx: &UpdateContext) -> Vec<Port> {
+        let ports = self.egress_ports(frame.dst, Some(ingress), ctx.t);
+        if ports.is_empty() {
+            // the destination lives on the ingress segment
+            return ports;
+        }
+        self.add_flog(ctx);
+        self.add_slog(&frame, ctx);
+        self.write_frame_ports(&frame, ports)
+    }
+
+    fn add_flog(&mut self, ctx: &UpdateContext) {
+        let t = self.t_rx;
+        self.flog.push(ForwardLog { t, latency: ctx.t - t, mode: SwitchingMode::StoreAndForward });
+    }
+
+    fn egress_ports(&self, dst: Mac, ingress: Option<Port>, t: usize) -> Vec<Port> {
+        let known = if dst.is_multicast() {
+            None
         } else {
-            for port in 0..self.base.get_num_ports() {
-                ports.push(Port::new(port as u32));
-            }
+            self.forward_table.get(dst, t)
         };
+        match known {
+            Some(port) if Some(port) == ingress => vec![],
+            Some(port) => vec![port],
+            None => {
+                // broadcast, multicast or unknown unicast. flood, once per aggregation
+                (0..self.base.get_num_ports())
+                    .map(|port| Port::new(port as u32))
+                    .filter(|port| Some(*port) != ingress)
+                    .filter(|port| self.get_link_aggregation(*port).is_none_or(|lag| lag.get_port() == *port))
+                    .collect()
+            }
+        }
+    }
 
-        let bytes = EthernetFrame::encode(&frame);
+    fn write_frame_ports(&mut self, frame: &EthernetFrame, ports: Vec<Port>) -> Vec<Port> {
+        let ports: Vec<Port> = ports
+            .into_iter()
+            .filter(|port| self.get_port_state(*port).is_forwarding() && !self.is_mirror_destination(*port))
+            .collect();
+        // an aggregation sends on one of its active members
+        let ports: Vec<Port> = ports
+            .into_iter()
+            .filter_map(|port| match self.lags.iter_mut().find(|lag| lag.is_member(port)) {
+                Some(lag) => lag.distribute(frame),
+                None => Some(port),
+            })
+            .collect();
+        let mut sent = vec![];
         for port in ports {
-            for byte in &bytes {
-                self.base.send((port, *byte));
+            let Some(bytes) = self.encode_for(port, frame) else { continue };
+            // tail drop when the buffer is full
+            if self.buffer_size.is_some_and(|size| self.queued_len() + bytes.len() > size) {
+                if crate::output::is_frame_level() {
+                    println!("{}({}): buffer full. drop frame", self.base.get_name(), self.base.get_mac().value);
+                }
+                self.drops += 1;
+                continue;
+            }
+            if let Some(egress) = &mut self.egress {
+                if egress.push(frame.get_priority(), port, bytes.clone()) {
+                    sent.push(port);
+                } else {
+                    self.drops += 1;
+                }
+                continue;
             }
+            self.write_frame(port, &bytes);
+            sent.push(port);
         }
+        sent
+    }
+
+    // bytes waiting for transmission, in the transmit queue and the priority queues
+    fn queued_len(&self) -> usize {
+        self.base.get_send_len() + self.egress.as_ref().map_or(0, |egress| egress.get_len())
+    }
+
+    // hand the next queued frame to the wire once the previous one has been sent
+    pub fn schedule_egress(&mut self) {
+        if self.cut_through.is_some() || self.base.get_send_len() > 0 {
+            return;
+        }
+        if let Some((port, bytes)) = self.egress.as_mut().and_then(|egress| egress.pop()) {
+            self.write_frame(port, &bytes);
+        }
+    }
+
+    // send a frame on the given ports. returns the ports the frame was sent to
+    pub fn send_ports(&mut self, frame: EthernetFrame, ports: Vec<Port>, ctx: &UpdateContext) -> Vec<Port> {
+        if ports.is_empty() {
+            return ports;
+        }
+        self.add_flog(ctx);
+        self.add_slog(&frame, ctx);
+        self.write_frame_ports(&frame, ports)
+    }
+
+    // send a frame on the given port regardless of the port state. used for BPDUs
+    pub fn send_to(&mut self, port: Port, frame: EthernetFrame, ctx: &UpdateContext) {
+        self.add_slog(&frame, ctx);
+        if let Some(bytes) = self.encode_for(port, &frame) {
+            self.write_frame(port, &bytes);
+        }
+    }
+
+    // a dynamic entry. it ages out and moves like a learned address
+    pub fn add_forwarding_table(&mut self, dst: Mac, port: Port, ctx: &UpdateContext) {
+        self.forward_table.learn(dst, port, ctx.t);
+    }
+
+    // forget learned addresses. static entries are kept
+    pub fn clear_forwarding_table(&mut self) {
+        self.forward_table.flush_dynamic();
+    }
+
+    pub fn join_group(&mut self, group: Mac) {
+        self.groups.insert(group);
+    }
+
+    pub fn leave_group(&mut self, group: Mac) {
+        self.groups.remove(&group);
+    }
+
+    // NIC filter. frames for other stations and unsubscribed groups are dropped
+    pub fn accepts(&self, dst: Mac) -> bool {
+        dst == self.base.get_mac() || dst.is_broadcast() || self.groups.contains(&dst)
+    }
+
+    // bundle the ports into one logical link negotiated by LACP
+    pub fn add_link_aggregation(&mut self, ports: Vec<Port>, config: LagConfig) {
+        let lag = LinkAggregation::new(self.base.get_mac(), ports, config);
+        self.lags.push(lag);
+    }
+
+    // the aggregation the port belongs to
+    pub fn get_link_aggregation(&self, port: Port) -> Option<&LinkAggregation> {
+        self.lags.iter().find(|lag| lag.is_member(port))
+    }
+
+    pub fn set_buffer_size(&mut self, buffer_size: Option<usize>) {
+        self.buffer_size = buffer_size;
+    }
+
+    // frames dropped because the buffer was full
+    pub fn get_drop_count(&self) -> usize {
+        self.drops
+    }
+
+    // emit PAUSE frames when the buffer fills. PAUSE frames from the peers are always honoured
+    pub fn set_flow_control(&mut self, flow_control: Option<FlowControl>) {
+        self.flow_control = flow_control;
+    }
+
+    // frames wait in priority queues instead of the single transmit queue.
+    // only switches drain the queues, see schedule_egress
+    pub(super) fn set_qos(&mut self, qos: Option<QosConfig>) -> Res<()> {
+        self.egress = qos.map(EgressQueues::new).transpose()?;
+        Ok(())
+    }
+
+    pub fn get_egress_queues(&self) -> Option<&EgressQueues> {
+        self.egress.as_ref()
+    }
+
+    pub fn set_switching_mode(&mut self, mode: SwitchingMode) {
+        self.mode = mode;
+    }
+
+    // forwarding latency of each relayed frame
+    pub fn get_forward_log(&self) -> &Vec<ForwardLog> {
+        &self.flog
+    }
+
+    // frames which could not be decoded
+    pub fn get_rx_error_count(&self) -> usize {
+        self.rx_errors
+    }
+
+    // advertise this device and discover its neighbors on every port
+    pub fn enable_lldp(&mut self, config: LldpConfig) {
+        self.lldp = Some(Lldp::new(self.base.get_mac(), self.base.get_name(), config));
+    }
+
+    pub fn get_lldp(&self) -> Option<&Lldp> {
+        self.lldp.as_ref()
+    }
+
+    // frame the port with PPP and negotiate the link with LCP. for serial links between routers
+    pub fn set_ppp(&mut self, port: Port, config: PppConfig) {
+        let magic = (self.base.get_mac().value as u32) ^ port.value;
+        self.ppp.insert(port, PppLink::new(magic, config));
+    }
+
+    pub fn get_ppp(&self, port: Port) -> Option<&PppLink> {
+        self.ppp.get(&port)
+    }
+
+    pub fn get_mac_table(&self) -> &MacTable {
+        &self.forward_table
+    }
+
+    pub fn mac_table_mut(&mut self) -> &mut MacTable {
+        &mut self.forward_table
+    }
+
+    pub fn get_port_state(&self, port: Port) -> PortState {
+        self.port_states.get(&port).copied().unwrap_or(PortState::Forwarding)
+    }
+
+    pub fn set_port_state(&mut self, port: Port, state: PortState) {
+        self.port_states.insert(port, state);
+    }
+
+    pub fn set_mirror_destination(&mut self, port: Port) {
+        self.mirror_destinations.insert(port);
     }
 
-    pub fn add_forwarding_table(&mut self, dst: Mac, port: Port) {
-        self.forward_table.insert(dst, port);
+    pub fn is_mirror_destination(&self, port: Port) -> bool {
+        self.mirror_destinations.contains(&port)
     }
 }
 
@@ -112,8 +653,8 @@ pub trait EthernetDevice {
         self.ether_base_mut().send(frame, ctx)
     }
 
-    fn add_forwarding_table(&mut self, dst: Mac, port: Port) {
-        self.ether_base_mut().add_forwarding_table(dst, port)
+    fn add_forwarding_table(&mut self, dst: Mac, port: Port, ctx: &UpdateContext) {
+        self.ether_base_mut().add_forwarding_table(dst, port, ctx)
     }
 
     fn get_rlog(&self) -> &Vec<EthernetLog> {
@@ -1,10 +1,38 @@
 use super::super::utils::{read_6bytes, read_2bytes, split_6bytes, split_2bytes};
 use super::super::types::{Mac, Res, Error};
+use super::{lldpdu_len, slow_protocols_len, ETHERTYPE_LLDP, ETHERTYPE_MAC_CONTROL, ETHERTYPE_SLOW_PROTOCOLS, PAUSE_LEN};
+
+pub const ETHERTYPE_VLAN: u16 = 0x8100;
+// preamble, addresses, VLAN tag, type and 1500 bytes of payload
+pub const MAX_FRAME_LEN: usize = 8 + 6 + 6 + 4 + 2 + 1500;
+
+// 802.1Q tag control information
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VlanTag {
+    pub pcp: u8,   // 3 bits, 802.1p priority
+    pub dei: bool, // 1 bit
+    pub vid: u16,  // 12 bits
+}
+
+impl VlanTag {
+    pub fn decode(tci: u16) -> VlanTag {
+        VlanTag {
+            pcp: (tci >> 13) as u8,
+            dei: (tci >> 12) & 1 == 1,
+            vid: tci & 0x0FFF,
+        }
+    }
+
+    pub fn encode(&self) -> u16 {
+        ((self.pcp as u16 & 0x07) << 13) | ((self.dei as u16) << 12) | (self.vid & 0x0FFF)
+    }
+}
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct EthernetFrame {
     pub dst: Mac,       // 6 bytes
     pub src: Mac,       // 6 bytes
+    pub tag: Option<VlanTag>, // 4 bytes if tagged
     pub ethertype: u16, // 2 bytes
     pub payload: Vec<u8>,
 }
@@ -16,11 +44,27 @@ impl EthernetFrame {
         EthernetFrame {
             dst,
             src,
+            tag: None,
+            ethertype,
+            payload,
+        }
+    }
+
+    pub fn new_tagged(dst: Mac, src: Mac, tag: VlanTag, ethertype: u16, payload: Vec<u8>) -> EthernetFrame {
+        EthernetFrame {
+            dst,
+            src,
+            tag: Some(tag),
             ethertype,
             payload,
         }
     }
 
+    // untagged frames have the default priority 0
+    pub fn get_priority(&self) -> u8 {
+        self.tag.map_or(0, |tag| tag.pcp)
+    }
+
     // pub fn new_bloadcast(src: Mac, ethertype: u16, payload: Vec<u8>) -> EthernetFrame {
     //     EthernetFrame {
     //         dst: MAC_BLOADCAST,
@@ -30,8 +74,9 @@ impl EthernetFrame {
     //     }
     // }
 
-    pub fn is_bloadcast(&self) -> bool {
-        self.dst == MAC_BROADCAST
+    // broadcast is also a multicast
+    pub fn is_multicast(&self) -> bool {
+        self.dst.is_multicast()
     }
 
     pub fn decode(xs: &Vec<u8>) -> Res<EthernetFrame> {
@@ -54,17 +99,36 @@ impl EthernetFrame {
         }
         let dst = Mac::new(read_6bytes(xs, 8));
         let src = Mac::new(read_6bytes(xs, 8 + 6));
-        let ty = read_2bytes(xs, 8 + 6 + 6);
+        let mut i = 8 + 6 + 6;
+        let mut ty = read_2bytes(xs, i);
+        let mut tag = None;
+        if ty == ETHERTYPE_VLAN {
+            if xs.len() < i + 4 + 2 {
+                return Err(Error::NotEnoughBytes);
+            }
+            tag = Some(VlanTag::decode(read_2bytes(xs, i + 2)));
+            i += 4;
+            ty = read_2bytes(xs, i);
+        }
+        let i = i + 2; // payload
         let len = match ty {
             0x0800 => { 
                 // IPv4
-                let i = 8+6+6+2;
                 if xs.len() < i + 2 + 2 {
                     return Err(Error::NotEnoughBytes);
                 }
                 read_2bytes(xs, i+2) as usize
             }
-            0x0806 => 24, // ARP
+            0x0806 => {
+                // ARP. the addresses are sized by the header
+                if xs.len() < i + 6 {
+                    return Err(Error::NotEnoughBytes);
+                }
+                8 + 2 * (xs[i + 4] as usize + xs[i + 5] as usize)
+            }
+            ETHERTYPE_SLOW_PROTOCOLS => slow_protocols_len(&xs[i..])?,
+            ETHERTYPE_MAC_CONTROL => PAUSE_LEN,
+            ETHERTYPE_LLDP => lldpdu_len(&xs[i..])?,
             ty if ty <= 0x05DC => ty as usize,
             _ => {
                 return Err(Error::InvalidBytes {
@@ -72,13 +136,14 @@ impl EthernetFrame {
                 });
             }
         };
-        if xs.len() < 8 + 6 + 6 + 2 + len {
+        if xs.len() < i + len {
             return Err(Error::NotEnoughBytes);
         }
-        let payload = Vec::from(&xs[8+6+6+2..8+6+6+2+len]);
+        let payload = Vec::from(&xs[i..i+len]);
         Ok(EthernetFrame {
             dst,
             src,
+            tag,
             ethertype: ty,
             payload,
         })
@@ -91,8 +156,13 @@ impl EthernetFrame {
         let mut xs = vec![
             0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAB, // preamble
             dst[0], dst[1], dst[2], dst[3], dst[4], dst[5], src[0], src[1], src[2], src[3], src[4],
-            src[5], et[0], et[1],
+            src[5],
         ];
+        if let Some(tag) = frame.tag {
+            xs.extend_from_slice(&split_2bytes(ETHERTYPE_VLAN));
+            xs.extend_from_slice(&split_2bytes(tag.encode()));
+        }
+        xs.extend_from_slice(&et);
         xs.append(&mut frame.payload.clone());
         xs
     }
@@ -105,6 +175,10 @@ impl std::fmt::Display for EthernetFrame {
             payload = format!("{} {}", payload, x);
         }
         payload = format!("{}]", payload);
+        if let Some(tag) = self.tag {
+            return write!(f, "EthernetFrame(dst:{}, src:{}, vid:{}, pcp:{}, typ:{}, payload:{})",
+                          self.dst.value, self.src.value, tag.vid, tag.pcp, self.ethertype, payload);
+        }
         write!(f, "EthernetFrame(dst:{}, src:{}, typ:{}, payload:{})", 
                self.dst.value, self.src.value, self.ethertype, payload)
     }
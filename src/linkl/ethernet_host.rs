@@ -2,38 +2,40 @@ use std::collections::VecDeque;
 
 use crate::{types::{Res, Mac, UpdateContext}, physl::{Device, BaseDevice}};
 
-use super::{BaseEthernetDevice, EthernetDevice, EthernetFrame, EthernetLog};
+use super::{AppContext, BaseEthernetDevice, ConsumerApp, EchoApp, EthernetApp, EthernetDevice, EthernetFrame, EthernetLog};
 pub struct EthernetHost {
     pub base: BaseEthernetDevice,
     schedules: VecDeque<EthernetLog>,
-    handler: Box<dyn Fn(&Vec<u8>) -> Res<Option<Vec<u8>>>>,
+    app: Box<dyn EthernetApp>,
+    app_ctx: AppContext,
 }
 
 impl EthernetHost {
-    fn new(base: BaseEthernetDevice, handler: Box<dyn Fn(&Vec<u8>) -> Res<Option<Vec<u8>>>>) -> EthernetHost {
+    fn new(base: BaseEthernetDevice, app: Box<dyn EthernetApp>) -> EthernetHost {
+        let app_ctx = AppContext::new(base.base.get_mac());
         EthernetHost {
             base,
             schedules: VecDeque::new(),
-            handler,
+            app,
+            app_ctx,
         }
     }
 
     pub fn build_consumer(mac: Mac, name: &str) -> Box<EthernetHost> {
-        let base = BaseEthernetDevice::new(mac, name, 1);
-        let handler = Box::new(
-            |_bytes: &Vec<u8>| {
-                Ok(None)
-            }
-        );
-        Box::new(Self::new(base, handler))
+        Self::build_app(mac, name, Box::new(ConsumerApp))
     }
 
     pub fn build_echo(mac: Mac, name: &str) -> Box<EthernetHost> {
+        Self::build_app(mac, name, Box::new(EchoApp))
+    }
+
+    pub fn build_app(mac: Mac, name: &str, app: Box<dyn EthernetApp>) -> Box<EthernetHost> {
         let base = BaseEthernetDevice::new(mac, name, 1);
-        let handler = Box::new(
-            |bytes: &Vec<u8>| Ok(Some(bytes.clone()))
-        );
-        Box::new(Self::new(base, handler))
+        Box::new(Self::new(base, app))
+    }
+
+    pub fn get_app(&self) -> &dyn EthernetApp {
+        self.app.as_ref()
     }
 
     pub fn add_schedule(&mut self, t: usize, frame: EthernetFrame) {
@@ -64,6 +66,10 @@ impl Device for EthernetHost {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
     fn update(&mut self, ctx: &UpdateContext) -> Res<()> {
         if let Some(schedule) = self.schedules.front() {
             if schedule.t == ctx.t {
@@ -72,14 +78,19 @@ impl Device for EthernetHost {
             }
         }
 
+        self.app_ctx.set_t(ctx.t);
+        for id in self.app_ctx.pop_expired_timers() {
+            self.app.on_timer(id, &mut self.app_ctx)?;
+        }
         if let Some(frame) = self.recv(ctx) {
-            if frame.dst == self.get_mac() {
-                if let Some(bytes) = (self.handler)(&frame.payload)? {
-                    let frame = EthernetFrame::new(frame.src, frame.dst, frame.ethertype, bytes);
-                    self.send(frame, ctx);
-                }
+            if self.base.accepts(frame.dst) {
+                self.app.on_frame(&frame, &mut self.app_ctx)?;
             }
         }
+        self.app.on_tick(&mut self.app_ctx)?;
+        for frame in self.app_ctx.take_outbox() {
+            self.send(frame, ctx);
+        }
         Ok(())
     }
 }
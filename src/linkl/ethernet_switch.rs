@@ -1,16 +1,48 @@
-use crate::types::{Mac, Res, UpdateContext};
+use crate::types::{Mac, Port, Res, UpdateContext};
 use crate::physl::{BaseDevice, Device};
 
-use super::BaseEthernetDevice;
+use super::{BaseEthernetDevice, Bpdu, Lldp, LldpConfig, EgressQueues, EthernetFrame, FlowControl, ForwardLog, SwitchingMode, QosConfig, LagConfig, LinkAggregation, MacTable, MulticastMode, MulticastTable, PortState, Stp, StpConfig, STP_MULTICAST};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MirrorDirection {
+    Ingress,
+    Egress,
+    Both,
+}
+
+// SPAN. traffic of the source ports is copied to the destination (monitor) port
+#[derive(Debug, Clone)]
+pub struct MirrorSession {
+    pub sources: Vec<(Port, MirrorDirection)>,
+    pub destination: Port,
+}
+
+impl MirrorSession {
+    fn matches(&self, ingress: Port, egress: &[Port]) -> bool {
+        self.sources.iter().any(|(port, dir)| match dir {
+            MirrorDirection::Ingress => *port == ingress,
+            MirrorDirection::Egress => egress.contains(port),
+            MirrorDirection::Both => *port == ingress || egress.contains(port),
+        })
+    }
+}
 
 pub struct EthernetSwitch {
     base: BaseEthernetDevice,
+    stp: Option<Stp>,
+    mirrors: Vec<MirrorSession>,
+    multicast_mode: MulticastMode,
+    multicast: MulticastTable,
 }
 
 impl EthernetSwitch {
     fn new(base: BaseEthernetDevice) -> EthernetSwitch {
         EthernetSwitch {
             base,
+            stp: None,
+            mirrors: Vec::new(),
+            multicast_mode: MulticastMode::Flood,
+            multicast: MulticastTable::new(),
         }
     }
 
@@ -23,6 +55,108 @@ impl EthernetSwitch {
         let base = BaseEthernetDevice::new(mac, name, num_ports);
         Box::new(Self::new(base))
     }
+
+    pub fn build_stp_switch(mac: Mac, name: &str, num_ports: usize, config: StpConfig) -> Box<EthernetSwitch> {
+        let mut switch = Self::new(BaseEthernetDevice::new(mac, name, num_ports));
+        let mut stp = Stp::new(mac, num_ports, config);
+        stp.init(&mut switch.base);
+        switch.stp = Some(stp);
+        Box::new(switch)
+    }
+
+    pub fn get_stp(&self) -> Option<&Stp> {
+        self.stp.as_ref()
+    }
+
+    pub fn set_multicast_mode(&mut self, mode: MulticastMode) {
+        self.multicast_mode = mode;
+    }
+
+    fn switch_frame(&mut self, frame: &EthernetFrame, ingress: Port, ctx: &UpdateContext) -> Vec<Port> {
+        if self.multicast_mode == MulticastMode::Snooping {
+            self.multicast.snoop(frame, ingress);
+            if let (true, Some(ports)) = (frame.is_multicast(), self.multicast.get(frame.dst)) {
+                // registered group. only the member ports
+                let ports = ports.into_iter().filter(|p| *p != ingress).collect();
+                return self.base.send_ports(frame.clone(), ports, ctx);
+            }
+        }
+        self.base.forward(frame.clone(), ingress, ctx)
+    }
+
+    // the monitor port leaves normal switching and STP. it neither learns nor receives flooded frames
+    pub fn add_mirror_session(&mut self, session: MirrorSession) {
+        self.base.set_port_state(session.destination, PortState::Blocking);
+        self.base.set_mirror_destination(session.destination);
+        self.mirrors.push(session);
+    }
+
+    fn mirror(&mut self, frame: &EthernetFrame, ingress: Port, egress: &[Port], ctx: &UpdateContext) {
+        let destinations: Vec<Port> = self.mirrors
+            .iter()
+            .filter(|m| m.destination != ingress && m.matches(ingress, egress))
+            .map(|m| m.destination)
+            .collect();
+        for port in destinations {
+            self.base.send_to(port, frame.clone(), ctx);
+        }
+    }
+
+    pub fn add_link_aggregation(&mut self, ports: Vec<Port>, config: LagConfig) {
+        self.base.add_link_aggregation(ports, config);
+    }
+
+    pub fn get_link_aggregation(&self, port: Port) -> Option<&LinkAggregation> {
+        self.base.get_link_aggregation(port)
+    }
+
+    pub fn set_buffer_size(&mut self, buffer_size: Option<usize>) {
+        self.base.set_buffer_size(buffer_size);
+    }
+
+    pub fn set_flow_control(&mut self, flow_control: Option<FlowControl>) {
+        self.base.set_flow_control(flow_control);
+    }
+
+    pub fn get_drop_count(&self) -> usize {
+        self.base.get_drop_count()
+    }
+
+    pub fn enable_lldp(&mut self, config: LldpConfig) {
+        self.base.enable_lldp(config);
+    }
+
+    pub fn get_lldp(&self) -> Option<&Lldp> {
+        self.base.get_lldp()
+    }
+
+    pub fn set_switching_mode(&mut self, mode: SwitchingMode) {
+        self.base.set_switching_mode(mode);
+    }
+
+    pub fn get_forward_log(&self) -> &Vec<ForwardLog> {
+        self.base.get_forward_log()
+    }
+
+    pub fn get_rx_error_count(&self) -> usize {
+        self.base.get_rx_error_count()
+    }
+
+    pub fn set_qos(&mut self, qos: Option<QosConfig>) -> Res<()> {
+        self.base.set_qos(qos)
+    }
+
+    pub fn get_egress_queues(&self) -> Option<&EgressQueues> {
+        self.base.get_egress_queues()
+    }
+
+    pub fn get_mac_table(&self) -> &MacTable {
+        self.base.get_mac_table()
+    }
+
+    pub fn mac_table_mut(&mut self) -> &mut MacTable {
+        self.base.mac_table_mut()
+    }
 }
 
 impl Device for EthernetSwitch {
@@ -38,12 +172,184 @@ impl Device for EthernetSwitch {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
     fn update(&mut self, ctx: &UpdateContext) -> Res<()> {
+        if let Some(stp) = &mut self.stp {
+            stp.update(&mut self.base, ctx);
+        }
+
         // rbuf -> sbuf
-        while let Some(frame) = self.base.recv(ctx) {
-            self.base.send(frame, ctx);
+        while let Some((port, frame)) = self.base.recv_from(ctx) {
+            if let (Some(stp), true) = (&mut self.stp, frame.dst == STP_MULTICAST) {
+                match Bpdu::decode(&frame.payload) {
+                    Ok(bpdu) => stp.recv(port, &bpdu, &mut self.base, ctx),
+                    Err(e) => {
+                        if crate::output::is_frame_level() {
+                            println!("{}({}): invalid BPDU: {}", self.base.base.get_name(), self.base.base.get_mac().value, e);
+                        }
+                        stp.recv_invalid();
+                    }
+                }
+                continue;
+            }
+            let egress = match self.base.take_relayed() {
+                Some(ports) => ports,
+                None => self.switch_frame(&frame, port, ctx),
+            };
+            self.mirror(&frame, port, &egress, ctx);
         }
+        self.base.schedule_egress();
         Ok(())
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use crate::types::Port;
+    use super::super::{EthernetFrame, MAC_BROADCAST, MAX_FRAME_LEN};
+    use super::*;
+
+    fn push_frame(switch: &mut EthernetSwitch, port: Port, frame: &EthernetFrame) {
+        for x in EthernetFrame::encode(frame) {
+            switch.push_recv(port, x);
+        }
+    }
+
+    fn sent_ports(switch: &mut EthernetSwitch) -> Vec<Port> {
+        let mut ports = vec![];
+        while let Some((port, _)) = switch.pop_send() {
+            if !ports.contains(&port) {
+                ports.push(port);
+            }
+        }
+        ports
+    }
+
+    #[test]
+    fn test_forward_ingress_exclusion() {
+        let ctx = UpdateContext { t: 0 };
+        let mac_a = Mac::new(21);
+        let mac_b = Mac::new(22);
+        let mac_c = Mac::new(23);
+        let mut switch = EthernetSwitch::build_switch(Mac::new(30), "switch", 3);
+
+        // unknown unicast is flooded except to the ingress port
+        push_frame(&mut switch, Port::new(0), &EthernetFrame::new(mac_b, mac_a, 3, vec![1, 1, 1]));
+        switch.update(&ctx).unwrap();
+        assert_eq!(vec![Port::new(1), Port::new(2)], sent_ports(&mut switch));
+
+        // the destination was learned on the ingress port. filter it
+        push_frame(&mut switch, Port::new(0), &EthernetFrame::new(mac_a, mac_c, 3, vec![2, 2, 2]));
+        switch.update(&ctx).unwrap();
+        assert!(sent_ports(&mut switch).is_empty());
+
+        // known unicast goes to the learned port only
+        push_frame(&mut switch, Port::new(2), &EthernetFrame::new(mac_c, mac_b, 3, vec![3, 3, 3]));
+        switch.update(&ctx).unwrap();
+        assert_eq!(vec![Port::new(0)], sent_ports(&mut switch));
+
+        // broadcast is flooded even if its address was somehow learned
+        switch.mac_table_mut().add_static(MAC_BROADCAST, Port::new(1));
+        push_frame(&mut switch, Port::new(1), &EthernetFrame::new(MAC_BROADCAST, mac_a, 3, vec![4, 4, 4]));
+        switch.update(&ctx).unwrap();
+        assert_eq!(vec![Port::new(0), Port::new(2)], sent_ports(&mut switch));
+    }
+
+    #[test]
+    fn test_stp_mirror_destination() {
+        let config = StpConfig::default();
+        let mut switch = EthernetSwitch::build_stp_switch(Mac::new(30), "switch", 3, config);
+        switch.add_mirror_session(MirrorSession {
+            sources: vec![(Port::new(0), MirrorDirection::Ingress)],
+            destination: Port::new(2),
+        });
+        // long enough for the other ports to reach forwarding
+        for t in 0..=3 * config.forward_delay as usize {
+            switch.update(&UpdateContext { t }).unwrap();
+        }
+        let stp = switch.get_stp().unwrap();
+        assert_eq!(PortState::Forwarding, stp.get_port_state(Port::new(0)));
+        assert_eq!(PortState::Blocking, stp.get_port_state(Port::new(2)));
+        assert_eq!(PortState::Blocking, switch.base.get_port_state(Port::new(2)));
+        while switch.pop_send().is_some() {}
+
+        // flooded frames from a port that is not mirrored skip the monitor port
+        // before the next hello
+        let ctx = UpdateContext { t: 3 * config.forward_delay as usize + 1 };
+        push_frame(&mut switch, Port::new(1), &EthernetFrame::new(MAC_BROADCAST, Mac::new(22), 3, vec![1, 1, 1]));
+        switch.update(&ctx).unwrap();
+        assert_eq!(vec![Port::new(0)], sent_ports(&mut switch));
+
+        // the mirrored port is copied to it
+        push_frame(&mut switch, Port::new(0), &EthernetFrame::new(MAC_BROADCAST, Mac::new(21), 3, vec![2, 2, 2]));
+        switch.update(&ctx).unwrap();
+        assert_eq!(vec![Port::new(1), Port::new(2)], sent_ports(&mut switch));
+    }
+
+    #[test]
+    fn test_qos_buffer_size() {
+        let ctx = UpdateContext { t: 0 };
+        let mut switch = EthernetSwitch::build_switch(Mac::new(30), "switch", 3);
+        assert!(switch.set_qos(Some(QosConfig { num_queues: 0, ..QosConfig::default() })).is_err());
+        switch.set_qos(Some(QosConfig::default())).unwrap();
+        switch.set_buffer_size(Some(100));
+        switch.mac_table_mut().add_static(Mac::new(23), Port::new(2));
+
+        // the buffer counts the frames in the priority queues too
+        for i in 0..3 {
+            push_frame(&mut switch, Port::new(0), &EthernetFrame::new(Mac::new(23), Mac::new(21), 40, vec![i; 40]));
+        }
+        switch.update(&ctx).unwrap();
+        assert_eq!(2, switch.get_drop_count());
+    }
+
+    #[test]
+    fn test_cut_through_mirror() {
+        let ctx = UpdateContext { t: 0 };
+        let mut switch = EthernetSwitch::build_switch(Mac::new(30), "switch", 3);
+        switch.set_switching_mode(SwitchingMode::CutThrough);
+        switch.add_mirror_session(MirrorSession {
+            sources: vec![(Port::new(0), MirrorDirection::Ingress)],
+            destination: Port::new(2),
+        });
+        switch.mac_table_mut().add_static(Mac::new(22), Port::new(1));
+
+        // relayed once and copied to the monitor port
+        let frame = EthernetFrame::new(Mac::new(22), Mac::new(21), 3, vec![1, 1, 1]);
+        push_frame(&mut switch, Port::new(0), &frame);
+        switch.update(&ctx).unwrap();
+        let mut counts = [0; 3];
+        while let Some((port, _)) = switch.pop_send() {
+            counts[port.value as usize] += 1;
+        }
+        let len = EthernetFrame::encode(&frame).len();
+        assert_eq!([0, len, len], counts);
+        assert_eq!(SwitchingMode::CutThrough, switch.get_forward_log()[0].mode);
+
+        // the length never completes. the relay gives up after the largest frame
+        let mut xs = EthernetFrame::encode(&EthernetFrame::new(Mac::new(22), Mac::new(21), 0x0800, vec![0x45, 0, 0xFF, 0xFF]));
+        xs.resize(MAX_FRAME_LEN + 1, 0);
+        for x in xs {
+            switch.push_recv(Port::new(0), x);
+        }
+        switch.update(&ctx).unwrap();
+        assert_eq!(1, switch.get_rx_error_count());
+        while switch.pop_send().is_some() {}
+        push_frame(&mut switch, Port::new(0), &frame);
+        switch.update(&ctx).unwrap();
+        assert_eq!(vec![Port::new(1), Port::new(2)], sent_ports(&mut switch));
+    }
+
+    #[test]
+    fn test_invalid_bpdu() {
+        let ctx = UpdateContext { t: 0 };
+        let mut switch = EthernetSwitch::build_stp_switch(Mac::new(30), "switch", 2, StpConfig::default());
+        let payload = vec![0x42, 0x42, 0x03, 0x00, 0x00, 0x00, 0x55];
+        push_frame(&mut switch, Port::new(0), &EthernetFrame::new(STP_MULTICAST, Mac::new(21), payload.len() as u16, payload));
+        switch.update(&ctx).unwrap();
+        assert_eq!(1, switch.get_stp().unwrap().get_invalid_bpdus());
+    }
+}
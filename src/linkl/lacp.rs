@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+
+use crate::types::{Mac, Port, Res, Error};
+use crate::utils::{read_2bytes, read_4bytes, read_6bytes};
+
+use super::EthernetFrame;
+
+// 01:80:C2:00:00:02, slow protocols group address
+pub const SLOW_PROTOCOLS_MULTICAST: Mac = Mac { value: 0x0180_C200_0002 };
+pub const ETHERTYPE_SLOW_PROTOCOLS: u16 = 0x8809;
+pub const LACPDU_LEN: usize = 2 + 11 + 11;
+const SUBTYPE_LACP: u8 = 0x01;
+
+// actor/partner state bits
+pub const LACP_ACTIVITY: u8 = 0x01;
+pub const LACP_AGGREGATION: u8 = 0x04;
+pub const LACP_SYNCHRONIZATION: u8 = 0x08;
+pub const LACP_COLLECTING: u8 = 0x10;
+pub const LACP_DISTRIBUTING: u8 = 0x20;
+
+// the length of a slow protocols PDU is given by its subtype. only LACP is supported
+pub fn slow_protocols_len(xs: &[u8]) -> Res<usize> {
+    match xs.first() {
+        None => Err(Error::NotEnoughBytes),
+        Some(&SUBTYPE_LACP) => Ok(LACPDU_LEN),
+        Some(subtype) => Err(Error::InvalidBytes { msg: format!("unsupported slow protocol subtype: {}", subtype) }),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LacpInfo { // total = 11 bytes
+    pub system: Mac,
+    pub key: u16,
+    pub port: u16,
+    pub state: u8,
+}
+
+impl LacpInfo {
+    fn decode(xs: &Vec<u8>, offset: usize) -> LacpInfo {
+        LacpInfo {
+            system: Mac::new(read_6bytes(xs, offset)),
+            key: read_2bytes(xs, offset + 6),
+            port: read_2bytes(xs, offset + 8),
+            state: xs[offset + 10],
+        }
+    }
+
+    fn encode(&self, xs: &mut Vec<u8>) {
+        xs.extend_from_slice(&self.system.value.to_be_bytes()[2..]);
+        xs.extend_from_slice(&self.key.to_be_bytes());
+        xs.extend_from_slice(&self.port.to_be_bytes());
+        xs.push(self.state);
+    }
+
+    // same aggregation end, ignoring the state
+    fn is_same_port(&self, other: &LacpInfo) -> bool {
+        (self.system, self.key, self.port) == (other.system, other.key, other.port)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lacpdu {
+    pub actor: LacpInfo,
+    pub partner: LacpInfo, // what the sender knows about us
+}
+
+impl Lacpdu {
+    pub fn decode(xs: &[u8]) -> Res<Lacpdu> {
+        if xs.len() < LACPDU_LEN {
+            return Err(Error::NotEnoughBytes);
+        }
+        if xs[0] != SUBTYPE_LACP || xs[1] != 0x01 {
+            return Err(Error::InvalidBytes { msg: format!("unsupported slow protocol: {}.{}", xs[0], xs[1]) });
+        }
+        let xs = Vec::from(xs);
+        Ok(Lacpdu {
+            actor: LacpInfo::decode(&xs, 2),
+            partner: LacpInfo::decode(&xs, 13),
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut xs = vec![SUBTYPE_LACP, 0x01]; // subtype, version
+        self.actor.encode(&mut xs);
+        self.partner.encode(&mut xs);
+        xs
+    }
+
+    pub fn to_frame(&self, src: Mac) -> EthernetFrame {
+        EthernetFrame::new(SLOW_PROTOCOLS_MULTICAST, src, ETHERTYPE_SLOW_PROTOCOLS, self.encode())
+    }
+}
+
+impl std::fmt::Display for Lacpdu {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "LACPDU(actor:{}.{}.{} state:{:0>2X}, partner:{}.{}.{} state:{:0>2X})",
+               self.actor.system.value, self.actor.key, self.actor.port, self.actor.state,
+               self.partner.system.value, self.partner.key, self.partner.port, self.partner.state)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LacpMode {
+    Active,  // sends LACPDUs on its own
+    Passive, // only answers an active partner
+}
+
+// timers are counted in ticks
+#[derive(Debug, Clone, Copy)]
+pub struct LagConfig {
+    pub key: u16,
+    pub mode: LacpMode,
+    pub periodic_time: usize,
+    pub timeout: usize, // a member without LACPDUs for this long is taken out of the bundle
+}
+
+impl Default for LagConfig {
+    fn default() -> LagConfig {
+        LagConfig {
+            key: 1,
+            mode: LacpMode::Active,
+            periodic_time: 300,
+            timeout: 900,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct LagMember {
+    partner: Option<LacpInfo>,
+    acked: bool, // the partner reported our port correctly
+    t_recv: usize,
+    t_sent: Option<usize>,
+    ntt: bool, // need to transmit
+    tx: usize,
+}
+
+// one logical link made of several physical ports. the first port stands for the bundle
+// in the mac address table and in flooding
+pub struct LinkAggregation {
+    system: Mac,
+    ports: Vec<Port>,
+    config: LagConfig,
+    members: HashMap<Port, LagMember>,
+}
+
+impl LinkAggregation {
+    pub fn new(system: Mac, ports: Vec<Port>, config: LagConfig) -> LinkAggregation {
+        let members = ports.iter().map(|port| (*port, LagMember::default())).collect();
+        LinkAggregation { system, ports, config, members }
+    }
+
+    pub fn get_port(&self) -> Port {
+        self.ports[0]
+    }
+
+    pub fn get_ports(&self) -> &Vec<Port> {
+        &self.ports
+    }
+
+    pub fn is_member(&self, port: Port) -> bool {
+        self.members.contains_key(&port)
+    }
+
+    // the partner of the bundle is taken from the lowest acknowledged port
+    fn aggregator_partner(&self) -> Option<(Mac, u16)> {
+        self.ports
+            .iter()
+            .filter_map(|port| self.members.get(port))
+            .filter(|m| m.acked)
+            .find_map(|m| m.partner.map(|p| (p.system, p.key)))
+    }
+
+    fn is_selected(&self, port: Port) -> bool {
+        let Some(m) = self.members.get(&port) else { return false };
+        match (m.partner, self.aggregator_partner()) {
+            (Some(p), Some(agg)) => m.acked && p.state & LACP_AGGREGATION != 0 && (p.system, p.key) == agg,
+            _ => false,
+        }
+    }
+
+    // collecting and distributing on this port
+    pub fn is_active(&self, port: Port) -> bool {
+        self.is_selected(port) && self.members[&port].partner.is_some_and(|p| p.state & LACP_SYNCHRONIZATION != 0)
+    }
+
+    pub fn get_active_ports(&self) -> Vec<Port> {
+        self.ports.iter().copied().filter(|port| self.is_active(*port)).collect()
+    }
+
+    // frames sent on the port
+    pub fn get_tx_count(&self, port: Port) -> usize {
+        self.members.get(&port).map_or(0, |m| m.tx)
+    }
+
+    fn actor_info(&self, port: Port) -> LacpInfo {
+        let mut state = LACP_AGGREGATION;
+        if self.config.mode == LacpMode::Active {
+            state |= LACP_ACTIVITY;
+        }
+        if self.is_selected(port) {
+            state |= LACP_SYNCHRONIZATION | LACP_COLLECTING | LACP_DISTRIBUTING;
+        }
+        LacpInfo {
+            system: self.system,
+            key: self.config.key,
+            port: port.value as u16 + 1,
+            state,
+        }
+    }
+
+    pub fn recv(&mut self, port: Port, pdu: &Lacpdu, t: usize) {
+        let actor = self.actor_info(port);
+        let Some(m) = self.members.get_mut(&port) else { return };
+        let changed = m.partner != Some(pdu.actor);
+        m.partner = Some(pdu.actor);
+        m.acked = pdu.partner.is_same_port(&actor);
+        m.t_recv = t;
+        // answer as soon as the partner has a stale view of us
+        if changed || pdu.partner != self.actor_info(port) {
+            self.members.get_mut(&port).unwrap().ntt = true;
+        }
+    }
+
+    // expire silent partners and collect LACPDUs to send
+    pub fn update(&mut self, t: usize) -> Vec<(Port, Lacpdu)> {
+        for (port, m) in self.members.iter_mut() {
+            if m.partner.is_some() && t >= m.t_recv + self.config.timeout {
+                if crate::output::is_frame_level() {
+                    println!("{:>3}: lacp({}): port {} timed out", t, self.system.value, port.value);
+                }
+                m.partner = None;
+                m.acked = false;
+            }
+        }
+
+        let mut pdus = vec![];
+        for port in self.ports.clone() {
+            let m = &self.members[&port];
+            let periodic = (self.config.mode == LacpMode::Active || m.partner.is_some())
+                && m.t_sent.is_none_or(|t_sent| t >= t_sent + self.config.periodic_time);
+            if m.ntt || periodic {
+                let pdu = Lacpdu {
+                    actor: self.actor_info(port),
+                    partner: m.partner.unwrap_or_default(),
+                };
+                let m = self.members.get_mut(&port).unwrap();
+                m.ntt = false;
+                m.t_sent = Some(t);
+                pdus.push((port, pdu));
+            }
+        }
+        pdus
+    }
+
+    // pick the member for the frame. frames of the same flow always take the same member
+    pub fn distribute(&mut self, frame: &EthernetFrame) -> Option<Port> {
+        let ports = self.get_active_ports();
+        if ports.is_empty() {
+            return None;
+        }
+        let port = ports[(flow_hash(frame) % ports.len() as u64) as usize];
+        self.members.get_mut(&port).unwrap().tx += 1;
+        Some(port)
+    }
+}
+
+fn flow_hash(frame: &EthernetFrame) -> u64 {
+    let mut h = frame.src.value ^ frame.dst.value;
+    if frame.ethertype == 0x0800 && frame.payload.len() >= 20 {
+        // IPv4 source and destination
+        h ^= (read_4bytes(&frame.payload, 12) ^ read_4bytes(&frame.payload, 16)) as u64;
+    }
+    h ^ (h >> 16) ^ (h >> 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lacpdu() {
+        let pdu = Lacpdu {
+            actor: LacpInfo { system: Mac::new(31), key: 1, port: 2, state: LACP_ACTIVITY | LACP_AGGREGATION },
+            partner: LacpInfo::default(),
+        };
+        let xs = pdu.encode();
+        assert_eq!(LACPDU_LEN, xs.len());
+        assert_eq!(pdu, Lacpdu::decode(&xs).unwrap());
+        assert_eq!(LACPDU_LEN, slow_protocols_len(&xs).unwrap());
+        // marker protocol
+        assert!(slow_protocols_len(&[0x02, 0x01]).is_err());
+        assert!(matches!(slow_protocols_len(&[]), Err(Error::NotEnoughBytes)));
+    }
+
+    #[test]
+    fn test_lacp_negotiation() {
+        let ports = vec![Port::new(1), Port::new(2)];
+        let mut a = LinkAggregation::new(Mac::new(31), ports.clone(), LagConfig::default());
+        let passive = LagConfig { mode: LacpMode::Passive, ..LagConfig::default() };
+        let mut b = LinkAggregation::new(Mac::new(32), ports.clone(), passive);
+
+        // the passive side keeps quiet until it hears from the active side
+        assert!(b.update(0).is_empty());
+        for t in 0..10 {
+            for (port, pdu) in a.update(t) {
+                b.recv(port, &pdu, t);
+            }
+            for (port, pdu) in b.update(t) {
+                a.recv(port, &pdu, t);
+            }
+        }
+        assert_eq!(ports, a.get_active_ports());
+        assert_eq!(ports, b.get_active_ports());
+
+        // port 2 stops receiving
+        for t in 10..1000 {
+            for (port, pdu) in a.update(t) {
+                if port == Port::new(1) {
+                    b.recv(port, &pdu, t);
+                }
+            }
+            for (port, pdu) in b.update(t) {
+                if port == Port::new(1) {
+                    a.recv(port, &pdu, t);
+                }
+            }
+        }
+        assert_eq!(vec![Port::new(1)], a.get_active_ports());
+        assert_eq!(vec![Port::new(1)], b.get_active_ports());
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use crate::types::{Mac, Port, Res, Error};
+use crate::utils::{read_2bytes, read_6bytes};
+
+use super::EthernetFrame;
+
+// 01:80:C2:00:00:0E, nearest bridge group address
+pub const LLDP_MULTICAST: Mac = Mac { value: 0x0180_C200_000E };
+pub const ETHERTYPE_LLDP: u16 = 0x88CC;
+
+const TLV_END: u8 = 0;
+const TLV_CHASSIS_ID: u8 = 1;
+const TLV_PORT_ID: u8 = 2;
+const TLV_TTL: u8 = 3;
+const TLV_SYSTEM_NAME: u8 = 5;
+const CHASSIS_SUBTYPE_MAC: u8 = 4;
+const PORT_SUBTYPE_LOCAL: u8 = 7;
+const MAX_LLDPDU_LEN: usize = 1500;
+
+// type(7 bits) + length(9 bits) + value
+fn read_tlv_header(xs: &[u8], i: usize) -> (u8, usize) {
+    let x = (xs[i] as usize) << 8 | xs[i + 1] as usize;
+    ((x >> 9) as u8, x & 0x01FF)
+}
+
+fn write_tlv(xs: &mut Vec<u8>, ty: u8, value: &[u8]) {
+    let x = (ty as u16) << 9 | value.len() as u16;
+    xs.extend_from_slice(&x.to_be_bytes());
+    xs.extend_from_slice(value);
+}
+
+// TLVs up to and including the end TLV
+pub fn lldpdu_len(xs: &[u8]) -> Res<usize> {
+    let mut i = 0;
+    loop {
+        if i > MAX_LLDPDU_LEN {
+            return Err(Error::InvalidBytes { msg: "LLDPDU without end TLV".to_string() });
+        }
+        if xs.len() < i + 2 {
+            return Err(Error::NotEnoughBytes);
+        }
+        let (ty, len) = read_tlv_header(xs, i);
+        i += 2 + len;
+        if ty == TLV_END {
+            return if xs.len() < i { Err(Error::NotEnoughBytes) } else { Ok(i) };
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lldpdu {
+    pub chassis: Mac,
+    pub port: Port,
+    pub ttl: u16, // ticks. 0 withdraws the information
+    pub system_name: String,
+}
+
+impl Lldpdu {
+    pub fn decode(xs: &[u8]) -> Res<Lldpdu> {
+        let end = lldpdu_len(xs)?;
+        let (mut chassis, mut port, mut ttl, mut system_name) = (None, None, None, String::new());
+        let mut i = 0;
+        while i < end {
+            let (ty, len) = read_tlv_header(xs, i);
+            let value = Vec::from(&xs[i + 2..i + 2 + len]);
+            match ty {
+                TLV_CHASSIS_ID if len == 7 && value[0] == CHASSIS_SUBTYPE_MAC => {
+                    chassis = Some(Mac::new(read_6bytes(&value, 1)));
+                }
+                TLV_PORT_ID if len > 1 && value[0] == PORT_SUBTYPE_LOCAL => {
+                    port = String::from_utf8_lossy(&value[1..]).parse::<u32>().ok().map(Port::new);
+                }
+                TLV_TTL if len == 2 => ttl = Some(read_2bytes(&value, 0)),
+                TLV_SYSTEM_NAME => system_name = String::from_utf8_lossy(&value).to_string(),
+                _ => {} // optional TLVs we do not know
+            }
+            i += 2 + len;
+        }
+        match (chassis, port, ttl) {
+            (Some(chassis), Some(port), Some(ttl)) => Ok(Lldpdu { chassis, port, ttl, system_name }),
+            _ => Err(Error::InvalidBytes { msg: "LLDPDU misses a mandatory TLV".to_string() }),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut xs = vec![];
+        let mut chassis = vec![CHASSIS_SUBTYPE_MAC];
+        chassis.extend_from_slice(&self.chassis.value.to_be_bytes()[2..]);
+        write_tlv(&mut xs, TLV_CHASSIS_ID, &chassis);
+        let mut port = vec![PORT_SUBTYPE_LOCAL];
+        port.extend_from_slice(self.port.value.to_string().as_bytes());
+        write_tlv(&mut xs, TLV_PORT_ID, &port);
+        write_tlv(&mut xs, TLV_TTL, &self.ttl.to_be_bytes());
+        write_tlv(&mut xs, TLV_SYSTEM_NAME, self.system_name.as_bytes());
+        write_tlv(&mut xs, TLV_END, &[]);
+        xs
+    }
+
+    pub fn to_frame(&self, src: Mac) -> EthernetFrame {
+        EthernetFrame::new(LLDP_MULTICAST, src, ETHERTYPE_LLDP, self.encode())
+    }
+}
+
+// timers are counted in ticks
+#[derive(Debug, Clone, Copy)]
+pub struct LldpConfig {
+    pub tx_interval: usize,
+    pub hold: usize, // the advertised ttl is hold * tx_interval
+}
+
+impl Default for LldpConfig {
+    fn default() -> LldpConfig {
+        LldpConfig {
+            tx_interval: 1000,
+            hold: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LldpNeighbor {
+    pub local_port: Port,
+    pub chassis: Mac,
+    pub port: Port,
+    pub system_name: String,
+    pub t_expire: usize,
+}
+
+pub struct Lldp {
+    config: LldpConfig,
+    chassis: Mac,
+    system_name: String,
+    neighbors: HashMap<Port, LldpNeighbor>,
+    t_sent: Option<usize>,
+}
+
+impl Lldp {
+    pub fn new(chassis: Mac, system_name: &str, config: LldpConfig) -> Lldp {
+        Lldp {
+            config,
+            chassis,
+            system_name: system_name.to_string(),
+            neighbors: HashMap::new(),
+            t_sent: None,
+        }
+    }
+
+    pub fn recv(&mut self, local_port: Port, pdu: &Lldpdu, t: usize) {
+        if pdu.ttl == 0 {
+            self.neighbors.remove(&local_port);
+            return;
+        }
+        self.neighbors.insert(local_port, LldpNeighbor {
+            local_port,
+            chassis: pdu.chassis,
+            port: pdu.port,
+            system_name: pdu.system_name.clone(),
+            t_expire: t + pdu.ttl as usize,
+        });
+    }
+
+    // expire stale neighbors and advertise ourselves on every port when the interval has passed
+    pub fn update(&mut self, num_ports: usize, t: usize) -> Vec<(Port, Lldpdu)> {
+        self.neighbors.retain(|_, n| t < n.t_expire);
+        if self.t_sent.is_some_and(|t_sent| t < t_sent + self.config.tx_interval) {
+            return vec![];
+        }
+        self.t_sent = Some(t);
+        let ttl = (self.config.hold * self.config.tx_interval).min(u16::MAX as usize) as u16;
+        (0..num_ports)
+            .map(|port| {
+                let port = Port::new(port as u32);
+                (port, Lldpdu { chassis: self.chassis, port, ttl, system_name: self.system_name.clone() })
+            })
+            .collect()
+    }
+
+    pub fn get_neighbors(&self) -> Vec<&LldpNeighbor> {
+        let mut neighbors: Vec<&LldpNeighbor> = self.neighbors.values().collect();
+        neighbors.sort_by_key(|n| n.local_port.value);
+        neighbors
+    }
+
+    // "show lldp neighbors"
+    pub fn dump(&self, t: usize) -> String {
+        let mut s = String::new();
+        s += &format!("LLDP neighbors of {}({})\n", self.system_name, self.chassis.value);
+        s += "Local Port  Chassis            Port  System Name  Hold\n";
+        for n in self.get_neighbors() {
+            s += &format!("{:<10}  {:<17}  {:<4}  {:<11}  {}\n",
+                          n.local_port.value, n.chassis.value, n.port.value, n.system_name, n.t_expire.saturating_sub(t));
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lldpdu() {
+        let pdu = Lldpdu {
+            chassis: Mac::new(0x0012_3456_789A),
+            port: Port::new(12),
+            ttl: 4000,
+            system_name: "switch1".to_string(),
+        };
+        let xs = pdu.encode();
+        assert_eq!(Ok(xs.len()), lldpdu_len(&xs).map_err(|e| e.to_string()));
+        assert_eq!(pdu, Lldpdu::decode(&xs).unwrap());
+        assert!(matches!(lldpdu_len(&xs[..xs.len() - 1]), Err(Error::NotEnoughBytes)));
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use crate::types::{Mac, Port};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacEntryType {
+    Dynamic,
+    Static,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacEntry {
+    pub port: Port,
+    pub ty: MacEntryType,
+    pub t: usize, // last time the address was seen
+}
+
+// what to do with a new address when the table is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    Oldest,    // forget the least recently seen dynamic entry
+    NoLearning, // keep the table, the new address is flooded like a real CAM overflow
+}
+
+pub struct MacTable {
+    entries: HashMap<Mac, MacEntry>,
+    aging_time: Option<usize>,
+    capacity: Option<usize>,
+    policy: EvictionPolicy,
+}
+
+impl Default for MacTable {
+    fn default() -> MacTable {
+        MacTable::new()
+    }
+}
+
+impl MacTable {
+    pub fn new() -> MacTable {
+        MacTable {
+            entries: HashMap::new(),
+            aging_time: Some(30000),
+            capacity: None,
+            policy: EvictionPolicy::Oldest,
+        }
+    }
+
+    pub fn set_aging_time(&mut self, aging_time: Option<usize>) {
+        self.aging_time = aging_time;
+    }
+
+    pub fn set_capacity(&mut self, capacity: Option<usize>, policy: EvictionPolicy) {
+        self.capacity = capacity;
+        self.policy = policy;
+    }
+
+    fn is_expired(&self, e: &MacEntry, t: usize) -> bool {
+        match (e.ty, self.aging_time) {
+            (MacEntryType::Dynamic, Some(aging_time)) => t >= e.t + aging_time,
+            _ => false,
+        }
+    }
+
+    pub fn get(&self, mac: Mac, t: usize) -> Option<Port> {
+        self.entries
+            .get(&mac)
+            .filter(|e| !self.is_expired(e, t))
+            .map(|e| e.port)
+    }
+
+    pub fn learn(&mut self, mac: Mac, port: Port, t: usize) {
+        match self.entries.get_mut(&mac) {
+            Some(e) if e.ty == MacEntryType::Static => {}
+            Some(e) => {
+                // also moves the host to the new port
+                e.port = port;
+                e.t = t;
+            }
+            None => {
+                if self.capacity.is_some_and(|c| self.entries.len() >= c) {
+                    if self.policy == EvictionPolicy::NoLearning {
+                        return;
+                    }
+                    let oldest = self.entries
+                        .iter()
+                        .filter(|(_, e)| e.ty == MacEntryType::Dynamic)
+                        .min_by_key(|(_, e)| e.t)
+                        .map(|(mac, _)| *mac);
+                    match oldest {
+                        Some(oldest) => { self.entries.remove(&oldest); }
+                        None => return,
+                    }
+                }
+                self.entries.insert(mac, MacEntry { port, ty: MacEntryType::Dynamic, t });
+            }
+        }
+    }
+
+    pub fn add_static(&mut self, mac: Mac, port: Port) {
+        self.entries.insert(mac, MacEntry { port, ty: MacEntryType::Static, t: 0 });
+    }
+
+    pub fn age(&mut self, t: usize) {
+        let expired: Vec<Mac> = self.entries
+            .iter()
+            .filter(|(_, e)| self.is_expired(e, t))
+            .map(|(mac, _)| *mac)
+            .collect();
+        for mac in expired {
+            self.entries.remove(&mac);
+        }
+    }
+
+    pub fn flush_dynamic(&mut self) {
+        self.entries.retain(|_, e| e.ty == MacEntryType::Static);
+    }
+
+    // "show mac address-table"
+    pub fn dump(&self, t: usize) -> String {
+        let mut entries: Vec<(&Mac, &MacEntry)> = self.entries
+            .iter()
+            .filter(|(_, e)| !self.is_expired(e, t))
+            .collect();
+        entries.sort_by_key(|(mac, e)| (e.port.value, mac.value));
+        let mut s = String::new();
+        s += "          Mac Address Table\n";
+        s += "-------------------------------------------\n";
+        s += "Mac Address        Type      Port   Age\n";
+        s += "-----------------  --------  -----  ------\n";
+        for (mac, e) in &entries {
+            let (ty, age) = match e.ty {
+                MacEntryType::Dynamic => ("DYNAMIC", format!("{}", t - e.t)),
+                MacEntryType::Static => ("STATIC", "-".to_string()),
+            };
+            s += &format!("{:<17}  {:<8}  {:<5}  {}\n", mac.value, ty, e.port.value, age);
+        }
+        s += &format!("Total Mac Addresses: {}\n", entries.len());
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mac_table_aging() {
+        let mut table = MacTable::new();
+        table.set_aging_time(Some(100));
+        table.learn(Mac::new(1), Port::new(0), 0);
+        table.add_static(Mac::new(2), Port::new(1));
+        assert_eq!(Some(Port::new(0)), table.get(Mac::new(1), 99));
+        assert_eq!(None, table.get(Mac::new(1), 100));
+
+        // the host moved to another port
+        table.learn(Mac::new(1), Port::new(2), 50);
+        assert_eq!(Some(Port::new(2)), table.get(Mac::new(1), 120));
+
+        // static entries neither age nor move
+        table.learn(Mac::new(2), Port::new(2), 60);
+        table.age(1000);
+        assert_eq!(1, table.entries.len());
+        assert_eq!(Some(Port::new(1)), table.get(Mac::new(2), 1000));
+        let dump = table.dump(1000);
+        assert!(dump.contains("2                  STATIC    1      -\n"));
+        assert!(dump.contains("Total Mac Addresses: 1\n"));
+    }
+
+    #[test]
+    fn test_mac_table_capacity() {
+        let mut table = MacTable::new();
+        table.set_capacity(Some(2), EvictionPolicy::Oldest);
+        table.learn(Mac::new(1), Port::new(0), 0);
+        table.learn(Mac::new(2), Port::new(1), 1);
+        table.learn(Mac::new(3), Port::new(2), 2);
+        assert_eq!(2, table.entries.len());
+        assert_eq!(None, table.get(Mac::new(1), 2));
+        assert_eq!(Some(Port::new(2)), table.get(Mac::new(3), 2));
+
+        table.set_capacity(Some(2), EvictionPolicy::NoLearning);
+        table.learn(Mac::new(4), Port::new(0), 3);
+        assert_eq!(None, table.get(Mac::new(4), 3));
+
+        table.flush_dynamic();
+        assert!(table.entries.is_empty());
+    }
+}
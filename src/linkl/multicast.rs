@@ -0,0 +1,99 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::types::{Mac, Port};
+use crate::utils::read_4bytes;
+
+use super::EthernetFrame;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MulticastMode {
+    Flood,
+    Snooping, // IGMP snooping
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IgmpMembership {
+    Join(Mac),
+    Leave(Mac),
+}
+
+// 01:00:5E + lower 23 bits of the group address
+pub fn ipv4_multicast_mac(group: u32) -> Mac {
+    Mac::new(0x0100_5E00_0000 | (group & 0x7F_FFFF) as u64)
+}
+
+// peek into IPv4 for IGMPv2 membership reports and leave messages
+fn parse_igmp(frame: &EthernetFrame) -> Option<IgmpMembership> {
+    let xs = &frame.payload;
+    if frame.ethertype != 0x0800 || xs.len() < 20 || xs[9] != 2 {
+        return None;
+    }
+    let ihl = (xs[0] & 0x0F) as usize * 4;
+    if xs.len() < ihl + 8 {
+        return None;
+    }
+    let group = ipv4_multicast_mac(read_4bytes(xs, ihl + 4));
+    match xs[ihl] {
+        0x16 => Some(IgmpMembership::Join(group)),
+        0x17 => Some(IgmpMembership::Leave(group)),
+        _ => None,
+    }
+}
+
+pub struct MulticastTable {
+    groups: HashMap<Mac, HashSet<Port>>,
+}
+
+impl Default for MulticastTable {
+    fn default() -> MulticastTable {
+        MulticastTable::new()
+    }
+}
+
+impl MulticastTable {
+    pub fn new() -> MulticastTable {
+        MulticastTable { groups: HashMap::new() }
+    }
+
+    pub fn snoop(&mut self, frame: &EthernetFrame, ingress: Port) {
+        match parse_igmp(frame) {
+            Some(IgmpMembership::Join(group)) => {
+                self.groups.entry(group).or_default().insert(ingress);
+            }
+            Some(IgmpMembership::Leave(group)) => {
+                if let Some(ports) = self.groups.get_mut(&group) {
+                    ports.remove(&ingress);
+                    if ports.is_empty() {
+                        self.groups.remove(&group);
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+
+    // member ports of the group. None for unregistered groups, which are flooded
+    pub fn get(&self, group: Mac) -> Option<Vec<Port>> {
+        // 224.0.0.x is link local and always flooded
+        if group.is_broadcast() || group.value & 0xFFFF_FFFF_FF00 == 0x0100_5E00_0000 {
+            return None;
+        }
+        self.groups.get(&group).map(|ports| {
+            let mut ports: Vec<Port> = ports.iter().copied().collect();
+            ports.sort_by_key(|p| p.value);
+            ports
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multicast_mac() {
+        assert!(ipv4_multicast_mac(0xEF01_0101).is_multicast());
+        assert_eq!(Mac::new(0x0100_5E01_0101), ipv4_multicast_mac(0xEF81_0101));
+        assert!(!Mac::new(0x0000_0000_0001).is_multicast());
+    }
+}
@@ -0,0 +1,81 @@
+use crate::types::{Mac, Res, Error};
+use crate::utils::read_2bytes;
+
+use super::EthernetFrame;
+
+// 01:80:C2:00:00:01, MAC control group address
+pub const MAC_CONTROL_MULTICAST: Mac = Mac { value: 0x0180_C200_0001 };
+pub const ETHERTYPE_MAC_CONTROL: u16 = 0x8808;
+pub const PAUSE_LEN: usize = 4;
+// a quantum is 512 bit times. the wire carries a byte per tick
+pub const PAUSE_QUANTUM_TICKS: usize = 512 / 8;
+
+const OPCODE_PAUSE: u16 = 0x0001;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PauseFrame {
+    pub quanta: u16, // 0 resumes the transmission at once
+}
+
+impl PauseFrame {
+    pub fn decode(xs: &[u8]) -> Res<PauseFrame> {
+        if xs.len() < PAUSE_LEN {
+            return Err(Error::NotEnoughBytes);
+        }
+        let xs = Vec::from(xs);
+        let opcode = read_2bytes(&xs, 0);
+        if opcode != OPCODE_PAUSE {
+            return Err(Error::InvalidBytes { msg: format!("unsupported MAC control opcode: {}", opcode) });
+        }
+        Ok(PauseFrame { quanta: read_2bytes(&xs, 2) })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut xs = OPCODE_PAUSE.to_be_bytes().to_vec();
+        xs.extend_from_slice(&self.quanta.to_be_bytes());
+        xs
+    }
+
+    pub fn to_frame(&self, src: Mac) -> EthernetFrame {
+        EthernetFrame::new(MAC_CONTROL_MULTICAST, src, ETHERTYPE_MAC_CONTROL, self.encode())
+    }
+
+    pub fn get_ticks(&self) -> usize {
+        self.quanta as usize * PAUSE_QUANTUM_TICKS
+    }
+}
+
+// watermarks on the bytes queued for transmission. above the high watermark the ports
+// delivering frames are paused, below the low watermark they are resumed
+#[derive(Debug, Clone, Copy)]
+pub struct FlowControl {
+    pub high_water: usize,
+    pub low_water: usize,
+    pub quanta: u16,
+}
+
+impl Default for FlowControl {
+    fn default() -> FlowControl {
+        FlowControl {
+            high_water: 300,
+            low_water: 100,
+            quanta: 0xFFFF,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pause_frame() {
+        let pause = PauseFrame { quanta: 0x1234 };
+        let frame = pause.to_frame(Mac::new(30));
+        let xs = EthernetFrame::encode(&frame);
+        let frame2 = EthernetFrame::decode(&xs).unwrap();
+        assert_eq!(frame, frame2);
+        assert_eq!(pause, PauseFrame::decode(&frame2.payload).unwrap());
+        assert_eq!(0x1234 * 64, pause.get_ticks());
+    }
+}
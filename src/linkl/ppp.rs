@@ -0,0 +1,443 @@
+use std::fmt;
+
+use crate::types::{Res, Error};
+use crate::utils::{read_2bytes, read_4bytes};
+
+// HDLC-like framing of RFC 1662
+const FLAG: u8 = 0x7E;
+const ESCAPE: u8 = 0x7D;
+const ADDRESS: u8 = 0xFF;
+const CONTROL: u8 = 0x03;
+const FCS_GOOD: u16 = 0xF0B8;
+
+pub const PPP_IPV4: u16 = 0x0021;
+pub const PPP_LCP: u16 = 0xC021;
+
+// FCS-16. the register before the one's complement
+fn fcs16_update(fcs: u16, xs: &[u8]) -> u16 {
+    xs.iter().fold(fcs, |fcs, x| {
+        let mut fcs = fcs ^ *x as u16;
+        for _ in 0..8 {
+            fcs = if fcs & 1 == 1 { (fcs >> 1) ^ 0x8408 } else { fcs >> 1 };
+        }
+        fcs
+    })
+}
+
+pub fn fcs16(xs: &[u8]) -> u16 {
+    !fcs16_update(0xFFFF, xs)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PppFrame {
+    pub protocol: u16,
+    pub info: Vec<u8>,
+}
+
+impl PppFrame {
+    pub fn new(protocol: u16, info: Vec<u8>) -> PppFrame {
+        PppFrame { protocol, info }
+    }
+
+    // the bytes between the flags, after the escapes were removed
+    pub fn decode(xs: &[u8]) -> Res<PppFrame> {
+        if xs.len() < 6 {
+            return Err(Error::InvalidBytes { msg: format!("PPP frame too short: {} bytes", xs.len()) });
+        }
+        if fcs16_update(0xFFFF, xs) != FCS_GOOD {
+            return Err(Error::InvalidBytes { msg: "PPP FCS mismatch".to_string() });
+        }
+        if xs[0] != ADDRESS || xs[1] != CONTROL {
+            return Err(Error::InvalidBytes { msg: format!("invalid HDLC address/control: {:02X} {:02X}", xs[0], xs[1]) });
+        }
+        let xs = Vec::from(xs);
+        let protocol = read_2bytes(&xs, 2);
+        let info = xs[4..xs.len() - 2].to_vec();
+        Ok(PppFrame { protocol, info })
+    }
+
+    // flag, stuffed address + control + protocol + information + FCS, flag
+    pub fn encode(&self) -> Vec<u8> {
+        let mut xs = vec![ADDRESS, CONTROL];
+        xs.extend_from_slice(&self.protocol.to_be_bytes());
+        xs.extend_from_slice(&self.info);
+        let fcs = fcs16(&xs);
+        xs.extend_from_slice(&fcs.to_le_bytes());
+
+        let mut ys = vec![FLAG];
+        for x in xs {
+            if x == FLAG || x == ESCAPE {
+                ys.push(ESCAPE);
+                ys.push(x ^ 0x20);
+            } else {
+                ys.push(x);
+            }
+        }
+        ys.push(FLAG);
+        ys
+    }
+}
+
+impl fmt::Display for PppFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PPP(protocol=0x{:04X}, {} bytes)", self.protocol, self.info.len())
+    }
+}
+
+// splits the byte stream at the flags and removes the escapes
+#[derive(Default)]
+pub struct HdlcDeframer {
+    buf: Vec<u8>,
+    escaped: bool,
+}
+
+impl HdlcDeframer {
+    pub fn push(&mut self, x: u8) -> Option<Res<PppFrame>> {
+        match x {
+            FLAG => {
+                self.escaped = false;
+                if self.buf.is_empty() {
+                    // back to back flags
+                    return None;
+                }
+                let xs = std::mem::take(&mut self.buf);
+                Some(PppFrame::decode(&xs))
+            }
+            ESCAPE => {
+                self.escaped = true;
+                None
+            }
+            _ => {
+                self.buf.push(if self.escaped { x ^ 0x20 } else { x });
+                self.escaped = false;
+                None
+            }
+        }
+    }
+}
+
+const LCP_CONFIGURE_REQUEST: u8 = 1;
+const LCP_CONFIGURE_ACK: u8 = 2;
+const LCP_CONFIGURE_NAK: u8 = 3;
+const LCP_CONFIGURE_REJECT: u8 = 4;
+const LCP_ECHO_REQUEST: u8 = 9;
+const LCP_ECHO_REPLY: u8 = 10;
+
+const OPTION_MRU: u8 = 1;
+const OPTION_MAGIC_NUMBER: u8 = 5;
+const MIN_MRU: u16 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LcpOption {
+    Mru(u16),
+    MagicNumber(u32),
+    Unknown(u8),
+}
+
+impl LcpOption {
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            LcpOption::Mru(mru) => {
+                let mut xs = vec![OPTION_MRU, 4];
+                xs.extend_from_slice(&mru.to_be_bytes());
+                xs
+            }
+            LcpOption::MagicNumber(magic) => {
+                let mut xs = vec![OPTION_MAGIC_NUMBER, 6];
+                xs.extend_from_slice(&magic.to_be_bytes());
+                xs
+            }
+            LcpOption::Unknown(ty) => vec![*ty, 2],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LcpPacket {
+    pub code: u8,
+    pub id: u8,
+    pub options: Vec<LcpOption>, // configure packets
+    pub data: Vec<u8>,           // other packets
+}
+
+impl LcpPacket {
+    fn new(code: u8, id: u8, options: Vec<LcpOption>) -> LcpPacket {
+        LcpPacket { code, id, options, data: vec![] }
+    }
+
+    fn is_configure(code: u8) -> bool {
+        (LCP_CONFIGURE_REQUEST..=LCP_CONFIGURE_REJECT).contains(&code)
+    }
+
+    pub fn decode(xs: &[u8]) -> Res<LcpPacket> {
+        if xs.len() < 4 {
+            return Err(Error::NotEnoughBytes);
+        }
+        let xs = Vec::from(xs);
+        let (code, id, len) = (xs[0], xs[1], read_2bytes(&xs, 2) as usize);
+        if len < 4 || xs.len() < len {
+            return Err(Error::InvalidBytes { msg: format!("invalid LCP length: {}", len) });
+        }
+        let body = &xs[4..len];
+        if !LcpPacket::is_configure(code) {
+            return Ok(LcpPacket { code, id, options: vec![], data: body.to_vec() });
+        }
+        let mut options = vec![];
+        let mut i = 0;
+        while i < body.len() {
+            if body.len() < i + 2 || body[i + 1] < 2 || body.len() < i + body[i + 1] as usize {
+                return Err(Error::InvalidBytes { msg: "invalid LCP option".to_string() });
+            }
+            let (ty, olen) = (body[i], body[i + 1] as usize);
+            let value = body[i + 2..i + olen].to_vec();
+            let option = match (ty, olen) {
+                (OPTION_MRU, 4) => LcpOption::Mru(read_2bytes(&value, 0)),
+                (OPTION_MAGIC_NUMBER, 6) => LcpOption::MagicNumber(read_4bytes(&value, 0)),
+                _ => LcpOption::Unknown(ty),
+            };
+            options.push(option);
+            i += olen;
+        }
+        Ok(LcpPacket { code, id, options, data: vec![] })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let body: Vec<u8> = if LcpPacket::is_configure(self.code) {
+            self.options.iter().flat_map(|o| o.encode()).collect()
+        } else {
+            self.data.clone()
+        };
+        let mut xs = vec![self.code, self.id];
+        xs.extend_from_slice(&(4 + body.len() as u16).to_be_bytes());
+        xs.extend_from_slice(&body);
+        xs
+    }
+
+    pub fn to_frame(&self) -> PppFrame {
+        PppFrame::new(PPP_LCP, self.encode())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PppConfig {
+    pub mru: u16,
+    pub restart_ticks: usize, // a Configure-Request without answer is sent again
+}
+
+impl Default for PppConfig {
+    fn default() -> PppConfig {
+        PppConfig {
+            mru: 1500,
+            restart_ticks: 300,
+        }
+    }
+}
+
+// the states of RFC 1661 while the link is up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LcpState {
+    ReqSent,
+    AckRcvd,
+    AckSent,
+    Opened,
+}
+
+// LCP negotiation and the framing of a serial port
+pub struct PppLink {
+    config: PppConfig,
+    magic: u32,
+    state: LcpState,
+    id: u8,
+    t_sent: Option<usize>,
+    peer_mru: u16,
+    deframer: HdlcDeframer,
+    drops: usize,
+}
+
+impl PppLink {
+    pub fn new(magic: u32, config: PppConfig) -> PppLink {
+        PppLink {
+            config,
+            magic,
+            state: LcpState::ReqSent,
+            id: 0,
+            t_sent: None,
+            peer_mru: 1500,
+            deframer: HdlcDeframer::default(),
+            drops: 0,
+        }
+    }
+
+    pub fn get_state(&self) -> LcpState {
+        self.state
+    }
+
+    pub fn is_opened(&self) -> bool {
+        self.state == LcpState::Opened
+    }
+
+    // the largest information field the peer accepts
+    pub fn get_peer_mru(&self) -> u16 {
+        self.peer_mru
+    }
+
+    // frames not sent because LCP was not opened or they exceeded the peer MRU
+    pub fn get_drop_count(&self) -> usize {
+        self.drops
+    }
+
+    pub fn deframe(&mut self, x: u8) -> Option<Res<PppFrame>> {
+        self.deframer.push(x)
+    }
+
+    // frame a datagram of the network layer. None if it cannot be sent now
+    pub fn frame(&mut self, protocol: u16, info: &[u8]) -> Option<PppFrame> {
+        if !self.is_opened() || info.len() > self.peer_mru as usize {
+            self.drops += 1;
+            return None;
+        }
+        Some(PppFrame::new(protocol, info.to_vec()))
+    }
+
+    fn configure_request(&mut self, t: usize) -> LcpPacket {
+        self.id = self.id.wrapping_add(1);
+        self.t_sent = Some(t);
+        let options = vec![LcpOption::Mru(self.config.mru), LcpOption::MagicNumber(self.magic)];
+        LcpPacket::new(LCP_CONFIGURE_REQUEST, self.id, options)
+    }
+
+    // send the first Configure-Request, and again when the restart timer expires
+    pub fn update(&mut self, t: usize) -> Vec<LcpPacket> {
+        if self.state == LcpState::Opened {
+            return vec![];
+        }
+        if self.t_sent.is_some_and(|t_sent| t < t_sent + self.config.restart_ticks) {
+            return vec![];
+        }
+        if self.state == LcpState::AckRcvd {
+            self.state = LcpState::ReqSent;
+        }
+        vec![self.configure_request(t)]
+    }
+
+    pub fn recv(&mut self, packet: &LcpPacket, t: usize) -> Vec<LcpPacket> {
+        match packet.code {
+            LCP_CONFIGURE_REQUEST => self.recv_configure_request(packet, t),
+            LCP_CONFIGURE_ACK if packet.id == self.id => {
+                match self.state {
+                    LcpState::ReqSent => self.state = LcpState::AckRcvd,
+                    LcpState::AckSent => self.state = LcpState::Opened,
+                    _ => {}
+                }
+                vec![]
+            }
+            LCP_CONFIGURE_NAK | LCP_CONFIGURE_REJECT if packet.id == self.id => {
+                for option in &packet.options {
+                    match (packet.code, option) {
+                        (LCP_CONFIGURE_NAK, LcpOption::Mru(mru)) => self.config.mru = *mru,
+                        (LCP_CONFIGURE_NAK, LcpOption::MagicNumber(magic)) => self.magic = *magic,
+                        _ => {}
+                    }
+                }
+                vec![self.configure_request(t)]
+            }
+            LCP_ECHO_REQUEST if self.is_opened() => {
+                let mut reply = packet.clone();
+                reply.code = LCP_ECHO_REPLY;
+                if reply.data.len() >= 4 {
+                    reply.data[0..4].copy_from_slice(&self.magic.to_be_bytes());
+                }
+                vec![reply]
+            }
+            _ => vec![], // stale answers and unsupported codes
+        }
+    }
+
+    fn recv_configure_request(&mut self, packet: &LcpPacket, t: usize) -> Vec<LcpPacket> {
+        let rejected: Vec<LcpOption> = packet.options
+            .iter()
+            .copied()
+            .filter(|o| matches!(o, LcpOption::Unknown(_)))
+            .collect();
+        let naked: Vec<LcpOption> = packet.options
+            .iter()
+            .filter_map(|o| match o {
+                LcpOption::Mru(mru) if *mru < MIN_MRU => Some(LcpOption::Mru(MIN_MRU)),
+                // the peer echoes our own magic number. ask for another one
+                LcpOption::MagicNumber(magic) if *magic == self.magic => Some(LcpOption::MagicNumber(!magic)),
+                _ => None,
+            })
+            .collect();
+
+        let mut packets = vec![];
+        if self.state == LcpState::Opened {
+            // the peer restarted the negotiation
+            self.state = LcpState::ReqSent;
+            packets.push(self.configure_request(t));
+        }
+        if !rejected.is_empty() {
+            packets.push(LcpPacket::new(LCP_CONFIGURE_REJECT, packet.id, rejected));
+            return packets;
+        }
+        if !naked.is_empty() {
+            packets.push(LcpPacket::new(LCP_CONFIGURE_NAK, packet.id, naked));
+            return packets;
+        }
+        for option in &packet.options {
+            if let LcpOption::Mru(mru) = option {
+                self.peer_mru = *mru;
+            }
+        }
+        self.state = match self.state {
+            LcpState::AckRcvd => LcpState::Opened,
+            _ => LcpState::AckSent,
+        };
+        packets.push(LcpPacket::new(LCP_CONFIGURE_ACK, packet.id, packet.options.clone()));
+        packets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ppp_frame() {
+        // flags and escapes in the information field are stuffed
+        let frame = PppFrame::new(PPP_IPV4, vec![0x01, FLAG, 0x02, ESCAPE, 0x03]);
+        let xs = frame.encode();
+        assert_eq!(FLAG, xs[0]);
+        assert_eq!(FLAG, xs[xs.len() - 1]);
+        assert!(xs[1..xs.len() - 1].iter().all(|x| *x != FLAG));
+
+        let mut deframer = HdlcDeframer::default();
+        let frames: Vec<PppFrame> = xs.iter().filter_map(|x| deframer.push(*x)).map(|r| r.unwrap()).collect();
+        assert_eq!(vec![frame], frames);
+
+        let mut ys = xs.clone();
+        ys[5] ^= 0x01;
+        let mut deframer = HdlcDeframer::default();
+        let results: Vec<Res<PppFrame>> = ys.iter().filter_map(|x| deframer.push(*x)).collect();
+        assert!(matches!(results[..], [Err(Error::InvalidBytes { .. })]));
+    }
+
+    #[test]
+    fn test_lcp_negotiation() {
+        let mut a = PppLink::new(0x1111, PppConfig::default());
+        let mut b = PppLink::new(0x2222, PppConfig { mru: 296, ..PppConfig::default() });
+        let req_a = a.update(0);
+        let req_b = b.update(0);
+        assert_eq!(req_a, vec![LcpPacket::decode(&req_a[0].encode()).unwrap()]);
+
+        let acks_b = b.recv(&req_a[0], 1);
+        let acks_a = a.recv(&req_b[0], 1);
+        assert_eq!(LcpState::AckSent, a.get_state());
+        a.recv(&acks_b[0], 2);
+        b.recv(&acks_a[0], 2);
+        assert!(a.is_opened() && b.is_opened());
+        assert_eq!(296, a.get_peer_mru());
+        assert!(a.frame(PPP_IPV4, &[0; 300]).is_none());
+        assert!(a.frame(PPP_IPV4, &[0; 296]).is_some());
+        assert_eq!(1, a.get_drop_count());
+    }
+}
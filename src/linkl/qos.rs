@@ -0,0 +1,142 @@
+use std::collections::VecDeque;
+
+use crate::types::{Error, Port, Res};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scheduling {
+    StrictPriority,
+    WeightedRoundRobin(Vec<usize>), // frames served per round, from the lowest queue
+}
+
+#[derive(Debug, Clone)]
+pub struct QosConfig {
+    pub num_queues: usize,
+    pub scheduling: Scheduling,
+    pub queue_size: Option<usize>, // bytes per queue
+}
+
+impl Default for QosConfig {
+    fn default() -> QosConfig {
+        QosConfig {
+            num_queues: 4,
+            scheduling: Scheduling::StrictPriority,
+            queue_size: None,
+        }
+    }
+}
+
+// egress priority queues. the device has a single transmitter, so the queues are shared by its ports.
+// a higher queue has a higher priority
+pub struct EgressQueues {
+    config: QosConfig,
+    queues: Vec<VecDeque<(Port, Vec<u8>)>>,
+    lens: Vec<usize>,
+    drops: Vec<usize>,
+    current: usize,
+    credits: usize,
+}
+
+impl EgressQueues {
+    pub fn new(config: QosConfig) -> Res<EgressQueues> {
+        let n = config.num_queues;
+        if n == 0 {
+            return Err(Error::InvalidConfig { msg: "no egress queues".to_string() });
+        }
+        Ok(EgressQueues {
+            config,
+            queues: (0..n).map(|_| VecDeque::new()).collect(),
+            lens: vec![0; n],
+            drops: vec![0; n],
+            current: 0,
+            credits: 0,
+        })
+    }
+
+    // 802.1p priorities are spread evenly over the queues
+    pub fn queue_of(&self, pcp: u8) -> usize {
+        (pcp as usize & 0x07) * self.config.num_queues / 8
+    }
+
+    // false if the queue is full and the frame was dropped
+    pub fn push(&mut self, pcp: u8, port: Port, bytes: Vec<u8>) -> bool {
+        let q = self.queue_of(pcp);
+        if self.config.queue_size.is_some_and(|size| self.lens[q] + bytes.len() > size) {
+            self.drops[q] += 1;
+            return false;
+        }
+        self.lens[q] += bytes.len();
+        self.queues[q].push_back((port, bytes));
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<(Port, Vec<u8>)> {
+        if self.queues.iter().all(|q| q.is_empty()) {
+            return None;
+        }
+        let q = match &self.config.scheduling {
+            Scheduling::StrictPriority => (0..self.queues.len()).rev().find(|q| !self.queues[*q].is_empty())?,
+            Scheduling::WeightedRoundRobin(weights) => {
+                while self.credits == 0 || self.queues[self.current].is_empty() {
+                    self.current = (self.current + 1) % self.queues.len();
+                    self.credits = weights.get(self.current).copied().unwrap_or(1).max(1);
+                }
+                self.credits -= 1;
+                self.current
+            }
+        };
+        let (port, bytes) = self.queues[q].pop_front()?;
+        self.lens[q] -= bytes.len();
+        Some((port, bytes))
+    }
+
+    // bytes waiting in all queues
+    pub fn get_len(&self) -> usize {
+        self.lens.iter().sum()
+    }
+
+    // frames dropped by the queue
+    pub fn get_drop_count(&self, queue: usize) -> usize {
+        self.drops[queue]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pop_queues(queues: &mut EgressQueues) -> Vec<u8> {
+        let mut xs = vec![];
+        while let Some((_, bytes)) = queues.pop() {
+            xs.push(bytes[0]);
+        }
+        xs
+    }
+
+    #[test]
+    fn test_egress_queues() {
+        let port = Port::new(0);
+        assert!(EgressQueues::new(QosConfig { num_queues: 0, ..QosConfig::default() }).is_err());
+        let mut queues = EgressQueues::new(QosConfig { num_queues: 2, ..QosConfig::default() }).unwrap();
+        assert_eq!(0, queues.queue_of(3));
+        assert_eq!(1, queues.queue_of(5));
+        for i in 0..3 {
+            queues.push(0, port, vec![i]);
+            queues.push(7, port, vec![10 + i]);
+        }
+        assert_eq!(vec![10, 11, 12, 0, 1, 2], pop_queues(&mut queues));
+
+        let config = QosConfig {
+            num_queues: 2,
+            scheduling: Scheduling::WeightedRoundRobin(vec![1, 2]),
+            queue_size: Some(3),
+        };
+        let mut queues = EgressQueues::new(config).unwrap();
+        for i in 0..4 {
+            queues.push(0, port, vec![i]);
+            queues.push(7, port, vec![10 + i]);
+        }
+        assert_eq!(6, queues.get_len());
+        assert_eq!(1, queues.get_drop_count(0));
+        assert_eq!(vec![10, 11, 0, 12, 1, 2], pop_queues(&mut queues));
+    }
+}
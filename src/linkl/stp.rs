@@ -0,0 +1,516 @@
+use crate::types::{Mac, Port, Res, Error, UpdateContext};
+use crate::utils::{read_2bytes, read_4bytes};
+
+use super::{BaseEthernetDevice, EthernetFrame};
+
+// 01:80:C2:00:00:00, bridge group address
+pub const STP_MULTICAST: Mac = Mac { value: 0x0180_C200_0000 };
+
+const LLC_HEADER: [u8; 3] = [0x42, 0x42, 0x03];
+const FLAG_TC: u8 = 0x01;
+const FLAG_TCA: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BridgeId { pub value: u64 } // priority(2 bytes) + mac(6 bytes)
+
+impl BridgeId {
+    pub fn new(priority: u16, mac: Mac) -> BridgeId {
+        BridgeId { value: (priority as u64) << 48 | (mac.value & 0xFFFF_FFFF_FFFF) }
+    }
+
+    pub fn priority(&self) -> u16 {
+        (self.value >> 48) as u16
+    }
+
+    pub fn mac(&self) -> Mac {
+        Mac::new(self.value & 0xFFFF_FFFF_FFFF)
+    }
+}
+
+impl std::fmt::Display for BridgeId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}.{}", self.priority(), self.mac().value)
+    }
+}
+
+// timers are counted in ticks instead of 1/256 seconds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigBpdu { // total = 35 bytes
+    pub flags: u8,
+    pub root_id: BridgeId,
+    pub root_path_cost: u32,
+    pub bridge_id: BridgeId,
+    pub port_id: u16,
+    pub message_age: u16,
+    pub max_age: u16,
+    pub hello_time: u16,
+    pub forward_delay: u16,
+}
+
+impl ConfigBpdu {
+    // priority vector. smaller is better
+    fn vector(&self) -> (BridgeId, u32, BridgeId, u16) {
+        (self.root_id, self.root_path_cost, self.bridge_id, self.port_id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bpdu {
+    Config(ConfigBpdu),
+    Tcn,
+}
+
+impl Bpdu {
+    pub fn decode(xs: &[u8]) -> Res<Bpdu> {
+        if xs.len() < 3 + 4 {
+            return Err(Error::NotEnoughBytes);
+        }
+        if xs[0..3] != LLC_HEADER {
+            return Err(Error::InvalidBytes { msg: "bad LLC header for BPDU".to_string() });
+        }
+        let xs = Vec::from(&xs[3..]);
+        if read_2bytes(&xs, 0) != 0 {
+            return Err(Error::InvalidBytes { msg: "unsupported BPDU protocol".to_string() });
+        }
+        match xs[3] {
+            0x00 => {
+                if xs.len() < 35 {
+                    return Err(Error::NotEnoughBytes);
+                }
+                let read_8bytes = |i: usize| (read_4bytes(&xs, i) as u64) << 32 | read_4bytes(&xs, i + 4) as u64;
+                Ok(Bpdu::Config(ConfigBpdu {
+                    flags: xs[4],
+                    root_id: BridgeId { value: read_8bytes(5) },
+                    root_path_cost: read_4bytes(&xs, 13),
+                    bridge_id: BridgeId { value: read_8bytes(17) },
+                    port_id: read_2bytes(&xs, 25),
+                    message_age: read_2bytes(&xs, 27),
+                    max_age: read_2bytes(&xs, 29),
+                    hello_time: read_2bytes(&xs, 31),
+                    forward_delay: read_2bytes(&xs, 33),
+                }))
+            }
+            0x80 => Ok(Bpdu::Tcn),
+            ty => Err(Error::InvalidBytes { msg: format!("unsupported BPDU type: {}", ty) }),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut xs = LLC_HEADER.to_vec();
+        xs.extend_from_slice(&[0x00, 0x00, 0x00]); // protocol id, version
+        match self {
+            Bpdu::Config(c) => {
+                xs.push(0x00);
+                xs.push(c.flags);
+                xs.extend_from_slice(&c.root_id.value.to_be_bytes());
+                xs.extend_from_slice(&c.root_path_cost.to_be_bytes());
+                xs.extend_from_slice(&c.bridge_id.value.to_be_bytes());
+                xs.extend_from_slice(&c.port_id.to_be_bytes());
+                xs.extend_from_slice(&c.message_age.to_be_bytes());
+                xs.extend_from_slice(&c.max_age.to_be_bytes());
+                xs.extend_from_slice(&c.hello_time.to_be_bytes());
+                xs.extend_from_slice(&c.forward_delay.to_be_bytes());
+            }
+            Bpdu::Tcn => xs.push(0x80),
+        }
+        xs
+    }
+
+    pub fn to_frame(&self, src: Mac) -> EthernetFrame {
+        let payload = self.encode();
+        // 802.3 frame. the type field holds the length
+        EthernetFrame::new(STP_MULTICAST, src, payload.len() as u16, payload)
+    }
+}
+
+impl std::fmt::Display for Bpdu {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Bpdu::Config(c) => write!(f, "BPDU(root:{}, cost:{}, bridge:{}, port:{:0>4X}, age:{}, flags:{:0>2X})",
+                                      c.root_id, c.root_path_cost, c.bridge_id, c.port_id, c.message_age, c.flags),
+            Bpdu::Tcn => write!(f, "BPDU(TCN)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortRole {
+    Root,
+    Designated,
+    Alternate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortState {
+    Blocking,
+    Listening,
+    Learning,
+    Forwarding,
+}
+
+impl PortState {
+    pub fn is_learning(&self) -> bool {
+        matches!(self, PortState::Learning | PortState::Forwarding)
+    }
+
+    pub fn is_forwarding(&self) -> bool {
+        matches!(self, PortState::Forwarding)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StpConfig {
+    pub priority: u16,
+    pub hello_time: u16,
+    pub max_age: u16,
+    pub forward_delay: u16,
+    pub path_cost: u32,
+}
+
+impl Default for StpConfig {
+    // one byte moves one hop per tick, so a BPDU frame takes 60 ticks to send
+    fn default() -> StpConfig {
+        StpConfig {
+            priority: 0x8000,
+            hello_time: 400,
+            max_age: 2000,
+            forward_delay: 600,
+            path_cost: 19,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StpLog {
+    pub t: usize,
+    pub port: Port,
+    pub role: PortRole,
+    pub state: PortState,
+}
+
+struct StpPort {
+    role: PortRole,
+    state: PortState,
+    since: usize,
+    info: Option<(ConfigBpdu, usize)>, // received BPDU and its time
+    tca: bool,
+}
+
+pub struct Stp {
+    config: StpConfig,
+    bridge_id: BridgeId,
+    root_id: BridgeId,
+    root_path_cost: u32,
+    root_port: Option<Port>,
+    ports: Vec<StpPort>,
+    last_hello: Option<usize>,
+    last_tcn: Option<usize>,
+    tcn_pending: bool,
+    tc_until: usize,
+    log: Vec<StpLog>,
+    invalid_bpdus: usize,
+}
+
+impl Stp {
+    pub fn new(mac: Mac, num_ports: usize, config: StpConfig) -> Stp {
+        let bridge_id = BridgeId::new(config.priority, mac);
+        let ports = (0..num_ports).map(|_| StpPort {
+            role: PortRole::Designated,
+            state: PortState::Blocking,
+            since: 0,
+            info: None,
+            tca: false,
+        }).collect();
+        Stp {
+            config,
+            bridge_id,
+            root_id: bridge_id,
+            root_path_cost: 0,
+            root_port: None,
+            ports,
+            last_hello: None,
+            last_tcn: None,
+            tcn_pending: false,
+            tc_until: 0,
+            log: Vec::new(),
+            invalid_bpdus: 0,
+        }
+    }
+
+    pub fn init(&mut self, base: &mut BaseEthernetDevice) {
+        for idx in 0..self.ports.len() {
+            base.set_port_state(Port::new(idx as u32), PortState::Blocking);
+        }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.root_id == self.bridge_id
+    }
+
+    pub fn get_root_id(&self) -> BridgeId {
+        self.root_id
+    }
+
+    pub fn get_root_port(&self) -> Option<Port> {
+        self.root_port
+    }
+
+    pub fn get_port_role(&self, port: Port) -> PortRole {
+        self.ports[port.value as usize].role
+    }
+
+    pub fn get_port_state(&self, port: Port) -> PortState {
+        self.ports[port.value as usize].state
+    }
+
+    pub fn get_log(&self) -> &Vec<StpLog> {
+        &self.log
+    }
+
+    // BPDUs dropped because they could not be decoded
+    pub fn get_invalid_bpdus(&self) -> usize {
+        self.invalid_bpdus
+    }
+
+    pub fn recv_invalid(&mut self) {
+        self.invalid_bpdus += 1;
+    }
+
+    fn port_id(&self, idx: usize) -> u16 {
+        0x8000 | (idx as u16 + 1)
+    }
+
+    fn message_age(&self, t: usize) -> u16 {
+        match self.root_port.and_then(|p| self.ports[p.value as usize].info.as_ref()) {
+            Some((info, rt)) => (info.message_age as usize + (t - rt) + 1).min(u16::MAX as usize) as u16,
+            None => 0,
+        }
+    }
+
+    fn tc_flag(&self, t: usize) -> bool {
+        if self.is_root() {
+            t < self.tc_until
+        } else {
+            self.root_port
+                .and_then(|p| self.ports[p.value as usize].info.as_ref())
+                .map(|(info, _)| info.flags & FLAG_TC != 0)
+                .unwrap_or(false)
+        }
+    }
+
+    fn send_config(&mut self, idx: usize, base: &mut BaseEthernetDevice, ctx: &UpdateContext) {
+        let mut flags = 0;
+        if self.tc_flag(ctx.t) {
+            flags |= FLAG_TC;
+        }
+        if self.ports[idx].tca {
+            flags |= FLAG_TCA;
+            self.ports[idx].tca = false;
+        }
+        let bpdu = Bpdu::Config(ConfigBpdu {
+            flags,
+            root_id: self.root_id,
+            root_path_cost: self.root_path_cost,
+            bridge_id: self.bridge_id,
+            port_id: self.port_id(idx),
+            message_age: self.message_age(ctx.t),
+            max_age: self.config.max_age,
+            hello_time: self.config.hello_time,
+            forward_delay: self.config.forward_delay,
+        });
+        base.send_to(Port::new(idx as u32), bpdu.to_frame(self.bridge_id.mac()), ctx);
+    }
+
+    fn send_designated(&mut self, base: &mut BaseEthernetDevice, ctx: &UpdateContext) {
+        for idx in 0..self.ports.len() {
+            if self.ports[idx].role == PortRole::Designated && !base.is_mirror_destination(Port::new(idx as u32)) {
+                self.send_config(idx, base, ctx);
+            }
+        }
+    }
+
+    fn set_state(&mut self, idx: usize, state: PortState, base: &mut BaseEthernetDevice, t: usize) {
+        let p = &mut self.ports[idx];
+        p.state = state;
+        p.since = t;
+        let log = StpLog { t, port: Port::new(idx as u32), role: p.role, state };
+        self.log.push(log);
+        base.set_port_state(Port::new(idx as u32), state);
+    }
+
+    fn topology_change(&mut self, base: &mut BaseEthernetDevice, t: usize) {
+        base.clear_forwarding_table();
+        if self.is_root() {
+            self.tc_until = t + (self.config.max_age + self.config.forward_delay) as usize;
+        } else {
+            self.tcn_pending = true;
+            self.last_tcn = None;
+        }
+    }
+
+    // root bridge election and port role selection
+    fn select_roles(&mut self, base: &mut BaseEthernetDevice, t: usize) {
+        let mut best: Option<(BridgeId, u32, BridgeId, u16, u16)> = None;
+        let mut root_port = None;
+        for idx in 0..self.ports.len() {
+            if let Some((info, _)) = &self.ports[idx].info {
+                let cand = (info.root_id, info.root_path_cost + self.config.path_cost,
+                            info.bridge_id, info.port_id, self.port_id(idx));
+                if cand.0 < self.bridge_id && best.is_none_or(|b| cand < b) {
+                    best = Some(cand);
+                    root_port = Some(Port::new(idx as u32));
+                }
+            }
+        }
+        (self.root_id, self.root_path_cost) = match best {
+            Some((root_id, cost, _, _, _)) => (root_id, cost),
+            None => (self.bridge_id, 0),
+        };
+        self.root_port = root_port;
+
+        for idx in 0..self.ports.len() {
+            if base.is_mirror_destination(Port::new(idx as u32)) {
+                continue;
+            }
+            let mine = (self.root_id, self.root_path_cost, self.bridge_id, self.port_id(idx));
+            let role = if root_port == Some(Port::new(idx as u32)) {
+                PortRole::Root
+            } else {
+                match &self.ports[idx].info {
+                    Some((info, _)) if info.vector() <= mine => PortRole::Alternate,
+                    _ => PortRole::Designated,
+                }
+            };
+            if role != self.ports[idx].role {
+                self.ports[idx].role = role;
+                let state = self.ports[idx].state;
+                if role == PortRole::Alternate && state != PortState::Blocking {
+                    self.set_state(idx, PortState::Blocking, base, t);
+                    if state == PortState::Forwarding {
+                        self.topology_change(base, t);
+                    }
+                } else {
+                    let log = StpLog { t, port: Port::new(idx as u32), role, state };
+                    self.log.push(log);
+                }
+            }
+        }
+    }
+
+    pub fn recv(&mut self, port: Port, bpdu: &Bpdu, base: &mut BaseEthernetDevice, ctx: &UpdateContext) {
+        let idx = port.value as usize;
+        // a monitor port takes no part in the topology
+        if base.is_mirror_destination(port) {
+            return;
+        }
+        match bpdu {
+            Bpdu::Config(c) => {
+                self.ports[idx].info = Some((c.clone(), ctx.t));
+                self.select_roles(base, ctx.t);
+                if self.root_port == Some(port) {
+                    if c.flags & FLAG_TCA != 0 {
+                        self.tcn_pending = false;
+                    }
+                    if c.flags & FLAG_TC != 0 {
+                        base.clear_forwarding_table();
+                    }
+                    // relay the root information
+                    self.send_designated(base, ctx);
+                } else if self.ports[idx].role == PortRole::Designated {
+                    // inferior information. answer with ours
+                    self.send_config(idx, base, ctx);
+                }
+            }
+            Bpdu::Tcn => {
+                if self.ports[idx].role == PortRole::Designated {
+                    self.ports[idx].tca = true;
+                    self.send_config(idx, base, ctx);
+                    self.topology_change(base, ctx.t);
+                }
+            }
+        }
+    }
+
+    pub fn update(&mut self, base: &mut BaseEthernetDevice, ctx: &UpdateContext) {
+        let t = ctx.t;
+
+        // age out information from the neighbours
+        let max_age = self.config.max_age as usize;
+        let mut expired = false;
+        for p in &mut self.ports {
+            if let Some((info, rt)) = &p.info {
+                if info.message_age as usize + (t - rt) >= max_age {
+                    p.info = None;
+                    expired = true;
+                }
+            }
+        }
+        if expired {
+            self.select_roles(base, t);
+        }
+
+        // port state transitions
+        let forward_delay = self.config.forward_delay as usize;
+        for idx in 0..self.ports.len() {
+            if base.is_mirror_destination(Port::new(idx as u32)) {
+                continue;
+            }
+            let p = &self.ports[idx];
+            match (p.role, p.state) {
+                (PortRole::Alternate, _) => {}
+                (_, PortState::Blocking) => self.set_state(idx, PortState::Listening, base, t),
+                (_, PortState::Listening) if t - p.since >= forward_delay => {
+                    self.set_state(idx, PortState::Learning, base, t)
+                }
+                (_, PortState::Learning) if t - p.since >= forward_delay => {
+                    self.set_state(idx, PortState::Forwarding, base, t);
+                    self.topology_change(base, t);
+                }
+                _ => {}
+            }
+        }
+
+        let hello_time = self.config.hello_time as usize;
+        if self.is_root() {
+            if self.last_hello.is_none_or(|h| t - h >= hello_time) {
+                self.last_hello = Some(t);
+                self.send_designated(base, ctx);
+            }
+        } else if self.tcn_pending && self.last_tcn.is_none_or(|h| t - h >= hello_time) {
+            if let Some(root_port) = self.root_port {
+                self.last_tcn = Some(t);
+                base.send_to(root_port, Bpdu::Tcn.to_frame(self.bridge_id.mac()), ctx);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bpdu() {
+        let bpdus = vec![
+            Bpdu::Config(ConfigBpdu {
+                flags: FLAG_TC,
+                root_id: BridgeId::new(0x1000, Mac::new(0x0102_0304_0506)),
+                root_path_cost: 19,
+                bridge_id: BridgeId::new(0x8000, Mac::new(0x0a0b_0c0d_0e0f)),
+                port_id: 0x8002,
+                message_age: 1,
+                max_age: 2000,
+                hello_time: 400,
+                forward_delay: 600,
+            }),
+            Bpdu::Tcn,
+        ];
+        for bpdu in bpdus {
+            let frame = bpdu.to_frame(Mac::new(1));
+            let xs = EthernetFrame::encode(&frame);
+            let frame2 = EthernetFrame::decode(&xs).unwrap();
+            assert_eq!(frame, frame2);
+            assert_eq!(bpdu, Bpdu::decode(&frame2.payload).unwrap());
+        }
+    }
+}
@@ -30,16 +30,87 @@ fn main() -> io::Result<()> {
         // 3 => { linkl::run_sample_ethernet_switch().unwrap(); } ,
 
         10 => { physl::run_sample().unwrap(); }
+        11 => {
+            let config = physl::UartConfig::default();
+            physl::run_sample_uart(config, config).unwrap();
+            physl::run_sample_uart(physl::UartConfig { baud: 4800, ..config }, config).unwrap();
+            let odd = physl::UartConfig { parity: physl::Parity::Odd, ..config };
+            physl::run_sample_uart(odd, physl::UartConfig { parity: physl::Parity::Even, ..config }).unwrap();
+        }
+        12 => {
+            for code in [physl::LineCode::Manchester, physl::LineCode::FourBFiveB, physl::LineCode::Nrzi] {
+                physl::run_sample_line_code(code, None).unwrap();
+                physl::run_sample_line_code(code, Some(23)).unwrap();
+            }
+        }
+        13 => {
+            let auto = physl::PortConfig::default();
+            let half = physl::PortConfig { autoneg: false, duplex: physl::Duplex::Half, ..auto };
+            let full = physl::PortConfig { autoneg: false, ..auto };
+            linkl::run_sample_duplex(auto, auto).unwrap();
+            linkl::run_sample_duplex(half, half).unwrap();
+            linkl::run_sample_duplex(full, auto).unwrap();
+        }
         20 => { linkl::run_sample().unwrap(); }
         21 => { linkl::run_sample_3host().unwrap(); }
+        22 => { linkl::run_sample_stp().unwrap(); }
+        23 => { linkl::run_sample_mac_table().unwrap(); }
+        24 => {
+            for direction in [linkl::MirrorDirection::Ingress, linkl::MirrorDirection::Egress, linkl::MirrorDirection::Both] {
+                linkl::run_sample_port_mirror(direction).unwrap();
+            }
+        }
+        25 => { linkl::run_sample_lacp().unwrap(); }
+        26 => {
+            linkl::run_sample_pause(false).unwrap();
+            linkl::run_sample_pause(true).unwrap();
+        }
+        27 => {
+            linkl::run_sample_qos(None).unwrap();
+            linkl::run_sample_qos(Some(linkl::QosConfig::default())).unwrap();
+            let wrr = linkl::QosConfig {
+                num_queues: 2,
+                scheduling: linkl::Scheduling::WeightedRoundRobin(vec![1, 1]),
+                queue_size: None,
+            };
+            linkl::run_sample_qos(Some(wrr)).unwrap();
+        }
+        28 => {
+            linkl::run_sample_cut_through(linkl::SwitchingMode::StoreAndForward).unwrap();
+            linkl::run_sample_cut_through(linkl::SwitchingMode::CutThrough).unwrap();
+        }
+        29 => { linkl::run_sample_app().unwrap(); }
 
         30 => { netwl::run_host_host().unwrap(); }
         31 => { netwl::run_2host_1router().unwrap(); }
         32 => { netwl::run_2router().unwrap(); }
         33 => { netwl::run_unreachable().unwrap(); }
         34 => { netwl::run_test_router_arp().unwrap(); }
+        35 => {
+            netwl::run_multicast(linkl::MulticastMode::Flood).unwrap();
+            netwl::run_multicast(linkl::MulticastMode::Snooping).unwrap();
+        }
+        36 => { netwl::run_lldp().unwrap(); }
+        37 => { netwl::run_ppp().unwrap(); }
+        38 => { netwl::run_routing().unwrap(); }
+        39 => { netwl::run_interfaces().unwrap(); }
 
         40 => { tranl::run_test_tcp_nw().unwrap(); }
+
+        // network layer, continued. 30-39 are taken
+        50 => {
+            netwl::run_dynamic_arp(true).unwrap();
+            println!("{}", netwl::run_dynamic_arp(false).unwrap_err());
+        }
+        51 => { netwl::run_duplicate_ip().unwrap(); }
+        52 => {
+            netwl::run_traceroute(netwl::IpAddr::new(0x0a03_0002)).unwrap();
+            netwl::run_traceroute(netwl::IpAddr::new(0x0a09_0001)).unwrap();
+        }
+        53 => { netwl::run_ping().unwrap(); }
+        54 => { netwl::run_bad_checksum().unwrap(); }
+        55 => { netwl::run_fragmentation().unwrap(); }
+        56 => { netwl::run_ip_options().unwrap(); }
         _ => println!("No such run number"),
     }
 
@@ -1,23 +1,40 @@
 pub mod ip_addr;
 pub mod ip;
+pub mod ip_option;
 pub mod arp;
+pub mod arp_cache;
+pub mod arp_probe;
+pub mod icmp;
 pub mod network_protocol;
+pub mod reassembly;
+pub mod ping;
 pub mod ip_device;
 pub mod ip_host;
+pub mod interface;
 pub mod router;
+pub mod routing_table;
+pub mod traceroute;
 
-use super::linkl::EthernetSwitch;
+use super::linkl::{EthernetFrame, EthernetHost, EthernetSwitch, LldpConfig, LldpNeighbor, MulticastMode, PppConfig};
 
 use super::types::{Port, Mac, Res};
-use super::physl::Network;
+use super::physl::{Device, Network};
 
 pub use ip_addr::*;
 pub use ip::*;
+pub use ip_option::*;
 pub use arp::*;
+pub use arp_cache::*;
+pub use arp_probe::*;
+pub use icmp::*;
 pub use network_protocol::*;
+pub use ping::*;
 pub use ip_device::*;
 pub use ip_host::*;
+pub use interface::*;
 pub use router::*;
+pub use routing_table::*;
+pub use traceroute::*;
 
 #[derive(Debug)]
 pub struct NetworkLog {
@@ -25,6 +42,16 @@ pub struct NetworkLog {
     p: NetworkProtocol,
 }
 
+// the datagrams in a log. the gratuitous ARP of the devices is left out
+fn ip_logs(logs: &[NetworkLog]) -> Vec<&IP> {
+    logs.iter()
+        .filter_map(|log| match &log.p {
+            NetworkProtocol::IP(ip) => Some(ip),
+            _ => None,
+        })
+        .collect()
+}
+
 pub fn run_host_host() -> Res<()> {
     crate::output::set_level(crate::output::Level::Frame);
     let subnet_mask = SubnetMask::new(24);
@@ -53,12 +80,9 @@ pub fn run_host_host() -> Res<()> {
     // let log = &d.get_rlog()[0];
     // println!("received log: {:?}, {:?}", log.t, log.p);
     println!("{:?}", d.get_rlog());
-    assert_eq!(1, d.get_rlog().len());
-    let ip = match d.get_rlog()[0].p.clone() {
-        NetworkProtocol::IP(p) => p,
-        _ => panic!("")
-    };
-    assert_eq!(d.get_ip_addr(Port::new(0)), Some(ip.dst));
+    let ips = ip_logs(d.get_rlog());
+    assert_eq!(1, ips.len());
+    assert_eq!(d.get_ip_addr(Port::new(0)), Some(ips[0].dst));
     Ok(())
 }
 
@@ -93,19 +117,14 @@ pub fn run_2host_1router() -> Res<()> {
     nw.connect_both(mac_s, Port::new(1), mac_b, Port::new(0))?;
     nw.connect_both(mac_s, Port::new(2), mac_r, Port::new(0))?;
 
-    nw.run(200).unwrap();
+    nw.run(500).unwrap();
     let d = nw.get_device(mac_a).unwrap();
     let d = d.as_any().downcast_ref::<IpHost>().unwrap();
 
     println!("{:?}", d.get_rlog());
-    assert_eq!(1, d.get_rlog().len());
-    let log = &d.get_rlog()[0];
-    println!("t={}, p={}", log.t, log.p);
-    let ip = match log.p.clone() {
-        NetworkProtocol::IP(p) => p,
-        _ => panic!("")
-    };
-    assert_eq!(d.get_ip_addr(Port::new(0)), Some(ip.dst));
+    let ips = ip_logs(d.get_rlog());
+    assert_eq!(1, ips.len());
+    assert_eq!(d.get_ip_addr(Port::new(0)), Some(ips[0].dst));
     // println!("{}", d.get_name());    
 
     Ok(())    
@@ -173,15 +192,12 @@ pub fn run_2router() -> Res<()> {
     nw.connect_both(mac_3, Port::new(1), mac_d, Port::new(0))?;
     nw.connect_both(mac_3, Port::new(2), mac_s, Port::new(0))?;
 
-    nw.run(550).unwrap();
+    nw.run(1000).unwrap();
     let d = nw.get_device(mac_a)?;
     let d = d.as_any().downcast_ref::<IpHost>().unwrap();
-    let rlogs = d.get_rlog();
-    assert_eq!(1, rlogs.len());
-    let plog: &IP = match rlogs[0].p {
-        NetworkProtocol::IP(ref p) => p,
-        _ => panic!("")
-    };
+    let ips = ip_logs(d.get_rlog());
+    assert_eq!(1, ips.len());
+    let plog = ips[0];
     assert_eq!(plog.dst, addr_1a);
     assert_eq!(plog.src, addr_3d);
     Ok(())
@@ -249,7 +265,7 @@ pub fn run_unreachable() -> Res<()> {
     nw.connect_both(mac_3, Port::new(1), mac_d, Port::new(0))?;
     nw.connect_both(mac_3, Port::new(2), mac_s, Port::new(0))?;
 
-    let res = nw.run(400);
+    let res = nw.run(1000);
     match &res {
         Err(e) => println!("{}", e),
         _ => panic!("expect error"),
@@ -288,7 +304,9 @@ pub fn run_test_router_arp() -> Res<()> {
     nw.connect_both(mac_s, Port::new(1), mac_b, Port::new(0))?;
     nw.connect_both(mac_s, Port::new(2), mac_r, Port::new(0))?;
 
-    nw.run(250).unwrap();
+    // the request and the reply are stored and forwarded by the switch, each a 28-byte ARP.
+    // they wait there behind the announcements flooded at startup
+    nw.run(1000).unwrap();
 
     let d = nw.get_device(mac_a).unwrap();
     let d = d.as_any().downcast_ref::<IpHost>().unwrap();
@@ -301,6 +319,698 @@ pub fn run_test_router_arp() -> Res<()> {
     Ok(())        
 }
 
+// host_b joins a group, host_a sends to it twice and host_b leaves in between. returns the number of group packets
+// received by host_b and the number of group frames which reached host_c's NIC
+pub fn run_multicast(mode: MulticastMode) -> Res<(usize, usize)> {
+    crate::output::set_level(crate::output::Level::Frame);
+    let subnet_mask = SubnetMask::new(24);
+    let addr_a = IpAddr::new(0x0a00_0001);
+    let addr_b = IpAddr::new(0x0a00_0002);
+    let addr_c = IpAddr::new(0x0a00_0003);
+    let group = IpAddr::new(0xEF01_0101); // 239.1.1.1
+
+    let mac_a = Mac::new(761);
+    let mac_b = Mac::new(762);
+    let mac_c = Mac::new(763);
+    let mac_s = Mac::new(764);
+
+    let mut host_a = IpHost::build_echo(mac_a, "hostA", addr_a, subnet_mask);
+    let mut host_b = IpHost::build_echo(mac_b, "hostB", addr_b, subnet_mask);
+    let host_c = IpHost::build_echo(mac_c, "hostC", addr_c, subnet_mask);
+    host_b.join_group(group);
+    host_b.add_arp_entry(addr_a, mac_a)?;
+    host_a.add_arp_entry(addr_b, mac_b)?;
+    // after the gratuitous ARP of the hosts
+    host_a.add_schedule(800, NetworkProtocol::IP(IP::new_byte(addr_a, group, vec![0x01, 0x02])));
+    host_a.add_schedule(1100, NetworkProtocol::IP(IP::new_byte(addr_a, group, vec![0x03, 0x04])));
+    let mut switch = EthernetSwitch::build_switch(mac_s, "switch", 3);
+    switch.set_multicast_mode(mode);
+
+    let mut nw = Network::new(
+        vec![host_a, host_b, host_c, switch],
+        vec![]
+    );
+    nw.connect_both(mac_s, Port::new(0), mac_a, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(1), mac_b, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(2), mac_c, Port::new(0))?;
+    nw.run(1000)?;
+    let d = nw.get_device(mac_b)?;
+    d.as_any_mut().downcast_mut::<IpHost>().unwrap().leave_group(group);
+    nw.run(1300)?;
+
+    // the member answers with unicast, and ignores the group after leaving
+    let d = nw.get_device(mac_a)?;
+    let d = d.as_any().downcast_ref::<IpHost>().unwrap();
+    let ip = ip_logs(d.get_rlog())[0];
+    assert_eq!((addr_b, addr_a), (ip.src, ip.dst));
+
+    let d = nw.get_device(mac_b)?;
+    let d = d.as_any().downcast_ref::<IpHost>().unwrap();
+    let member = d.get_rlog().iter().filter(|log| match &log.p {
+        NetworkProtocol::IP(ip) => ip.dst == group,
+        _ => false,
+    }).count();
+
+    let d = nw.get_device(mac_c)?;
+    let d = d.as_any().downcast_ref::<IpHost>().unwrap();
+    assert_eq!(0, ip_logs(d.get_rlog()).len());
+    let non_member = d.ip_base().base.rlog.iter().filter(|log| log.frame.dst == group.multicast_mac()).count();
+    Ok((member, non_member))
+}
+
+// every device advertises itself with LLDP. the neighbor tables must match the cabling.
+// returns the number of checked ports
+pub fn run_lldp() -> Res<usize> {
+    crate::output::set_level(crate::output::Level::Frame);
+    let subnet_mask = SubnetMask::new(24);
+    let addr_a = IpAddr::new(0x0a01_0001);
+    let addr_b = IpAddr::new(0x0a02_0001);
+    let addr_r1 = IpAddr::new(0x0a01_0003);
+    let addr_r2 = IpAddr::new(0x0a02_0003);
+
+    let mac_a = Mac::new(761);
+    let mac_b = Mac::new(762);
+    let mac_s = Mac::new(763);
+    let mac_r = Mac::new(764);
+    let mac_e = Mac::new(765);
+
+    let config = LldpConfig::default();
+    let mut host_a = IpHost::build_echo(mac_a, "hostA", addr_a, subnet_mask);
+    let mut host_b = IpHost::build_echo(mac_b, "hostB", addr_b, subnet_mask);
+    let mut host_e = EthernetHost::build_consumer(mac_e, "hostE");
+    let mut switch = EthernetSwitch::build_switch(mac_s, "switch", 3);
+    let mut router = Router::build(mac_r, "router", vec![addr_r1, addr_r2], subnet_mask);
+    host_a.enable_lldp(config);
+    host_b.enable_lldp(config);
+    host_e.base.enable_lldp(config);
+    switch.enable_lldp(config);
+    router.enable_lldp(config);
+
+    let mut nw = Network::new(
+        vec![host_a, host_b, host_e, switch, router],
+        vec![]
+    );
+    nw.connect_both(mac_s, Port::new(0), mac_a, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(1), mac_r, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(2), mac_e, Port::new(0))?;
+    nw.connect_both(mac_r, Port::new(1), mac_b, Port::new(0))?;
+    nw.run(2500)?;
+
+    let t = nw.get_t();
+    let names = [(mac_a, "hostA"), (mac_b, "hostB"), (mac_s, "switch"), (mac_r, "router"), (mac_e, "hostE")];
+    let mut neighbors: Vec<(Mac, LldpNeighbor)> = vec![];
+    for (mac, _) in names {
+        let d = nw.get_device(mac)?.as_any();
+        let lldp = if let Some(d) = d.downcast_ref::<IpHost>() {
+            d.get_lldp()
+        } else if let Some(d) = d.downcast_ref::<Router>() {
+            d.get_lldp()
+        } else if let Some(d) = d.downcast_ref::<EthernetSwitch>() {
+            d.get_lldp()
+        } else {
+            d.downcast_ref::<EthernetHost>().unwrap().base.get_lldp()
+        };
+        let lldp = lldp.unwrap();
+        println!("{}", lldp.dump(t));
+        neighbors.extend(lldp.get_neighbors().into_iter().map(|n| (mac, n.clone())));
+    }
+    for (mac, n) in &neighbors {
+        assert_eq!((n.chassis, n.port), nw.get_peer(*mac, n.local_port)?);
+        let name = names.iter().find(|(mac, _)| *mac == n.chassis).unwrap().1;
+        assert_eq!(name, n.system_name);
+    }
+    Ok(neighbors.len())
+}
+
+// two LANs joined by a serial link between the routers. the link is framed by PPP, so the routers
+// need no ARP entries for each other. host_d accepts up to 296 bytes on the link.
+// returns the payload sizes host_d received and the datagrams the routers could not send on the link
+pub fn run_ppp() -> Res<(Vec<usize>, usize)> {
+    crate::output::set_level(crate::output::Level::Frame);
+    let subnet_mask = SubnetMask::new(24);
+
+    let addr_1a = IpAddr::new(0x0a01_0001);
+    let addr_1r = IpAddr::new(0x0a01_0003);
+    let addr_2r = IpAddr::new(0x0a02_0001);
+    let addr_2s = IpAddr::new(0x0a02_0002);
+    let addr_3d = IpAddr::new(0x0a03_0002);
+    let addr_3s = IpAddr::new(0x0a03_0003);
+
+    let mac_a = Mac::new(761);
+    let mac_d = Mac::new(764);
+    let mac_r = Mac::new(765);
+    let mac_s = Mac::new(766);
+
+    let mut host_a = IpHost::build_echo(mac_a, "host1a", addr_1a, subnet_mask);
+    let mut host_d = IpHost::build_echo(mac_d, "host3d", addr_3d, subnet_mask);
+    let mut router_r = Router::build(mac_r, "routeR", vec![addr_1r, addr_2r], subnet_mask);
+    let mut router_s = Router::build(mac_s, "routeS", vec![addr_3s, addr_2s], subnet_mask);
+    router_r.set_ppp(Port::new(1), PppConfig::default());
+    router_s.set_ppp(Port::new(1), PppConfig { mru: 296, ..PppConfig::default() });
+
+    // fits the MRU, exceeds the MRU
+    for (t, len) in [(100, 200), (400, 400)] {
+        let ip = IP::new_byte(addr_1a, addr_3d, vec![0x01; len]);
+        host_a.add_schedule(t, NetworkProtocol::IP(ip));
+    }
+
+    host_a.add_arp_entry(addr_1r, mac_r)?;
+    host_d.add_arp_entry(addr_3s, mac_s)?;
+    router_r.add_arp_entry(addr_1a, mac_a)?;
+    router_s.add_arp_entry(addr_3d, mac_d)?;
+
+    let nw3_part = addr_3s.nw(subnet_mask);
+    host_a.add_route_entry(nw3_part, addr_1r)?;
+    router_r.add_route_entry(nw3_part, addr_2s)?;
+    let nw1_part = addr_1r.nw(subnet_mask);
+    host_d.add_route_entry(nw1_part, addr_3s)?;
+    router_s.add_route_entry(nw1_part, addr_2r)?;
+
+    let mut nw = Network::new(
+        vec![host_a, host_d, router_r, router_s],
+        vec![]
+    );
+    nw.connect_both(mac_r, Port::new(0), mac_a, Port::new(0))?;
+    nw.connect_both(mac_r, Port::new(1), mac_s, Port::new(1))?;
+    nw.connect_both(mac_s, Port::new(0), mac_d, Port::new(0))?;
+    nw.run(1200)?;
+
+    let mut drops = 0;
+    for mac in [mac_r, mac_s] {
+        let d = nw.get_device(mac)?;
+        let router = d.as_any().downcast_ref::<Router>().unwrap();
+        let link = router.get_ppp(Port::new(1)).unwrap();
+        println!("{}: LCP {:?}, peer MRU {}, drops {}",
+                 router.base().get_name(), link.get_state(), link.get_peer_mru(), link.get_drop_count());
+        assert!(link.is_opened());
+        drops += link.get_drop_count();
+    }
+    let d = nw.get_device(mac_d)?;
+    let d = d.as_any().downcast_ref::<IpHost>().unwrap();
+    let mut lens: Vec<usize> = d.get_rlog()
+        .iter()
+        .filter_map(|log| match &log.p {
+            NetworkProtocol::IP(IP { payload: IpPayload::Bytes(xs), .. }) => Some(xs.len()),
+            _ => None,
+        })
+        .collect();
+    lens.dedup();
+    Ok((lens, drops))
+}
+
+// hosts only know their gateway. routeR has a /8 route and a floating static route
+// towards 10.3.0.0/24
+pub fn run_routing() -> Res<()> {
+    println!("netwl sample. routing table");
+    let subnet_mask = SubnetMask::new(24);
+
+    let addr_1a = IpAddr::new(0x0a01_0001);
+    let addr_1r = IpAddr::new(0x0a01_0003);
+    let addr_2r = IpAddr::new(0x0a02_0001);
+    let addr_2s = IpAddr::new(0x0a02_0002);
+    let addr_3d = IpAddr::new(0x0a03_0002);
+    let addr_3s = IpAddr::new(0x0a03_0003);
+
+    let mac_a = Mac::new(791);
+    let mac_d = Mac::new(792);
+    let mac_r = Mac::new(793);
+    let mac_s = Mac::new(794);
+
+    let mut host_a = IpHost::build_echo(mac_a, "host1a", addr_1a, subnet_mask);
+    let mut host_d = IpHost::build_echo(mac_d, "host3d", addr_3d, subnet_mask);
+    let mut router_r = Router::build(mac_r, "routeR", vec![addr_1r, addr_2r], subnet_mask);
+    let mut router_s = Router::build(mac_s, "routeS", vec![addr_3s, addr_2s], subnet_mask);
+
+    let ip0 = IP::new_byte(addr_1a, addr_3d, vec![0x01, 0x02]);
+    host_a.add_schedule(0, NetworkProtocol::IP(ip0));
+
+    host_a.add_arp_entry(addr_1r, mac_r)?;
+    host_d.add_arp_entry(addr_3s, mac_s)?;
+    router_r.add_arp_entry(addr_1a, mac_a)?;
+    router_r.add_arp_entry(addr_2s, mac_s)?;
+    router_s.add_arp_entry(addr_3d, mac_d)?;
+    router_s.add_arp_entry(addr_2r, mac_r)?;
+
+    host_a.add_route(Route::default_route(addr_1r))?;
+    host_d.add_route(Route::default_route(addr_3s))?;
+    let nw8 = NetworkPart::new(IpAddr::new(0x0a00_0000), SubnetMask::new(8));
+    router_r.add_route(Route::static_route(nw8, addr_2s))?;
+    // the floating static route is only used while the other /24 route is missing
+    let mut backup = Route::static_route(addr_3d.nw(subnet_mask), addr_2s);
+    backup.distance = 200;
+    router_r.add_route(backup)?;
+    // a wrong next hop. "no ip route" removes it again
+    router_r.add_route(Route::static_route(addr_3d.nw(subnet_mask), IpAddr::new(0x0a01_0009)))?;
+    println!("{}", router_r.show_ip_route());
+    router_r.remove_route(addr_3d.nw(subnet_mask), Some(IpAddr::new(0x0a01_0009)))?;
+    router_s.add_route(Route::static_route(NetworkPart::new(addr_1a, SubnetMask::new(16)), addr_2r))?;
+
+    println!("{}", router_r.show_ip_route());
+    println!("{}", router_s.show_ip_route());
+
+    let mut nw = Network::new(vec![host_a, host_d, router_r, router_s], vec![]);
+    nw.connect_both(mac_a, Port::new(0), mac_r, Port::new(0))?;
+    nw.connect_both(mac_r, Port::new(1), mac_s, Port::new(1))?;
+    nw.connect_both(mac_d, Port::new(0), mac_s, Port::new(0))?;
+
+    nw.run(550)?;
+    let d = nw.get_device(mac_a)?;
+    let d = d.as_any().downcast_ref::<IpHost>().unwrap();
+    let plog = ip_logs(d.get_rlog())[0];
+    assert_eq!(plog.dst, addr_1a);
+    assert_eq!(plog.src, addr_3d);
+    Ok(())
+}
+
+// the routers join a /30 point-to-point link and a /24 LAN, each port with its own MAC.
+// host1a learns the MAC of its gateway by ARP. returns it
+pub fn run_interfaces() -> Res<Mac> {
+    println!("netwl sample. interfaces");
+    let subnet_mask = SubnetMask::new(24);
+
+    let addr_1a = IpAddr::new(0x0a01_0001);
+    let addr_1r = IpAddr::new(0x0a01_0003);
+    let addr_2r = IpAddr::new(0x0a02_0001);
+    let addr_2s = IpAddr::new(0x0a02_0002);
+    let addr_3d = IpAddr::new(0x0a03_0002);
+    let addr_3s = IpAddr::new(0x0a03_0003);
+
+    let mac_a = Mac::new(811);
+    let mac_d = Mac::new(812);
+    let mac_r = Mac::new(813);
+    let mac_s = Mac::new(814);
+    let mac_r0 = Mac::new(815);
+    let mac_r1 = Mac::new(816);
+    let mac_s0 = Mac::new(817);
+    let mac_s1 = Mac::new(818);
+
+    let mut host_a = IpHost::build_echo(mac_a, "host1a", addr_1a, subnet_mask);
+    let mut host_d = IpHost::build_echo(mac_d, "host3d", addr_3d, subnet_mask);
+    let mut router_r = Router::build_interfaces(mac_r, "routeR", vec![
+        Interface::new(Port::new(0), addr_1r, 24, mac_r0),
+        Interface::new(Port::new(1), addr_2r, 30, mac_r1),
+    ]);
+    let mut router_s = Router::build_interfaces(mac_s, "routeS", vec![
+        Interface::new(Port::new(0), addr_3s, 24, mac_s0),
+        Interface::new(Port::new(1), addr_2s, 30, mac_s1),
+    ]);
+
+    let arp0 = ARP::new_request(mac_a, addr_1a, addr_1r);
+    host_a.add_schedule(0, NetworkProtocol::ARP(arp0));
+    let ip0 = IP::new_byte(addr_1a, addr_3d, vec![0x01, 0x02]);
+    host_a.add_schedule(150, NetworkProtocol::IP(ip0));
+
+    host_d.add_arp_entry(addr_3s, mac_s0)?;
+    router_r.add_arp_entry(addr_1a, mac_a)?;
+    router_r.add_arp_entry(addr_2s, mac_s1)?;
+    router_s.add_arp_entry(addr_3d, mac_d)?;
+    router_s.add_arp_entry(addr_2r, mac_r1)?;
+
+    host_a.add_route(Route::default_route(addr_1r))?;
+    host_d.add_route(Route::default_route(addr_3s))?;
+    router_r.add_route(Route::static_route(addr_3d.nw(subnet_mask), addr_2s))?;
+    router_s.add_route(Route::static_route(addr_1a.nw(subnet_mask), addr_2r))?;
+
+    println!("{}", router_r.show_ip_interface());
+    println!("{}", router_r.show_ip_route());
+
+    let mut nw = Network::new(vec![host_a, host_d, router_r, router_s], vec![]);
+    nw.connect_both(mac_a, Port::new(0), mac_r, Port::new(0))?;
+    nw.connect_both(mac_r, Port::new(1), mac_s, Port::new(1))?;
+    nw.connect_both(mac_d, Port::new(0), mac_s, Port::new(0))?;
+
+    nw.run(700)?;
+    let d = nw.get_device(mac_a)?;
+    let d = d.as_any().downcast_ref::<IpHost>().unwrap();
+    let plog = ip_logs(d.get_rlog()).first().copied();
+    assert_eq!(Some(addr_3d), plog.map(|p| p.src));
+    Ok(*d.get_arp_table().get(&addr_1r).unwrap())
+}
+
+// no ARP entries are configured. host1a forgets the MAC of its gateway after 1000 ticks.
+// returns the ARP replies host1a received. fails with host unreachable if host3d does not exist
+pub fn run_dynamic_arp(dst_exists: bool) -> Res<usize> {
+    println!("netwl sample. dynamic ARP");
+    let subnet_mask = SubnetMask::new(24);
+
+    let addr_1a = IpAddr::new(0x0a01_0001);
+    let addr_1r = IpAddr::new(0x0a01_0003);
+    let addr_3d = IpAddr::new(0x0a03_0002);
+    let addr_3r = IpAddr::new(0x0a03_0003);
+
+    let mac_a = Mac::new(821);
+    let mac_d = Mac::new(822);
+    let mac_r = Mac::new(823);
+
+    let mut host_a = IpHost::build_echo(mac_a, "host1a", addr_1a, subnet_mask);
+    let addr_d = if dst_exists { addr_3d } else { IpAddr::new(0x0a03_0009) };
+    let mut host_d = IpHost::build_echo(mac_d, "host3d", addr_d, subnet_mask);
+    let router_r = Router::build(mac_r, "routeR", vec![addr_1r, addr_3r], subnet_mask);
+
+    host_a.set_arp_config(ArpConfig { timeout_ticks: 1000, ..ArpConfig::default() });
+    host_a.add_route(Route::default_route(addr_1r))?;
+    host_d.add_route(Route::default_route(addr_3r))?;
+    // after the gratuitous ARP of routeR. a reply queued behind it would be requested twice
+    for t in [200, 1500] {
+        let ip = IP::new_byte(addr_1a, addr_3d, vec![0x01, 0x02]);
+        host_a.add_schedule(t, NetworkProtocol::IP(ip));
+    }
+
+    let mut nw = Network::new(vec![host_a, host_d, router_r], vec![]);
+    nw.connect_both(mac_a, Port::new(0), mac_r, Port::new(0))?;
+    nw.connect_both(mac_d, Port::new(0), mac_r, Port::new(1))?;
+
+    nw.run(2000)?;
+    let d = nw.get_device(mac_a)?;
+    let d = d.as_any().downcast_ref::<IpHost>().unwrap();
+    let replies = d.get_rlog()
+        .iter()
+        .filter(|log| matches!(log.p, NetworkProtocol::ARP(ref arp) if arp.opcode == 2))
+        .count();
+    Ok(replies)
+}
+
+// three hosts probe and announce their addresses. hostA learns hostB. then hostB is
+// readdressed to the address of hostA, and hostC takes over the old address of hostB.
+// returns the conflicts of hostB and the MAC hostA has for 10.0.0.2
+pub fn run_duplicate_ip() -> Res<(Vec<ArpConflict>, Option<Mac>)> {
+    println!("netwl sample. duplicate address detection");
+    crate::output::set_level(crate::output::Level::Frame);
+    let subnet_mask = SubnetMask::new(24);
+    let addr_a = IpAddr::new(0x0a00_0001);
+    let addr_b = IpAddr::new(0x0a00_0002);
+    let addr_c = IpAddr::new(0x0a00_0003);
+
+    let mac_a = Mac::new(831);
+    let mac_b = Mac::new(832);
+    let mac_c = Mac::new(833);
+    let mac_s = Mac::new(834);
+
+    let config = ArpConfig { detect_duplicates: true, ..ArpConfig::default() };
+    let mut host_a = IpHost::build_echo(mac_a, "hostA", addr_a, subnet_mask);
+    let mut host_b = IpHost::build_echo(mac_b, "hostB", addr_b, subnet_mask);
+    let mut host_c = IpHost::build_echo(mac_c, "hostC", addr_c, subnet_mask);
+    host_a.set_arp_config(config);
+    host_b.set_arp_config(config);
+    host_c.set_arp_config(config);
+    host_a.add_schedule(1000, NetworkProtocol::ARP(ARP::new_request(mac_a, addr_a, addr_b)));
+    let switch = EthernetSwitch::build_switch(mac_s, "switch", 3);
+
+    let mut nw = Network::new(vec![host_a, host_b, host_c, switch], vec![]);
+    nw.connect_both(mac_s, Port::new(0), mac_a, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(1), mac_b, Port::new(0))?;
+    nw.connect_both(mac_s, Port::new(2), mac_c, Port::new(0))?;
+
+    nw.run(1500)?;
+    let d = nw.get_device(mac_b)?;
+    d.as_any_mut().downcast_mut::<IpHost>().unwrap().set_ip_addr(Port::new(0), addr_a);
+    nw.run(2500)?;
+    let d = nw.get_device(mac_c)?;
+    d.as_any_mut().downcast_mut::<IpHost>().unwrap().set_ip_addr(Port::new(0), addr_b);
+    nw.run(3500)?;
+
+    let d = nw.get_device(mac_a)?;
+    let d = d.as_any().downcast_ref::<IpHost>().unwrap();
+    assert!(d.get_arp_conflicts().is_empty());
+    let mac = d.get_arp_table().get(&addr_b).copied();
+    let d = nw.get_device(mac_b)?;
+    let d = d.as_any().downcast_ref::<IpHost>().unwrap();
+    Ok((d.get_arp_conflicts().clone(), mac))
+}
+
+// host1a traces the route to dst. routeR and routeS send 10.9.0.0/16 to each other
+pub fn run_traceroute(dst: IpAddr) -> Res<Vec<Hop>> {
+    println!("netwl sample. traceroute");
+    let subnet_mask = SubnetMask::new(24);
+
+    let addr_1a = IpAddr::new(0x0a01_0001);
+    let addr_1r = IpAddr::new(0x0a01_0003);
+    let addr_2r = IpAddr::new(0x0a02_0001);
+    let addr_2s = IpAddr::new(0x0a02_0002);
+    let addr_3d = IpAddr::new(0x0a03_0002);
+    let addr_3s = IpAddr::new(0x0a03_0003);
+
+    let mac_a = Mac::new(841);
+    let mac_d = Mac::new(842);
+    let mac_r = Mac::new(843);
+    let mac_s = Mac::new(844);
+
+    let mut host_a = IpHost::build_echo(mac_a, "host1a", addr_1a, subnet_mask);
+    let mut host_d = IpHost::build_echo(mac_d, "host3d", addr_3d, subnet_mask);
+    let mut router_r = Router::build(mac_r, "routeR", vec![addr_1r, addr_2r], subnet_mask);
+    let mut router_s = Router::build(mac_s, "routeS", vec![addr_3s, addr_2s], subnet_mask);
+
+    host_a.add_route(Route::default_route(addr_1r))?;
+    host_d.add_route(Route::default_route(addr_3s))?;
+    router_r.add_route(Route::static_route(addr_3d.nw(subnet_mask), addr_2s))?;
+    router_s.add_route(Route::static_route(addr_1a.nw(subnet_mask), addr_2r))?;
+    let loop_nw = NetworkPart::new(IpAddr::new(0x0a09_0000), SubnetMask::new(16));
+    router_r.add_route(Route::static_route(loop_nw, addr_2s))?;
+    router_s.add_route(Route::static_route(loop_nw, addr_2r))?;
+    host_a.set_traceroute(Traceroute::new(dst, 4, 600));
+
+    let mut nw = Network::new(vec![host_a, host_d, router_r, router_s], vec![]);
+    nw.connect_both(mac_a, Port::new(0), mac_r, Port::new(0))?;
+    nw.connect_both(mac_r, Port::new(1), mac_s, Port::new(1))?;
+    nw.connect_both(mac_d, Port::new(0), mac_s, Port::new(0))?;
+
+    nw.run(3000)?;
+    let d = nw.get_device(mac_a)?;
+    let d = d.as_any().downcast_ref::<IpHost>().unwrap();
+    let tr = d.get_traceroute().unwrap();
+    assert!(tr.is_done());
+    println!("{}", tr.dump());
+    Ok(tr.get_hops().clone())
+}
+
+// host A pings host D behind two routers. the link between the routers is cut for a while
+pub fn run_ping() -> Res<PingStats> {
+    println!("netwl sample. ping");
+    let subnet_mask = SubnetMask::new(24);
+
+    let addr_1a = IpAddr::new(0x0a01_0001);
+    let addr_1r = IpAddr::new(0x0a01_0003);
+    let addr_2r = IpAddr::new(0x0a02_0001);
+    let addr_2s = IpAddr::new(0x0a02_0002);
+    let addr_3d = IpAddr::new(0x0a03_0002);
+    let addr_3s = IpAddr::new(0x0a03_0003);
+
+    let mac_a = Mac::new(851);
+    let mac_d = Mac::new(852);
+    let mac_r = Mac::new(853);
+    let mac_s = Mac::new(854);
+
+    let mut host_a = IpHost::build_echo(mac_a, "host1a", addr_1a, subnet_mask);
+    let mut host_d = IpHost::build_echo(mac_d, "host3d", addr_3d, subnet_mask);
+    let mut router_r = Router::build(mac_r, "routeR", vec![addr_1r, addr_2r], subnet_mask);
+    let mut router_s = Router::build(mac_s, "routeS", vec![addr_3s, addr_2s], subnet_mask);
+
+    host_a.add_route(Route::default_route(addr_1r))?;
+    host_d.add_route(Route::default_route(addr_3s))?;
+    router_r.add_route(Route::static_route(addr_3d.nw(subnet_mask), addr_2s))?;
+    router_s.add_route(Route::static_route(addr_1a.nw(subnet_mask), addr_2r))?;
+    // routers answer echo requests too
+    let request = Icmp::echo_request(0x0001, 0, vec![]);
+    host_d.add_schedule(2400, NetworkProtocol::IP(IP::new_icmp(addr_3d, addr_2r, request.clone())));
+
+    let mut nw = Network::new(vec![host_a, host_d, router_r, router_s], vec![]);
+    nw.connect_both(mac_a, Port::new(0), mac_r, Port::new(0))?;
+    nw.connect_both(mac_r, Port::new(1), mac_s, Port::new(1))?;
+    nw.connect_both(mac_d, Port::new(0), mac_s, Port::new(0))?;
+
+    // after the gratuitous ARP. the first requests wait for ARP on every hop
+    nw.run(400)?;
+    let d = nw.get_device(mac_a)?;
+    d.as_any_mut().downcast_mut::<IpHost>().unwrap().set_ping(Ping::new(addr_3d, 5, 400, 1000));
+    // the third request is lost
+    nw.run(1250)?;
+    nw.set_link_up(mac_r, Port::new(1), false)?;
+    nw.run(1550)?;
+    nw.set_link_up(mac_r, Port::new(1), true)?;
+    nw.run(2900)?;
+
+    let t = nw.get_t();
+    let d = nw.get_device(mac_a)?;
+    let d = d.as_any().downcast_ref::<IpHost>().unwrap();
+    let ping = d.get_ping().unwrap();
+    assert!(ping.is_done(t));
+    println!("{}", ping.dump());
+    let stats = ping.get_stats();
+
+    let d = nw.get_device(mac_d)?;
+    let d = d.as_any().downcast_ref::<IpHost>().unwrap();
+    let reply = IpPayload::ICMP(request.echo_reply());
+    assert!(d.get_rlog().iter().any(|log| matches!(&log.p, NetworkProtocol::IP(ip) if ip.src == addr_2r && ip.payload == reply)));
+    Ok(stats)
+}
+
+// a raw host sends three datagrams to host B through router R. the header of the second is damaged
+// on the way, and the ICMP message of the third. returns the datagrams dropped by R for their header,
+// by B for their header and for their ICMP checksum, and the datagrams B received
+pub fn run_bad_checksum() -> Res<(usize, usize, usize, usize)> {
+    println!("netwl sample. IP header checksum");
+    let subnet_mask = SubnetMask::new(24);
+
+    let addr_1e = IpAddr::new(0x0a01_0001);
+    let addr_1r = IpAddr::new(0x0a01_0003);
+    let addr_2r = IpAddr::new(0x0a02_0001);
+    let addr_2b = IpAddr::new(0x0a02_0002);
+
+    let mac_e = Mac::new(861);
+    let mac_r = Mac::new(862);
+    let mac_b = Mac::new(863);
+
+    let mut host_e = EthernetHost::build_consumer(mac_e, "host1e");
+    let mut router_r = Router::build(mac_r, "routeR", vec![addr_1r, addr_2r], subnet_mask);
+    let mut host_b = IpHost::build_echo(mac_b, "host2b", addr_2b, subnet_mask);
+    router_r.add_arp_entry(addr_1e, mac_e)?;
+    host_b.add_route(Route::default_route(addr_2r))?;
+
+    let good = IP::new_byte(addr_1e, addr_2b, vec![0x01, 0x02]).encode();
+    let mut bad = IP::new_byte(addr_1e, addr_2b, vec![0x03, 0x04]).encode();
+    bad[15] ^= 0x01; // source address
+    host_e.add_schedule(0, EthernetFrame::new(mac_r, mac_e, 0x0800, good));
+    host_e.add_schedule(100, EthernetFrame::new(mac_r, mac_e, 0x0800, bad));
+    let mut bad_icmp = IP::new_icmp(addr_1e, addr_2b, Icmp::echo_request(0x0001, 0, vec![0x05, 0x06])).encode();
+    bad_icmp[28] ^= 0x01; // echo data
+    host_e.add_schedule(200, EthernetFrame::new(mac_r, mac_e, 0x0800, bad_icmp));
+
+    let mut nw = Network::new(vec![host_e, router_r, host_b], vec![]);
+    nw.connect_both(mac_e, Port::new(0), mac_r, Port::new(0))?;
+    nw.connect_both(mac_r, Port::new(1), mac_b, Port::new(0))?;
+    nw.run(600)?;
+
+    let r = nw.get_device(mac_r)?;
+    let r = r.as_any().downcast_ref::<Router>().unwrap();
+    let r_bad = r.get_bad_checksums();
+    let r_malformed = r.get_rx_errors();
+    let b = nw.get_device(mac_b)?;
+    let b = b.as_any().downcast_ref::<IpHost>().unwrap();
+    // the forwarded datagram carries the checksum recomputed for the decremented TTL
+    let b_received = b.get_rlog().iter().filter(|log| matches!(log.p, NetworkProtocol::IP(_))).count();
+    // the router does not look into the ICMP message it forwards
+    println!("dropped by router: {} + {} malformed, dropped by host: {} + {} ICMP, received by host: {}",
+             r_bad, r_malformed, b.get_bad_checksums(), b.get_bad_icmp_checksums(), b_received);
+    Ok((r_bad, b.get_bad_checksums(), b.get_bad_icmp_checksums(), b_received))
+}
+
+// host A pings host D over a link with a small MTU. the requests are fragmented by router R and
+// the replies by D. then A discovers the path MTU with DF set
+pub fn run_fragmentation() -> Res<(PingStats, usize, PingStats, Vec<PingError>)> {
+    println!("netwl sample. fragmentation and path MTU discovery");
+    let subnet_mask = SubnetMask::new(24);
+
+    let addr_1a = IpAddr::new(0x0a01_0001);
+    let addr_1r = IpAddr::new(0x0a01_0003);
+    let addr_2r = IpAddr::new(0x0a02_0001);
+    let addr_2d = IpAddr::new(0x0a02_0002);
+
+    let mac_a = Mac::new(871);
+    let mac_r = Mac::new(872);
+    let mac_d = Mac::new(873);
+
+    let mut host_a = IpHost::build_echo(mac_a, "host1a", addr_1a, subnet_mask);
+    let mut router_r = Router::build(mac_r, "routeR", vec![addr_1r, addr_2r], subnet_mask);
+    let mut host_d = IpHost::build_echo(mac_d, "host2d", addr_2d, subnet_mask);
+    host_a.add_route(Route::default_route(addr_1r))?;
+    host_d.add_route(Route::default_route(addr_2r))?;
+    router_r.set_mtu(Port::new(1), 100)?;
+    host_d.set_mtu(Port::new(0), 100)?;
+    println!("{}", router_r.show_ip_interface());
+
+    let ping = |count: u16, dont_fragment: bool, data_len: usize| {
+        let mut ping = Ping::new(addr_2d, count, 300, 2000);
+        ping.set_data_len(data_len);
+        ping.set_dont_fragment(dont_fragment);
+        ping
+    };
+    host_a.set_ping(ping(1, false, 200));
+
+    let mut nw = Network::new(vec![host_a, router_r, host_d], vec![]);
+    nw.connect_both(mac_a, Port::new(0), mac_r, Port::new(0))?;
+    nw.connect_both(mac_r, Port::new(1), mac_d, Port::new(0))?;
+    // the first request waits for ARP on every hop
+    nw.run(2000)?;
+
+    // 228 bytes with DF do not pass R
+    let a = nw.get_device(mac_a)?.as_any_mut().downcast_mut::<IpHost>().unwrap();
+    let fragmented = a.get_ping().unwrap().get_stats();
+    println!("{}", a.get_ping().unwrap().dump());
+    a.set_ping(ping(1, true, 200));
+    nw.run(3000)?;
+
+    let a = nw.get_device(mac_a)?.as_any_mut().downcast_mut::<IpHost>().unwrap();
+    let path_mtu = a.get_path_mtu(addr_2d);
+    println!("{}", a.get_ping().unwrap().dump());
+    a.set_ping(ping(1, true, path_mtu - 20 - 8)); // IP and ICMP headers
+    nw.run(4000)?;
+
+    // A knows the path MTU now. its own stack refuses the large requests with DF
+    let a = nw.get_device(mac_a)?.as_any_mut().downcast_mut::<IpHost>().unwrap();
+    let discovered = a.get_ping().unwrap().get_stats();
+    println!("{}", a.get_ping().unwrap().dump());
+    a.set_ping(ping(3, true, 200));
+    nw.run(5000)?;
+
+    let a = nw.get_device(mac_a)?.as_any().downcast_ref::<IpHost>().unwrap();
+    let refused = a.get_ping().unwrap();
+    println!("{}", refused.dump());
+    assert_eq!(0, refused.get_stats().received);
+    Ok((fragmented, path_mtu, discovered, refused.get_errors().clone()))
+}
+
+// host A sends an echo request with record route and timestamp options. routers R and S fill them in
+pub fn run_ip_options() -> Res<(Vec<IpAddr>, Vec<u32>)> {
+    println!("netwl sample. IP options");
+    let subnet_mask = SubnetMask::new(24);
+
+    let addr_1a = IpAddr::new(0x0a01_0001);
+    let addr_1r = IpAddr::new(0x0a01_0003);
+    let addr_2r = IpAddr::new(0x0a02_0001);
+    let addr_2s = IpAddr::new(0x0a02_0002);
+    let addr_3d = IpAddr::new(0x0a03_0002);
+    let addr_3s = IpAddr::new(0x0a03_0003);
+
+    let mac_a = Mac::new(881);
+    let mac_d = Mac::new(882);
+    let mac_r = Mac::new(883);
+    let mac_s = Mac::new(884);
+
+    let mut host_a = IpHost::build_echo(mac_a, "host1a", addr_1a, subnet_mask);
+    let mut host_d = IpHost::build_echo(mac_d, "host3d", addr_3d, subnet_mask);
+    let mut router_r = Router::build(mac_r, "routeR", vec![addr_1r, addr_2r], subnet_mask);
+    let mut router_s = Router::build(mac_s, "routeS", vec![addr_3s, addr_2s], subnet_mask);
+    host_a.add_route(Route::default_route(addr_1r))?;
+    host_d.add_route(Route::default_route(addr_3s))?;
+    router_r.add_route(Route::static_route(addr_3d.nw(subnet_mask), addr_2s))?;
+    router_s.add_route(Route::static_route(addr_1a.nw(subnet_mask), addr_2r))?;
+
+    let mut ip = IP::new_icmp(addr_1a, addr_3d, Icmp::echo_request(1, 0, vec![]));
+    ip.set_options(vec![IpOption::new_record_route(4), IpOption::new_timestamp(4)])?;
+    host_a.add_schedule(0, NetworkProtocol::IP(ip));
+
+    let mut nw = Network::new(vec![host_a, host_d, router_r, router_s], vec![]);
+    nw.connect_both(mac_a, Port::new(0), mac_r, Port::new(0))?;
+    nw.connect_both(mac_r, Port::new(1), mac_s, Port::new(1))?;
+    nw.connect_both(mac_d, Port::new(0), mac_s, Port::new(0))?;
+    nw.run(1000)?;
+
+    let d = nw.get_device(mac_d)?;
+    let d = d.as_any().downcast_ref::<IpHost>().unwrap();
+    let options = d.get_rlog()
+        .iter()
+        .find_map(|log| match &log.p {
+            NetworkProtocol::IP(ip) if !ip.get_options().is_empty() => Some(ip.get_options().clone()),
+            _ => None,
+        })
+        .unwrap();
+    let route = options[0].get_route().unwrap().to_vec();
+    let stamps = options[1].get_stamps().unwrap().to_vec();
+    println!("route: {:?}", route.iter().map(|addr| addr.to_string()).collect::<Vec<_>>());
+    println!("timestamps: {:?}", stamps);
+    Ok((route, stamps))
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::types::Error;
@@ -331,8 +1041,164 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_multicast() {
+        // the report and the data are flooded but dropped by the NIC
+        assert_eq!((1, 3), run_multicast(MulticastMode::Flood).unwrap());
+        // the group has no members after the leave, so the second data is flooded
+        assert_eq!((1, 1), run_multicast(MulticastMode::Snooping).unwrap());
+    }
+
+    #[test]
+    fn test_lldp() {
+        // both ends of the four cables
+        assert_eq!(8, run_lldp().unwrap());
+    }
+
     #[test]
     fn test_router_arp() {
         run_test_router_arp().unwrap();
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_ppp() {
+        // the datagram larger than the MRU of host_d's router is dropped by routeR
+        assert_eq!((vec![200], 1), run_ppp().unwrap());
+    }
+
+    #[test]
+    fn test_routing() {
+        run_routing().unwrap();
+    }
+
+    #[test]
+    fn test_interfaces() {
+        // the ARP reply carries the MAC of the interface on host1a's LAN, not the device MAC
+        assert_eq!(Mac::new(815), run_interfaces().unwrap());
+    }
+
+    #[test]
+    fn test_dynamic_arp() {
+        // the entry expires and the gateway is resolved again
+        assert_eq!(2, run_dynamic_arp(true).unwrap());
+        // routeR gives up on 10.3.0.2 and reports it
+        assert!(matches!(run_dynamic_arp(false), Err(Error::IpUnreashcable { code: 1, .. })));
+    }
+
+    #[test]
+    fn test_duplicate_ip() {
+        let (conflicts, mac) = run_duplicate_ip().unwrap();
+        // hostA defends its address against the probe of hostB
+        assert_eq!(1, conflicts.len());
+        assert_eq!((IpAddr::new(0x0a00_0001), Mac::new(831)), (conflicts[0].ip_addr, conflicts[0].mac));
+        // the announcement of hostC updates the cache of hostA
+        assert_eq!(Some(Mac::new(833)), mac);
+    }
+
+    #[test]
+    fn test_traceroute() {
+        let addrs = |hops: Vec<Hop>| hops.iter().map(|hop| hop.addr.map(|a| a.value)).collect::<Vec<_>>();
+        let hops = run_traceroute(IpAddr::new(0x0a03_0002)).unwrap();
+        assert_eq!(vec![Some(0x0a01_0003), Some(0x0a02_0002), Some(0x0a03_0002)], addrs(hops));
+        // the loop is visible as alternating hops
+        let hops = run_traceroute(IpAddr::new(0x0a09_0001)).unwrap();
+        assert_eq!(vec![Some(0x0a01_0003), Some(0x0a02_0002), Some(0x0a01_0003), Some(0x0a02_0002)], addrs(hops));
+    }
+
+    #[test]
+    fn test_ping() {
+        let stats = run_ping().unwrap();
+        assert_eq!((5, 4, 20), (stats.sent, stats.received, stats.loss_percent()));
+        // the first reply waited for ARP on every hop
+        assert_eq!((Some(324), Some(776)), (stats.min_rtt, stats.max_rtt));
+    }
+
+    #[test]
+    fn test_bad_checksum() {
+        assert_eq!((1, 0, 1, 2), run_bad_checksum().unwrap());
+    }
+
+    #[test]
+    fn test_malformed_ip() {
+        let addr_e = IpAddr::new(0x0a00_0001);
+        let addr_b = IpAddr::new(0x0a00_0002);
+        let mac_e = Mac::new(891);
+        let mac_b = Mac::new(892);
+        let mut host_e = EthernetHost::build_consumer(mac_e, "hostE");
+        let host_b = IpHost::build_echo(mac_b, "hostB", addr_b, SubnetMask::new(24));
+        // a header length of 16 bytes
+        let mut malformed = IP::new_byte(addr_e, addr_b, vec![0x01, 0x02]).encode();
+        malformed[0] = 0x44;
+        host_e.add_schedule(0, EthernetFrame::new(mac_b, mac_e, 0x0800, malformed));
+        // version 6 in a header which sums up
+        let mut wrong_version = IP::new_byte(addr_e, addr_b, vec![0x01, 0x02]).encode();
+        wrong_version[0] += 0x20;
+        wrong_version[8] -= 0x20; // TTL. the sum is kept
+        host_e.add_schedule(50, EthernetFrame::new(mac_b, mac_e, 0x0800, wrong_version));
+        let good = IP::new_byte(addr_e, addr_b, vec![0x03, 0x04]).encode();
+        host_e.add_schedule(100, EthernetFrame::new(mac_b, mac_e, 0x0800, good));
+
+        let mut nw = Network::new(vec![host_e, host_b], vec![]);
+        nw.connect_both(mac_e, Port::new(0), mac_b, Port::new(0)).unwrap();
+        nw.run(300).unwrap();
+
+        // dropped and counted. the run goes on
+        let b = nw.get_device(mac_b).unwrap();
+        let b = b.as_any().downcast_ref::<IpHost>().unwrap();
+        assert_eq!((2, 0), (b.get_rx_errors(), b.get_bad_checksums()));
+        assert_eq!(1, ip_logs(b.get_rlog()).len());
+    }
+
+    #[test]
+    fn test_malformed_arp() {
+        let addr_e = IpAddr::new(0x0a00_0001);
+        let addr_b = IpAddr::new(0x0a00_0002);
+        let mac_e = Mac::new(893);
+        let mac_b = Mac::new(894);
+        let mut host_e = EthernetHost::build_consumer(mac_e, "hostE");
+        let host_b = IpHost::build_echo(mac_b, "hostB", addr_b, SubnetMask::new(24));
+        let request = ARP::new_request(mac_e, addr_e, addr_b).encode();
+        // IPv6 over ARP
+        let mut malformed = request.clone();
+        malformed[2] = 0x86;
+        malformed[3] = 0xDD;
+        host_e.add_schedule(0, EthernetFrame::new(mac_b, mac_e, 0x0806, malformed));
+        let mut unknown = request.clone();
+        unknown[7] = 3;
+        host_e.add_schedule(100, EthernetFrame::new(mac_b, mac_e, 0x0806, unknown));
+        host_e.add_schedule(200, EthernetFrame::new(mac_b, mac_e, 0x0806, request));
+
+        let mut nw = Network::new(vec![host_e, host_b], vec![]);
+        nw.connect_both(mac_e, Port::new(0), mac_b, Port::new(0)).unwrap();
+        nw.run(400).unwrap();
+
+        // dropped and counted. the run goes on
+        let b = nw.get_device(mac_b).unwrap();
+        let b = b.as_any().downcast_ref::<IpHost>().unwrap();
+        assert_eq!(2, b.get_rx_errors());
+    }
+
+    #[test]
+    fn test_fragmentation() {
+        let (fragmented, path_mtu, discovered, refused) = run_fragmentation().unwrap();
+        assert_eq!((1, 1), (fragmented.sent, fragmented.received));
+        assert_eq!(100, path_mtu);
+        assert_eq!((1, 1), (discovered.sent, discovered.received));
+        // every request is reported lost and the run goes on
+        assert_eq!(vec![0, 1, 2], refused.iter().map(|e| e.seq).collect::<Vec<_>>());
+        assert!(refused.iter().all(|e| e.from == IpAddr::new(0x0a01_0001) && e.reason == "frag needed (mtu 100)"));
+
+        // too small for the largest header and 8 bytes of data
+        let mut router = Router::build(Mac::new(1), "router", vec![IpAddr::new(0x0a01_0001)], SubnetMask::new(24));
+        assert!(matches!(router.set_mtu(Port::new(0), 67), Err(Error::InvalidConfig { .. })));
+        assert!(router.set_mtu(Port::new(0), 68).is_ok());
+    }
+
+    #[test]
+    fn test_ip_options() {
+        let (route, stamps) = run_ip_options().unwrap();
+        // the addresses the datagram left R and S from
+        assert_eq!(vec![IpAddr::new(0x0a02_0001), IpAddr::new(0x0a03_0003)], route);
+        assert!(stamps.len() == 2 && stamps[0] < stamps[1]);
+    }
+}
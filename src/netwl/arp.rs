@@ -1,16 +1,16 @@
-use crate::utils::{read_2bytes, read_4bytes};
+use crate::utils::read_2bytes;
 
 use super::super::types::*;
 use super::ip_addr::IpAddr;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct ARP { // total = 24 bytes
+pub struct ARP { // total = 28 bytes for Ethernet and IPv4
     pub hardware_type: u16,
     pub protocol_type: u16,
     pub hardware_size: u8,
     pub protocol_size: u8,
     pub opcode: u16,
-    pub sender_mac: Mac, // u32
+    pub sender_mac: Mac,
     pub sender_ipaddr: IpAddr,
     pub target_mac: Mac,
     pub target_ipaddr: IpAddr,
@@ -31,6 +31,20 @@ impl ARP {
         }
     }
 
+    // RFC 5227 probe. the sender address is unspecified so that no cache learns it
+    pub fn new_probe(sender_mac: Mac, target_ipaddr: IpAddr) -> ARP {
+        ARP::new_request(sender_mac, IpAddr::new(0), target_ipaddr)
+    }
+
+    // gratuitous ARP. the sender asks for its own address
+    pub fn new_announcement(sender_mac: Mac, ip_addr: IpAddr) -> ARP {
+        ARP::new_request(sender_mac, ip_addr, ip_addr)
+    }
+
+    pub fn is_probe(&self) -> bool {
+        self.opcode == 1 && self.sender_ipaddr.value == 0
+    }
+
     pub fn reply(&self, target_mac: Mac) -> ARP {
         ARP {
             hardware_type: self.hardware_type,
@@ -47,7 +61,7 @@ impl ARP {
 
     pub fn decode(xs: &[u8]) -> Res<ARP> {
         let xs = Vec::from(xs);
-        if xs.len() < 24 {
+        if xs.len() < 8 {
             return Err(Error::NotEnoughBytes);
         }
         let mut i = 0;
@@ -59,15 +73,27 @@ impl ARP {
         i += 1;
         let protocol_size = xs[i];
         i += 1;
+        let hlen = hardware_size as usize;
+        let plen = protocol_size as usize;
+        // only Ethernet and IPv4 are supported
+        if hardware_type != 1 || protocol_type != 0x0800 {
+            return Err(Error::InvalidBytes { msg: format!("ARP. hardware_type={}, protocol_type={:#06x}", hardware_type, protocol_type) });
+        }
+        if hlen != 6 || plen != 4 {
+            return Err(Error::InvalidBytes { msg: format!("ARP. hardware_size={}, protocol_size={}", hlen, plen) });
+        }
+        if xs.len() < 8 + 2 * (hlen + plen) {
+            return Err(Error::NotEnoughBytes);
+        }
         let opcode = read_2bytes(&xs, i);
         i += 2;
-        let sender_mac = Mac::new(read_4bytes(&xs, i) as u64);
-        i += 4;
-        let sender_ipaddr = IpAddr::new(read_4bytes(&xs, i));
-        i += 4;
-        let target_mac = Mac::new(read_4bytes(&xs, i) as u64);
-        i += 4;
-        let target_ipaddr = IpAddr::new(read_4bytes(&xs, i));
+        let sender_mac = Mac::new(read_nbytes(&xs, i, hlen));
+        i += hlen;
+        let sender_ipaddr = IpAddr::new(read_nbytes(&xs, i, plen) as u32);
+        i += plen;
+        let target_mac = Mac::new(read_nbytes(&xs, i, hlen));
+        i += hlen;
+        let target_ipaddr = IpAddr::new(read_nbytes(&xs, i, plen) as u32);
 
         Ok(ARP { 
             hardware_type,
@@ -83,24 +109,31 @@ impl ARP {
     }
 
     pub fn encode(&self) -> Vec<u8> {    
+        let hlen = self.hardware_size as usize;
+        let plen = self.protocol_size as usize;
         let mut xs = vec![];
         xs.append(&mut self.hardware_type.to_be_bytes().to_vec());
         xs.append(&mut self.protocol_type.to_be_bytes().to_vec());
         xs.push(self.hardware_size);
         xs.push(self.protocol_size);
         xs.append(&mut self.opcode.to_be_bytes().to_vec());
-        let u = self.sender_mac.value as u32;
-        xs.append(&mut u.to_be_bytes().to_vec());
-        let u = self.sender_ipaddr.value as u32;
-        xs.append(&mut u.to_be_bytes().to_vec());
-        let u = self.target_mac.value as u32;
-        xs.append(&mut u.to_be_bytes().to_vec());
-        let u = self.target_ipaddr.value as u32;
-        xs.append(&mut u.to_be_bytes().to_vec());
+        xs.extend(nbytes(self.sender_mac.value, hlen));
+        xs.extend(nbytes(self.sender_ipaddr.value as u64, plen));
+        xs.extend(nbytes(self.target_mac.value, hlen));
+        xs.extend(nbytes(self.target_ipaddr.value as u64, plen));
         xs
     }
 }
 
+// an address of n bytes, big endian
+fn read_nbytes(xs: &[u8], offset: usize, n: usize) -> u64 {
+    xs[offset..offset + n].iter().fold(0, |x, b| x << 8 | *b as u64)
+}
+
+fn nbytes(x: u64, n: usize) -> Vec<u8> {
+    (0..n).rev().map(|i| (x >> (8 * i)) as u8).collect()
+}
+
 
 impl std::fmt::Display for ARP {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -138,5 +171,22 @@ mod tests {
         let xs = arp.encode();
         let arp2 = ARP::decode(&xs).unwrap();
         assert_eq!(arp, arp2);
+
+        // MACs are 48 bits on the wire
+        let arp = ARP::new_request(Mac::new(0x0A0B_0C0D_0E0F), IpAddr::new(0xC0A8_0001), IpAddr::new(0xC0A8_0002));
+        let xs = arp.encode();
+        assert_eq!(28, xs.len());
+        assert_eq!(vec![0x00, 0x01, 0x08, 0x00, 0x06, 0x04, 0x00, 0x01,
+                        0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F, 0xC0, 0xA8, 0x00, 0x01,
+                        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC0, 0xA8, 0x00, 0x02], xs);
+        assert_eq!(arp, ARP::decode(&xs).unwrap());
+        assert!(matches!(ARP::decode(&xs[..27]), Err(Error::NotEnoughBytes)));
+
+        // anything but Ethernet and IPv4 is rejected
+        for (i, v) in [(1, 6), (3, 0x86), (4, 8), (5, 16)] {
+            let mut ys = xs.clone();
+            ys[i] = v;
+            assert!(matches!(ARP::decode(&ys), Err(Error::InvalidBytes { .. })));
+        }
     }
 }
\ No newline at end of file
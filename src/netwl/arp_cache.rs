@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use crate::types::Mac;
+
+use super::ip_addr::IpAddr;
+use super::network_protocol::NetworkProtocol;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArpConfig {
+    pub timeout_ticks: usize, // a learned entry is forgotten after this
+    pub retry_ticks: usize,   // the first retry. it doubles with every attempt
+    pub max_attempts: usize,
+    pub detect_duplicates: bool, // probe and announce the addresses of the interfaces
+}
+
+impl Default for ArpConfig {
+    fn default() -> ArpConfig {
+        ArpConfig {
+            timeout_ticks: 3000,
+            retry_ticks: 150,
+            max_attempts: 3,
+            detect_duplicates: false,
+        }
+    }
+}
+
+// packets waiting for the MAC of their next hop
+struct Pending {
+    packets: Vec<NetworkProtocol>,
+    attempts: usize,
+    next_retry: usize,
+}
+
+// what the cache wants done after time passed
+#[derive(Default)]
+pub struct ArpEvents {
+    pub requests: Vec<IpAddr>,              // resolve these again
+    pub unresolved: Vec<NetworkProtocol>,   // given up. their senders are told the host is unreachable
+}
+
+// the ARP table of a device. entries added by hand never expire
+pub struct ArpCache {
+    config: ArpConfig,
+    entries: HashMap<IpAddr, Mac>,
+    learned: HashMap<IpAddr, usize>, // when a dynamic entry was learned
+    pending: HashMap<IpAddr, Pending>,
+}
+
+impl ArpCache {
+    pub fn new(config: ArpConfig) -> ArpCache {
+        ArpCache {
+            config,
+            entries: HashMap::new(),
+            learned: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn get_config(&self) -> ArpConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: ArpConfig) {
+        self.config = config;
+    }
+
+    pub fn get(&self, ip_addr: &IpAddr) -> Option<&Mac> {
+        self.entries.get(ip_addr)
+    }
+
+    pub fn get_entries(&self) -> &HashMap<IpAddr, Mac> {
+        &self.entries
+    }
+
+    pub fn add_static(&mut self, ip_addr: IpAddr, mac: Mac) {
+        self.entries.insert(ip_addr, mac);
+        self.learned.remove(&ip_addr);
+    }
+
+    // returns the packets that waited for the address
+    pub fn learn(&mut self, ip_addr: IpAddr, mac: Mac, t: usize) -> Vec<NetworkProtocol> {
+        let is_static = self.entries.contains_key(&ip_addr) && !self.learned.contains_key(&ip_addr);
+        if !is_static {
+            self.entries.insert(ip_addr, mac);
+            self.learned.insert(ip_addr, t);
+        }
+        self.pending.remove(&ip_addr).map_or(vec![], |p| p.packets)
+    }
+
+    // a dynamic entry is updated if it exists
+    pub fn refresh(&mut self, ip_addr: IpAddr, mac: Mac, t: usize) {
+        if self.learned.contains_key(&ip_addr) {
+            self.entries.insert(ip_addr, mac);
+            self.learned.insert(ip_addr, t);
+        }
+    }
+
+    // queue the packet. true if a request has to be sent, i.e. nobody is resolving the address yet
+    pub fn enqueue(&mut self, ip_addr: IpAddr, p: NetworkProtocol, t: usize) -> bool {
+        if let Some(pending) = self.pending.get_mut(&ip_addr) {
+            pending.packets.push(p);
+            return false;
+        }
+        let next_retry = t + self.config.retry_ticks;
+        self.pending.insert(ip_addr, Pending { packets: vec![p], attempts: 1, next_retry });
+        true
+    }
+
+    pub fn update(&mut self, t: usize) -> ArpEvents {
+        let timeout = self.config.timeout_ticks;
+        let expired: Vec<IpAddr> = self.learned
+            .iter()
+            .filter(|(_, t_learned)| t >= **t_learned + timeout)
+            .map(|(ip_addr, _)| *ip_addr)
+            .collect();
+        for ip_addr in expired {
+            self.learned.remove(&ip_addr);
+            self.entries.remove(&ip_addr);
+        }
+
+        let mut events = ArpEvents::default();
+        let mut given_up = vec![];
+        for (ip_addr, pending) in self.pending.iter_mut() {
+            if t < pending.next_retry {
+                continue;
+            }
+            if pending.attempts >= self.config.max_attempts {
+                given_up.push(*ip_addr);
+                continue;
+            }
+            // binary exponential backoff
+            pending.next_retry = t + (self.config.retry_ticks << pending.attempts);
+            pending.attempts += 1;
+            events.requests.push(*ip_addr);
+        }
+        for ip_addr in given_up {
+            events.unresolved.extend(self.pending.remove(&ip_addr).unwrap().packets);
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ip::IP;
+    use super::*;
+
+    #[test]
+    fn test_arp_cache() {
+        let a = IpAddr::new(0x0a00_0001);
+        let b = IpAddr::new(0x0a00_0002);
+        let p = NetworkProtocol::IP(IP::new_byte(a, b, vec![0x01]));
+        let mut cache = ArpCache::new(ArpConfig::default());
+
+        assert!(cache.enqueue(b, p.clone(), 0));
+        assert!(!cache.enqueue(b, p.clone(), 10));
+        // retries at 150 and 150+300, then gives up at 450+600
+        assert_eq!(vec![b], cache.update(150).requests);
+        assert!(cache.update(449).requests.is_empty());
+        assert_eq!(vec![b], cache.update(450).requests);
+        let events = cache.update(1050);
+        assert!(events.requests.is_empty());
+        assert_eq!(2, events.unresolved.len());
+        assert!(cache.update(5000).unresolved.is_empty());
+
+        assert!(cache.enqueue(b, p.clone(), 2000));
+        assert_eq!(vec![p], cache.learn(b, Mac::new(2), 2010));
+        assert_eq!(Some(&Mac::new(2)), cache.get(&b));
+        cache.refresh(b, Mac::new(3), 2020);
+        assert_eq!(Some(&Mac::new(3)), cache.get(&b));
+        cache.refresh(a, Mac::new(3), 2020);
+        assert_eq!(None, cache.get(&a));
+        cache.add_static(a, Mac::new(1));
+        cache.update(5020);
+        assert_eq!(None, cache.get(&b));
+        assert_eq!(Some(&Mac::new(1)), cache.get(&a));
+    }
+}
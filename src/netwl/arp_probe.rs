@@ -0,0 +1,91 @@
+use crate::types::{Mac, Port};
+
+use super::arp::ARP;
+use super::ip_addr::IpAddr;
+
+pub const PROBE_NUM: usize = 2;
+pub const ANNOUNCE_NUM: usize = 2;
+pub const PROBE_INTERVAL: usize = 100; // ticks between the messages
+// after the last probe. a defense has to arrive by then. the probe and the defense each cross
+// a switch store-and-forward, possibly behind the announcements of the other stations
+pub const ANNOUNCE_WAIT: usize = 300;
+
+// another station uses the address of an interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArpConflict {
+    pub t: usize,
+    pub ip_addr: IpAddr,
+    pub mac: Mac, // of the other station
+}
+
+// duplicate address detection of an interface (RFC 5227). probes, then announces the address
+pub struct ArpProbe {
+    pub port: Port,
+    pub ip_addr: IpAddr,
+    probe: bool,
+    sent: usize, // counted as if the probes were sent when only announcing
+    next_t: usize,
+}
+
+impl ArpProbe {
+    pub fn new(port: Port, ip_addr: IpAddr) -> ArpProbe {
+        ArpProbe { port, ip_addr, probe: true, sent: 0, next_t: 0 }
+    }
+
+    // gratuitous ARP only. the address is ours from the start
+    pub fn announce(port: Port, ip_addr: IpAddr) -> ArpProbe {
+        ArpProbe { port, ip_addr, probe: false, sent: PROBE_NUM, next_t: 0 }
+    }
+
+    // until the first announcement the address is not ours yet
+    pub fn is_probing(&self) -> bool {
+        self.probe && self.sent <= PROBE_NUM
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.sent >= PROBE_NUM + ANNOUNCE_NUM
+    }
+
+    pub fn update(&mut self, mac: Mac, t: usize) -> Option<ARP> {
+        if self.is_done() || t < self.next_t {
+            return None;
+        }
+        let arp = if self.sent < PROBE_NUM {
+            ARP::new_probe(mac, self.ip_addr)
+        } else {
+            ARP::new_announcement(mac, self.ip_addr)
+        };
+        self.sent += 1;
+        self.next_t = t + if self.sent == PROBE_NUM { ANNOUNCE_WAIT } else { PROBE_INTERVAL };
+        Some(arp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arp_probe() {
+        let ip_addr = IpAddr::new(0x0a00_0001);
+        let mut probe = ArpProbe::new(Port::new(0), ip_addr);
+        let mut arps = vec![];
+        for t in 0..1000 {
+            arps.extend(probe.update(Mac::new(1), t));
+            if t == PROBE_INTERVAL * (PROBE_NUM - 1) + ANNOUNCE_WAIT - 1 {
+                assert!(probe.is_probing());
+            }
+        }
+        assert!(probe.is_done());
+        assert_eq!(PROBE_NUM + ANNOUNCE_NUM, arps.len());
+        assert!(arps[0].is_probe());
+        assert_eq!(ip_addr, arps[PROBE_NUM].sender_ipaddr);
+        assert_eq!(ip_addr, arps[PROBE_NUM].target_ipaddr);
+
+        let mut announce = ArpProbe::announce(Port::new(0), ip_addr);
+        assert!(!announce.is_probing());
+        let arps: Vec<ARP> = (0..1000).filter_map(|t| announce.update(Mac::new(1), t)).collect();
+        assert_eq!(ANNOUNCE_NUM, arps.len());
+        assert!(arps.iter().all(|arp| !arp.is_probe() && arp.sender_ipaddr == ip_addr));
+    }
+}
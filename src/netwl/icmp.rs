@@ -0,0 +1,162 @@
+use crate::{types::{Res, Error}, utils::{read_2bytes, checksum}};
+
+use super::ip::IP;
+use super::ip_addr::IpAddr;
+
+pub const ICMP_ECHO_REPLY: u8 = 0;
+pub const ICMP_UNREACHABLE: u8 = 3;
+pub const ICMP_ECHO_REQUEST: u8 = 8;
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+
+// codes of destination unreachable
+pub const ICMP_HOST_UNREACHABLE: u8 = 1;
+pub const ICMP_FRAGMENTATION_NEEDED: u8 = 4;
+
+// codes of time exceeded
+pub const ICMP_TTL_EXCEEDED: u8 = 0;
+pub const ICMP_FRAGMENT_REASSEMBLY_EXCEEDED: u8 = 1;
+
+// an error message quotes the header of the datagram, options included, and the first 8 bytes of its payload
+const QUOTED_PAYLOAD_LEN: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Icmp {
+    pub ty: u8,
+    pub code: u8,
+    pub rest: u32, // identifier and sequence number of echo messages. unused by errors
+    pub data: Vec<u8>,
+}
+
+impl Icmp {
+    pub fn new(ty: u8, code: u8, rest: u32, data: Vec<u8>) -> Icmp {
+        Icmp { ty, code, rest, data }
+    }
+
+    pub fn echo_request(identifier: u16, sequence: u16, data: Vec<u8>) -> Icmp {
+        Icmp::new(ICMP_ECHO_REQUEST, 0, (identifier as u32) << 16 | sequence as u32, data)
+    }
+
+    pub fn echo_reply(&self) -> Icmp {
+        Icmp::new(ICMP_ECHO_REPLY, 0, self.rest, self.data.clone())
+    }
+
+    pub fn error(ty: u8, code: u8, original: &IP) -> Icmp {
+        let mut data = original.encode();
+        data.truncate(original.header_len() + QUOTED_PAYLOAD_LEN);
+        Icmp::new(ty, code, 0, data)
+    }
+
+    // the datagram does not fit the next hop and may not be fragmented. tells the MTU of the next hop
+    pub fn fragmentation_needed(mtu: usize, original: &IP) -> Icmp {
+        let mut icmp = Icmp::error(ICMP_UNREACHABLE, ICMP_FRAGMENTATION_NEEDED, original);
+        icmp.rest = mtu as u32 & 0xFFFF;
+        icmp
+    }
+
+    pub fn get_next_hop_mtu(&self) -> usize {
+        (self.rest & 0xFFFF) as usize
+    }
+
+    // the destination of the datagram an error is about
+    pub fn get_original_dst(&self) -> Option<IpAddr> {
+        let xs = self.data.get(16..20)?;
+        Some(IpAddr::new(u32::from_be_bytes([xs[0], xs[1], xs[2], xs[3]])))
+    }
+
+    // the first bytes of the payload of the datagram an error is about
+    pub fn get_original_payload(&self) -> Option<&[u8]> {
+        let ihl = (*self.data.first()? & 0x0F) as usize * 4;
+        self.data.get(ihl..)
+    }
+
+    // no error is sent about these
+    pub fn is_error(&self) -> bool {
+        matches!(self.ty, 3 | 4 | 5 | 11 | 12)
+    }
+
+    pub fn get_identifier(&self) -> u16 {
+        (self.rest >> 16) as u16
+    }
+
+    pub fn get_sequence(&self) -> u16 {
+        self.rest as u16
+    }
+
+    pub fn len(&self) -> usize {
+        8 + self.data.len()
+    }
+
+    pub fn decode(xs: &[u8]) -> Res<Icmp> {
+        let xs = Vec::from(xs);
+        if xs.len() < 8 {
+            return Err(Error::NotEnoughBytes);
+        }
+        if checksum(&xs) != 0 {
+            return Err(Error::InvalidBytes { msg: "ICMP checksum".to_string() });
+        }
+        let rest = (read_2bytes(&xs, 4) as u32) << 16 | read_2bytes(&xs, 6) as u32;
+        Ok(Icmp::new(xs[0], xs[1], rest, xs[8..].to_vec()))
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut xs = vec![self.ty, self.code, 0, 0];
+        xs.extend_from_slice(&self.rest.to_be_bytes());
+        xs.extend_from_slice(&self.data);
+        let sum = checksum(&xs).to_be_bytes();
+        xs[2] = sum[0];
+        xs[3] = sum[1];
+        xs
+    }
+}
+
+impl std::fmt::Display for Icmp {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.ty {
+            ICMP_ECHO_REQUEST | ICMP_ECHO_REPLY =>
+                write!(f, "ICMP[type:{:0>2X}, code:{:0>2X}, id:{}, seq:{}]",
+                       self.ty, self.code, self.get_identifier(), self.get_sequence()),
+            _ => write!(f, "ICMP[type:{:0>2X}, code:{:0>2X}]", self.ty, self.code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ip_addr::IpAddr;
+    use super::super::ip_option::IpOption;
+    use super::*;
+
+    #[test]
+    fn test_icmp() {
+        let request = Icmp::echo_request(0x1234, 7, vec![0x61, 0x62, 0x63]);
+        let xs = request.encode();
+        assert_eq!(11, xs.len());
+        assert_eq!(request, Icmp::decode(&xs).unwrap());
+        let reply = request.echo_reply();
+        assert_eq!((0x1234, 7), (reply.get_identifier(), reply.get_sequence()));
+
+        let mut ys = xs.clone();
+        ys[9] ^= 0x01;
+        assert!(Icmp::decode(&ys).is_err());
+
+        let ip = IP::new_byte(IpAddr::new(0x0a00_0001), IpAddr::new(0x0a00_0002), vec![0; 100]);
+        let error = Icmp::error(ICMP_TIME_EXCEEDED, 0, &ip);
+        assert!(error.is_error());
+        assert_eq!(ip.encode()[..28], error.data[..]);
+
+        let error = Icmp::fragmentation_needed(576, &ip);
+        let error = Icmp::decode(&error.encode()).unwrap();
+        assert_eq!((ICMP_UNREACHABLE, ICMP_FRAGMENTATION_NEEDED), (error.ty, error.code));
+        assert_eq!(576, error.get_next_hop_mtu());
+        assert_eq!(Some(ip.dst), error.get_original_dst());
+        assert_eq!(Some(&[0u8; 8][..]), error.get_original_payload());
+
+        // the options are quoted along with the header
+        let mut ip = IP::new_byte(IpAddr::new(0x0a00_0001), IpAddr::new(0x0a00_0002), (0..100).collect());
+        ip.set_options(vec![IpOption::new_record_route(9)]).unwrap();
+        let error = Icmp::error(ICMP_TIME_EXCEEDED, 0, &ip);
+        assert_eq!(ip.encode()[..68], error.data[..]);
+        assert_eq!(Some(ip.dst), error.get_original_dst());
+        assert_eq!(Some(&[0, 1, 2, 3, 4, 5, 6, 7][..]), error.get_original_payload());
+    }
+}
@@ -0,0 +1,54 @@
+use crate::types::{Mac, Port};
+
+use super::ip_addr::{IpAddr, NetworkPart, SubnetMask};
+
+pub const DEFAULT_MTU: usize = 1500;
+// RFC 791. the largest header and the smallest fragment fit
+pub const MIN_MTU: usize = 68;
+
+// an addressed port of an IP device. each interface has its own network and MAC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interface {
+    pub port: Port,
+    pub ip_addr: IpAddr,
+    pub subnet_mask: SubnetMask,
+    pub mac: Mac,
+    pub mtu: usize,
+}
+
+impl Interface {
+    pub fn new(port: Port, ip_addr: IpAddr, prefix: u8, mac: Mac) -> Interface {
+        Interface {
+            port,
+            ip_addr,
+            subnet_mask: SubnetMask::new(prefix),
+            mac,
+            mtu: DEFAULT_MTU,
+        }
+    }
+
+    pub fn nw(&self) -> NetworkPart {
+        self.ip_addr.nw(self.subnet_mask)
+    }
+}
+
+impl std::fmt::Display for Interface {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "port {:<3} {}/{:<3} mac {:<6} mtu {}",
+               self.port.value, self.ip_addr, self.subnet_mask.prefix, self.mac.value, self.mtu)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interface() {
+        let interface = Interface::new(Port::new(1), IpAddr::new(0x0a02_0001), 30, Mac::new(765));
+        assert_eq!(NetworkPart::new(IpAddr::new(0x0a02_0000), SubnetMask::new(30)), interface.nw());
+        assert!(interface.nw().contains(IpAddr::new(0x0a02_0002)));
+        assert!(!interface.nw().contains(IpAddr::new(0x0a02_0004)));
+        assert_eq!("port 1   10.2.0.1/30  mac 765    mtu 1500", interface.to_string());
+    }
+}
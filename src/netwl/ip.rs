@@ -1,6 +1,13 @@
-use crate::{types::{Res, Error}, utils::read_2bytes};
+use crate::{types::{Res, Error}, utils::{read_2bytes, checksum}};
 
 use super::ip_addr::IpAddr;
+use super::icmp::Icmp;
+use super::ip_option::IpOption;
+
+const FLAG_DF: u16 = 0x4000; // don't fragment
+const FLAG_MF: u16 = 0x2000; // more fragments
+const OFFSET_MASK: u16 = 0x1FFF; // in units of 8 bytes
+const MAX_OPTIONS_LEN: usize = 40;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct IP {
@@ -15,12 +22,13 @@ pub struct IP {
     checksum: u16,
     pub src: IpAddr,
     pub dst: IpAddr,
+    options: Vec<IpOption>,
     pub payload: IpPayload,
 }
 
 impl IP {
     pub fn new_byte(src: IpAddr, dst: IpAddr, payload: Vec<u8>) -> IP {
-        IP { 
+        let mut ip = IP { 
             version_ihl: 0x45,
             tos: 0,
             total_length: 20 + payload.len() as u16,
@@ -31,16 +39,35 @@ impl IP {
             checksum: 0,
             src, 
             dst,
+            options: vec![],
             payload: IpPayload::Bytes(payload),
-         }
+        };
+        ip.update_checksum();
+        ip
     }
 
-    pub fn new_icmp(src: IpAddr, dst: IpAddr, ty: u8, code: u8) -> IP {
-        IP::new(src, dst, IpPayload::ICMP{ty, code})
+    pub fn new_icmp(src: IpAddr, dst: IpAddr, icmp: Icmp) -> IP {
+        IP::new(src, dst, IpPayload::ICMP(icmp))
+    }
+
+    // IGMPv2 membership report (0x16) or leave group (0x17)
+    pub fn new_igmp(src: IpAddr, dst: IpAddr, ty: u8, group: IpAddr) -> IP {
+        let mut igmp = vec![ty, 0, 0, 0];
+        igmp.extend_from_slice(&group.value.to_be_bytes());
+        let sum = checksum(&igmp).to_be_bytes();
+        igmp[2] = sum[0];
+        igmp[3] = sum[1];
+        let mut ip = IP {
+            ttl: 1,
+            protocol: 2,
+            ..IP::new_byte(src, dst, igmp)
+        };
+        ip.update_checksum();
+        ip
     }
 
     pub fn new(src: IpAddr, dst: IpAddr, payload: IpPayload) -> IP {
-        IP { 
+        let mut ip = IP { 
             version_ihl: 0x45,
             tos: 0,
             total_length: 20 + payload.len() as u16,
@@ -51,13 +78,49 @@ impl IP {
             checksum: 0,
             src, 
             dst,
+            options: vec![],
             payload,
-         }        
+        };
+        ip.update_checksum();
+        ip
+    }
+
+    // true if the header sums to zero together with its checksum
+    pub fn verify_checksum(xs: &[u8]) -> bool {
+        let header_len = xs.first().map_or(0, |x| (x & 0x0F) as usize * 4);
+        header_len >= 20 && xs.len() >= header_len && checksum(&xs[..header_len]) == 0
+    }
+
+    // the header has a valid length but does not sum to zero. it was damaged on the way
+    pub fn has_bad_checksum(xs: &[u8]) -> bool {
+        let header_len = xs.first().map_or(0, |x| (x & 0x0F) as usize * 4);
+        header_len >= 20 && xs.len() >= header_len && checksum(&xs[..header_len]) != 0
     }
 
+    // bytes after total_length are padding of the link and dropped
     pub fn decode(xs: &[u8]) -> Res<IP> {
         let xs = Vec::from(xs);
-        if xs.len() < 8 {
+        if xs.len() < 20 {
+            return Err(Error::NotEnoughBytes);
+        }
+        if xs[0] >> 4 != 4 {
+            return Err(Error::InvalidBytes { msg: format!("IP version {}", xs[0] >> 4) });
+        }
+        let header_len = (xs[0] & 0x0F) as usize * 4;
+        if header_len < 20 {
+            return Err(Error::InvalidBytes { msg: format!("IP header length {}", header_len) });
+        }
+        if xs.len() < header_len {
+            return Err(Error::NotEnoughBytes);
+        }
+        if !IP::verify_checksum(&xs) {
+            return Err(Error::InvalidBytes { msg: "IP header checksum".to_string() });
+        }
+        let total_len = read_2bytes(&xs, 2) as usize;
+        if total_len < header_len {
+            return Err(Error::InvalidBytes { msg: format!("IP total length {} shorter than the header", total_len) });
+        }
+        if xs.len() < total_len {
             return Err(Error::NotEnoughBytes);
         }
         let mut i = 0;
@@ -74,10 +137,13 @@ impl IP {
         i += 4;
         let dst = IpAddr::new((xs[i] as u32) << 24 | (xs[i+1] as u32) << 16 | (xs[i+2] as u32) << 8 | (xs[i+3] as u32));
         i += 4;
-        let payload= if protocol == 1 {
-            IpPayload::ICMP { ty: xs[i], code: xs[i+1] }
+        let options = IpOption::decode_all(&xs[i..header_len])?;
+        let i = header_len;
+        let is_fragment = flags_fragment_offset & (FLAG_MF | OFFSET_MASK) != 0;
+        let payload = if is_fragment {
+            IpPayload::Bytes(xs[i..total_len].to_vec())
         } else {
-            IpPayload::Bytes(xs[i..].to_vec())
+            IpPayload::decode(protocol, &xs[i..total_len])
         };
         Ok(IP { 
             version_ihl,
@@ -90,11 +156,27 @@ impl IP {
             checksum,
             src,
             dst,
+            options,
             payload,
         })
     }
 
     pub fn encode(&self) -> Vec<u8> {
+        let mut xs = self.encode_header();
+        match &self.payload {
+            IpPayload::Bytes(xs2) => {
+                for x in xs2 {
+                    xs.push(*x);
+                }
+            },
+            IpPayload::ICMP(icmp) => {
+                xs.append(&mut icmp.encode());
+            }
+        }
+        xs
+    }
+
+    fn encode_header(&self) -> Vec<u8> {
         let mut xs = vec![];
         xs.push(self.version_ihl);
         xs.push(self.tos);
@@ -112,24 +194,163 @@ impl IP {
         xs.push((self.dst.value >> 16) as u8);
         xs.push((self.dst.value >> 8) as u8);
         xs.push(self.dst.value as u8);
-        match &self.payload {
-            IpPayload::Bytes(xs2) => {
-                for x in xs2 {
-                    xs.push(*x);
-                }
-            },
-            IpPayload::ICMP { ty, code } => {
-                xs.push(*ty);
-                xs.push(*code);
-            }
-        }
+        xs.append(&mut IpOption::encode_all(&self.options));
         xs
     }
 
+    pub fn header_len(&self) -> usize {
+        (self.version_ihl & 0x0F) as usize * 4
+    }
+
+    // to be called whenever the options or the payload change
+    fn update_lengths(&mut self) {
+        let header_len = 20 + IpOption::encode_all(&self.options).len();
+        self.version_ihl = 0x40 | (header_len / 4) as u8;
+        self.total_length = (header_len + self.payload.len()) as u16;
+        self.update_checksum();
+    }
+
+    pub fn get_options(&self) -> &Vec<IpOption> {
+        &self.options
+    }
+
+    pub fn set_options(&mut self, options: Vec<IpOption>) -> Res<()> {
+        if IpOption::encode_all(&options).len() > MAX_OPTIONS_LEN {
+            return Err(Error::InvalidBytes { msg: "IP options longer than 40 bytes".to_string() });
+        }
+        self.options = options;
+        self.update_lengths();
+        Ok(())
+    }
+
+    // a router forwards the datagram from the address at time t
+    pub fn record_options(&mut self, addr: IpAddr, t: usize) {
+        if self.options.is_empty() {
+            return;
+        }
+        for option in self.options.iter_mut() {
+            option.record(addr, t);
+        }
+        self.update_checksum();
+    }
+
+    // to be called whenever a header field changes
+    fn update_checksum(&mut self) {
+        self.checksum = 0;
+        self.checksum = checksum(&self.encode_header());
+    }
+
+    pub fn get_protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    pub fn get_id(&self) -> u16 {
+        self.id
+    }
+
+    pub fn set_id(&mut self, id: u16) {
+        self.id = id;
+        self.update_checksum();
+    }
+
+    pub fn is_dont_fragment(&self) -> bool {
+        self.flags_fragment_offset & FLAG_DF != 0
+    }
+
+    pub fn set_dont_fragment(&mut self, df: bool) {
+        if df {
+            self.flags_fragment_offset |= FLAG_DF;
+        } else {
+            self.flags_fragment_offset &= !FLAG_DF;
+        }
+        self.update_checksum();
+    }
+
+    pub fn has_more_fragments(&self) -> bool {
+        self.flags_fragment_offset & FLAG_MF != 0
+    }
+
+    // in bytes
+    pub fn get_fragment_offset(&self) -> usize {
+        (self.flags_fragment_offset & OFFSET_MASK) as usize * 8
+    }
+
+    // an ICMP message which failed to decode, e.g. for its checksum. only its destination drops it
+    pub fn is_damaged_icmp(&self) -> bool {
+        self.protocol == 1 && !self.is_fragment() && matches!(self.payload, IpPayload::Bytes(_))
+    }
+
+    pub fn is_fragment(&self) -> bool {
+        self.has_more_fragments() || self.get_fragment_offset() != 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.total_length as usize
+    }
+
+    // split into datagrams of at most mtu bytes. None if the DF bit forbids it
+    // or the mtu cannot hold the header and 8 bytes of data
+    pub fn fragment(&self, mtu: usize) -> Option<Vec<IP>> {
+        if self.len() <= mtu {
+            return Some(vec![self.clone()]);
+        }
+        if self.is_dont_fragment() || mtu < self.header_len() + 8 {
+            return None;
+        }
+        let bytes = self.payload_as_bytes();
+        let chunk_len = (mtu - self.header_len()) / 8 * 8;
+        let chunks: Vec<&[u8]> = bytes.chunks(chunk_len).collect();
+        let fragments = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let last = i + 1 == chunks.len();
+                let offset = self.get_fragment_offset() + i * chunk_len;
+                let mf = if last { self.flags_fragment_offset & FLAG_MF } else { FLAG_MF };
+                // only the first fragment carries every option
+                let options = if i == 0 {
+                    self.options.clone()
+                } else {
+                    self.options.iter().filter(|o| o.is_copied()).cloned().collect()
+                };
+                let mut ip = IP {
+                    flags_fragment_offset: (self.flags_fragment_offset & FLAG_DF) | mf | (offset / 8) as u16,
+                    options,
+                    payload: IpPayload::Bytes(chunk.to_vec()),
+                    ..self.clone()
+                };
+                ip.update_lengths();
+                ip
+            })
+            .collect();
+        Some(fragments)
+    }
+
+    // the datagram the fragments were cut from. header is the first fragment
+    pub fn reassemble(header: &IP, bytes: Vec<u8>) -> IP {
+        let payload = IpPayload::decode(header.protocol, &bytes);
+        let mut ip = IP {
+            flags_fragment_offset: header.flags_fragment_offset & FLAG_DF,
+            payload,
+            ..header.clone()
+        };
+        ip.update_lengths();
+        ip
+    }
+
+    pub fn get_ttl(&self) -> u8 {
+        self.ttl
+    }
+
+    pub fn set_ttl(&mut self, ttl: u8) {
+        self.ttl = ttl;
+        self.update_checksum();
+    }
+
     pub fn payload_as_bytes(&self) -> Vec<u8> {
         match &self.payload {
             IpPayload::Bytes(xs) => xs.clone(),
-            IpPayload::ICMP { ty, code } => vec![ty.clone(), code.clone()],
+            IpPayload::ICMP(icmp) => icmp.encode(),
         }
     }
 }
@@ -146,9 +367,7 @@ impl std::fmt::Display for IP {
                 payload = format!("{}]", payload);
                 payload
             },
-            IpPayload::ICMP { ty, code } => {
-                format!("ICMP[type:{:0>2X}, code:{:0>2X}]", ty, code)
-            }
+            IpPayload::ICMP(icmp) => icmp.to_string(),
         };
         
         write!(f, "IP(dst:{}, src:{}, payload:{})", self.dst, self.src, payload)
@@ -158,21 +377,29 @@ impl std::fmt::Display for IP {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum IpPayload {
     Bytes(Vec<u8>),
-    ICMP { ty: u8, code: u8 },
+    ICMP(Icmp),
 }
 
 impl IpPayload {
+    // a damaged ICMP message is kept as bytes. routers forward it untouched
+    fn decode(protocol: u8, xs: &[u8]) -> IpPayload {
+        match protocol {
+            1 => Icmp::decode(xs).map_or_else(|_| IpPayload::Bytes(xs.to_vec()), IpPayload::ICMP),
+            _ => IpPayload::Bytes(xs.to_vec()),
+        }
+    }
+
     pub fn len(&self) -> usize {
         match self {
             IpPayload::Bytes(xs) => xs.len(),
-            IpPayload::ICMP { ty: _, code: _ } => 2,
+            IpPayload::ICMP(icmp) => icmp.len(),
         }
     }
 
     fn protocol(&self) -> u8 {
         match self {
             IpPayload::Bytes(_) => 0, // it is true
-            IpPayload::ICMP { ty: _, code: _ } => 1,
+            IpPayload::ICMP(_) => 1,
         }
     }
 }
@@ -185,12 +412,96 @@ mod tests {
     fn test_ip() {
         let ips = vec![
             IP::new_byte(IpAddr::new(0x0a000001), IpAddr::new(0x0a000002), vec![0x01, 0x02, 0x03]),
-            IP::new(IpAddr::new(0x0a000001), IpAddr::new(0x0a000002), IpPayload::ICMP{ty:2, code:3})
+            IP::new_icmp(IpAddr::new(0x0a000001), IpAddr::new(0x0a000002), Icmp::echo_request(1, 2, vec![0x03])),
+            IP::new_igmp(IpAddr::new(0x0a000001), IpAddr::new(0xEF010101), 0x16, IpAddr::new(0xEF010101)),
         ];
         for ip in ips {
-            let xs = ip.encode();
+            let mut xs = ip.encode();
             let ip2 = IP::decode(&xs).unwrap();
             assert_eq!(ip, ip2);
+            xs[8] -= 1; // TTL
+            assert!(IP::decode(&xs).is_err());
+        }
+
+        let mut ip = IP::new_byte(IpAddr::new(0x0a000001), IpAddr::new(0x0a000002), vec![0x01]);
+        ip.set_ttl(ip.get_ttl() - 1);
+        assert!(IP::verify_checksum(&ip.encode()));
+    }
+
+    #[test]
+    fn test_ip_decode() {
+        let mut ip = IP::new_byte(IpAddr::new(0x0a000001), IpAddr::new(0x0a000002), vec![0x01, 0x02, 0x03]);
+        ip.set_options(vec![IpOption::new_record_route(2), IpOption::new_timestamp(1)]).unwrap();
+        ip.record_options(IpAddr::new(0x0a000003), 5);
+        let mut xs = ip.encode();
+        assert_eq!((0x4A, 43), (xs[0], ip.len()));
+        // the padding of a short Ethernet frame
+        xs.extend_from_slice(&[0; 3]);
+        let ip2 = IP::decode(&xs).unwrap();
+        assert_eq!(ip, ip2);
+        assert_eq!(Some(&[IpAddr::new(0x0a000003)][..]), ip2.get_options()[0].get_route());
+        assert_eq!(Some(&[5][..]), ip2.get_options()[1].get_stamps());
+        assert!(ip.clone().set_options(vec![IpOption::new_record_route(10)]).is_err());
+
+        let xs = ip.encode();
+        assert!(matches!(IP::decode(&xs[..12]), Err(Error::NotEnoughBytes)));
+        assert!(matches!(IP::decode(&xs[..30]), Err(Error::NotEnoughBytes)));
+        assert!(matches!(IP::decode(&xs[..42]), Err(Error::NotEnoughBytes)));
+        // with the checksum fixed up so that only the broken field is noticed
+        let broken = |i: usize, x: u8| {
+            let mut ys = xs.clone();
+            ys[i] = x;
+            let header_len = (ys[0] & 0x0F) as usize * 4;
+            ys[10] = 0;
+            ys[11] = 0;
+            let sum = checksum(&ys[..header_len.max(20)]).to_be_bytes();
+            ys[10] = sum[0];
+            ys[11] = sum[1];
+            IP::decode(&ys)
+        };
+        assert!(matches!(broken(0, 0x6A), Err(Error::InvalidBytes { .. })));
+        assert!(matches!(broken(0, 0x44), Err(Error::InvalidBytes { .. })));
+        assert!(matches!(broken(3, 30), Err(Error::InvalidBytes { .. })));
+        assert!(matches!(broken(21, 0), Err(Error::InvalidBytes { .. }))); // option length
+
+        // the ICMP checksum is left to the destination
+        let ip = IP::new_icmp(IpAddr::new(0x0a000001), IpAddr::new(0x0a000002), Icmp::echo_request(1, 2, vec![0x01]));
+        let mut xs = ip.encode();
+        xs[28] ^= 0x01;
+        let ip2 = IP::decode(&xs).unwrap();
+        assert!(ip2.is_damaged_icmp() && !ip.is_damaged_icmp());
+        assert_eq!(xs, ip2.encode());
+    }
+
+    #[test]
+    fn test_fragment() {
+        let icmp = Icmp::echo_request(1, 2, (0..100).collect());
+        let mut ip = IP::new_icmp(IpAddr::new(0x0a000001), IpAddr::new(0x0a000002), icmp);
+        ip.set_id(7);
+        assert_eq!(128, ip.len());
+        let fragments = ip.fragment(60).unwrap();
+        // 40 + 40 + 28 bytes of the ICMP message
+        assert_eq!(vec![60, 60, 48], fragments.iter().map(|f| f.len()).collect::<Vec<_>>());
+        assert_eq!(vec![0, 40, 80], fragments.iter().map(|f| f.get_fragment_offset()).collect::<Vec<_>>());
+        assert_eq!(vec![true, true, false], fragments.iter().map(|f| f.has_more_fragments()).collect::<Vec<_>>());
+        for f in &fragments {
+            assert_eq!(*f, IP::decode(&f.encode()).unwrap());
         }
+        // a fragment is cut again by the next router
+        let again = fragments[0].fragment(36).unwrap();
+        assert_eq!(vec![(0, true), (16, true), (32, true)],
+                   again.iter().map(|f| (f.get_fragment_offset(), f.has_more_fragments())).collect::<Vec<_>>());
+
+        let bytes = fragments.iter().flat_map(|f| f.payload_as_bytes()).collect();
+        assert_eq!(ip, IP::reassemble(&fragments[0], bytes));
+
+        // too small for the header and 8 bytes of data
+        assert!(ip.fragment(28).is_some());
+        assert!(ip.fragment(27).is_none());
+        assert!(ip.fragment(0).is_none());
+
+        ip.set_dont_fragment(true);
+        assert!(ip.fragment(60).is_none());
+        assert!(ip.fragment(128).is_some());
     }
 }
\ No newline at end of file
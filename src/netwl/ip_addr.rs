@@ -1,3 +1,5 @@
+use crate::{types::Mac, linkl::ipv4_multicast_mac};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct IpAddr {
     pub value: u32,
@@ -11,6 +13,15 @@ impl IpAddr {
     pub fn nw(&self, subnet_mask: SubnetMask) -> NetworkPart {
         NetworkPart::new(*self, subnet_mask)
     }
+
+    // 224.0.0.0/4
+    pub fn is_multicast(&self) -> bool {
+        self.value >> 28 == 0xE
+    }
+
+    pub fn multicast_mac(&self) -> Mac {
+        ipv4_multicast_mac(self.value)
+    }
 }
 
 impl std::fmt::Display for IpAddr {
@@ -66,7 +77,17 @@ impl NetworkPart {
             prefix: subnet_mask.prefix,
         }
     }
-    // pub fn get_value(&self) -> u32 { self.value }
+    pub fn get_value(&self) -> u32 {
+        self.value
+    }
+
+    pub fn get_prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    pub fn contains(&self, ip_addr: IpAddr) -> bool {
+        ip_addr.value & SubnetMask::new(self.prefix).value == self.value
+    }
 }
 
 impl std::fmt::Display for NetworkPart {
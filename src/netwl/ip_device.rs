@@ -1,52 +1,89 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use super::super::physl::Device;
 
 use super::super::types::*;
-use super::super::linkl::{BaseEthernetDevice, EthernetFrame, MAC_BROADCAST};
+use super::super::linkl::{BaseEthernetDevice, EthernetFrame, Lldp, LldpConfig, PppConfig, PppLink, MAC_BROADCAST};
 use super::{network_protocol::*, NetworkLog};
 use super::ip::*;
+use super::icmp::*;
 use super::arp::*;
+use super::arp_cache::*;
+use super::arp_probe::*;
 use super::ip_addr::*;
+use super::interface::*;
+use super::reassembly::*;
+use super::routing_table::*;
 
 pub struct BaseIpDevice {
     pub base: BaseEthernetDevice,
 
     // rbuf: VecDeque<NetworkProtocol>,
     // sbuf: VecDeque<Protocol>,
-    pub subnet_mask: SubnetMask,
-    pub ip_addr_ports: Vec<(IpAddr, Port)>,
-    pub routing_table: HashMap<NetworkPart, IpAddr>,
-    pub arp_table: HashMap<IpAddr, Mac>,
+    pub interfaces: Vec<Interface>,
+    pub routing_table: RoutingTable,
+    pub arp_table: ArpCache,
+    probes: Vec<ArpProbe>,
+    conflicts: Vec<ArpConflict>,
+    groups: Vec<IpAddr>,
+    outbox: VecDeque<NetworkProtocol>, // sent at the next update
+    inbox: VecDeque<NetworkProtocol>, // errors about our own datagrams. handled at the next update as if received
+    bad_checksums: usize, // datagrams dropped for a damaged header
+    bad_icmp_checksums: usize, // ICMP messages for us dropped for a damaged checksum
+    rx_errors: usize, // malformed datagrams dropped, e.g. for their version or lengths
+    reassembly: Reassembly,
+    path_mtu: HashMap<IpAddr, usize>, // learned from "fragmentation needed"
+    next_id: u16,
 
     slog: Vec<NetworkLog>,
     rlog: Vec<NetworkLog>,
 }
 
 impl BaseIpDevice {
+    // every port shares the MAC of the device and the subnet mask
     pub fn new(mac: Mac, name: &str, ip_addr_list: Vec<IpAddr>, subnet_mask: SubnetMask) -> BaseIpDevice {
-        let ip_addr_ports: Vec<(IpAddr, Port)> = ip_addr_list
+        let interfaces: Vec<Interface> = ip_addr_list
             .into_iter()
             .enumerate()
             .map(|(i, ip_addr)| {
-                (ip_addr, Port::new(i as u32))
+                Interface::new(Port::new(i as u32), ip_addr, subnet_mask.prefix, mac)
             })
             .collect();
-        // let num_ports = ip_addr_ports.len();
-        let base = BaseEthernetDevice::new(mac, name, ip_addr_ports.len());
+        BaseIpDevice::with_interfaces(mac, name, interfaces)
+    }
+
+    pub fn with_interfaces(mac: Mac, name: &str, interfaces: Vec<Interface>) -> BaseIpDevice {
+        let num_ports = interfaces.iter().map(|i| i.port.value as usize + 1).max().unwrap_or(0);
+        let base = BaseEthernetDevice::new(mac, name, num_ports);
+        let mut routing_table = RoutingTable::new();
+        for interface in &interfaces {
+            routing_table.add(Route::connected(interface.nw(), interface.port));
+        }
+        // gratuitous ARP on startup
+        let probes = interfaces.iter().map(|i| ArpProbe::announce(i.port, i.ip_addr)).collect();
         let device = BaseIpDevice {
             base,
-            subnet_mask,
-            ip_addr_ports: ip_addr_ports,
-            routing_table: HashMap::new(),
-            arp_table: HashMap::new(),
+            interfaces,
+            routing_table,
+            arp_table: ArpCache::new(ArpConfig::default()),
+            probes,
+            conflicts: Vec::new(),
+            groups: Vec::new(),
+            outbox: VecDeque::new(),
+            inbox: VecDeque::new(),
+            bad_checksums: 0,
+            bad_icmp_checksums: 0,
+            rx_errors: 0,
+            reassembly: Reassembly::new(REASSEMBLY_TIMEOUT),
+            path_mtu: HashMap::new(),
+            next_id: 1,
             slog: Vec::new(),
             rlog: Vec::new(),
         };
         device
     }
 
-    fn recv_frame(&mut self, ctx: &UpdateContext) -> Option<EthernetFrame> {
-        self.base.recv(ctx)
+    fn recv_frame(&mut self, ctx: &UpdateContext) -> Option<(Port, EthernetFrame)> {
+        self.base.recv_from(ctx)
     }
 
     fn send_frame(&mut self, frame: EthernetFrame, ctx: &UpdateContext) {
@@ -54,75 +91,347 @@ impl BaseIpDevice {
     }
 
     fn find_next_mac(&self, ip_addr: IpAddr) -> Res<Mac> {
-        // FIXME: default routing
-        let nw_part = NetworkPart::new(ip_addr, self.subnet_mask);
-        if let Some(_port) = self.find_port(&nw_part) {
-            // dst is in same network
-            if let Some(mac) = self.arp_table.get(&ip_addr) {
-                Ok(*mac)
-            } else {
-                panic!("failed to find in arp table. ip.dst={}", ip_addr);
-            }
-        } else if let Some(next_ip_addr) = self.routing_table.get(&nw_part) {
-            if let Some(dst_mac) = self.arp_table.get(next_ip_addr) {
-                Ok(*dst_mac)
-            } else {
-                panic!("failed to find in arp table");
+        if ip_addr.is_multicast() {
+            return Ok(ip_addr.multicast_mac());
+        }
+        match self.routing_table.resolve(ip_addr) {
+            Some((_, next_ip_addr)) => {
+                self.arp_table.get(&next_ip_addr).copied().ok_or(Error::MacNotFailed)
             }
-        } else {
-            Err(Error::MacNotFailed)
+            None => Err(Error::MacNotFailed),
         }
     }
 
+    // the port the datagram leaves from if it is a serial link. such links need no ARP
+    fn find_ppp_port(&self, ip_addr: IpAddr) -> Option<Port> {
+        let (port, _) = self.routing_table.resolve(ip_addr)?;
+        self.base.get_ppp(port).map(|_| port)
+    }
+
     fn is_for_me(&self, ip_addr: &IpAddr) -> bool {
-        self.ip_addr_ports
-        .iter()
-        .any(|(ip, _)| *ip == *ip_addr)
+        (self.find_interface(*ip_addr).is_some() && !self.is_tentative(*ip_addr))
+        || self.groups.contains(ip_addr)
+    }
+
+    // the address is still being probed. it is neither answered for nor used as a source
+    fn is_tentative(&self, ip_addr: IpAddr) -> bool {
+        self.probes.iter().any(|p| p.ip_addr == ip_addr && p.is_probing())
     }
 
-    fn find_port(&self, nw_part: &NetworkPart) -> Option<Port> {
-        self.ip_addr_ports
-        .iter()
-        .find(|(ip_addr, _)| {
-            NetworkPart::new(*ip_addr, self.subnet_mask) == *nw_part
-        })
-        .map(|(_, port)| *port)
+    // the interface owning the address
+    fn find_interface(&self, ip_addr: IpAddr) -> Option<&Interface> {
+        self.interfaces.iter().find(|i| i.ip_addr == ip_addr)
+    }
+
+    pub fn get_interface(&self, port: Port) -> Option<&Interface> {
+        self.interfaces.iter().find(|i| i.port == port)
+    }
+
+    // the MAC frames leave the port with
+    fn port_mac(&self, port: Option<Port>) -> Mac {
+        port.and_then(|port| self.get_interface(port))
+            .map_or(self.get_mac(), |i| i.mac)
+    }
+
+    // a frame is taken if it is for the MAC of the ingress interface
+    fn accepts(&self, port: Port, dst: Mac) -> bool {
+        self.base.accepts(dst) || dst == self.port_mac(Some(port))
+    }
+
+    // the address of the first interface. a device may have none
+    pub fn primary_ip_addr(&self) -> Option<IpAddr> {
+        self.interfaces.first().map(|i| i.ip_addr)
+    }
+
+    pub fn join_group(&mut self, group: IpAddr) {
+        if self.groups.contains(&group) {
+            return;
+        }
+        self.groups.push(group);
+        self.base.join_group(group.multicast_mac());
+        let Some(src) = self.primary_ip_addr() else { return };
+        let ip = IP::new_igmp(src, group, 0x16, group);
+        self.outbox.push_back(NetworkProtocol::IP(ip));
+    }
+
+    pub fn leave_group(&mut self, group: IpAddr) {
+        if !self.groups.contains(&group) {
+            return;
+        }
+        self.groups.retain(|g| *g != group);
+        self.base.leave_group(group.multicast_mac());
+        let all_routers = IpAddr::new(0xE000_0002);
+        let Some(src) = self.primary_ip_addr() else { return };
+        let ip = IP::new_igmp(src, all_routers, 0x17, group);
+        self.outbox.push_back(NetworkProtocol::IP(ip));
+    }
+
+    // the port of the network the address is on
+    fn find_connected_port(&self, ip_addr: IpAddr) -> Option<Port> {
+        match self.routing_table.lookup(ip_addr)? {
+            Route { next_hop: None, port, .. } => *port,
+            _ => None,
+        }
     }
 
     pub fn get_ip_addr(&self, port: Port) -> Option<IpAddr> {
-        self.ip_addr_ports
-        .iter()
-        .find(|(_, p)| *p == port)
-        .map(|(ip_addr, _)| *ip_addr)
+        self.get_interface(port).map(|i| i.ip_addr)
     }
 
     pub fn get_arp_table(&self) -> &HashMap<IpAddr, Mac> {
-        &self.arp_table
+        self.arp_table.get_entries()
     }
 
+    // a static entry
     pub fn add_arp_entry(&mut self, ip_addr: IpAddr, mac: Mac) -> Res<()> {
-        self.arp_table.insert(ip_addr, mac);
+        self.arp_table.add_static(ip_addr, mac);
         Ok(())
     }
 
+    pub fn set_arp_config(&mut self, config: ArpConfig) {
+        self.arp_table.set_config(config);
+        if config.detect_duplicates {
+            self.probes = self.interfaces.iter().map(|i| ArpProbe::new(i.port, i.ip_addr)).collect();
+        }
+    }
+
+    // readdress the interface. the new address is announced, and probed before if duplicates are detected
+    pub fn set_ip_addr(&mut self, port: Port, ip_addr: IpAddr) {
+        let interface = match self.interfaces.iter_mut().find(|i| i.port == port) {
+            Some(interface) => interface,
+            None => return,
+        };
+        self.routing_table.remove(interface.nw(), None);
+        interface.ip_addr = ip_addr;
+        self.routing_table.add(Route::connected(interface.nw(), port));
+        self.probes.retain(|p| p.port != port);
+        if self.arp_table.get_config().detect_duplicates {
+            self.probes.push(ArpProbe::new(port, ip_addr));
+        } else {
+            self.probes.push(ArpProbe::announce(port, ip_addr));
+        }
+    }
+
+    pub fn get_arp_conflicts(&self) -> &Vec<ArpConflict> {
+        &self.conflicts
+    }
+
+    pub fn get_bad_checksums(&self) -> usize {
+        self.bad_checksums
+    }
+
+    pub fn get_bad_icmp_checksums(&self) -> usize {
+        self.bad_icmp_checksums
+    }
+
+    pub fn get_rx_errors(&self) -> usize {
+        self.rx_errors
+    }
+
+    // the MTU of the link is set on both ends by the caller
+    pub fn set_mtu(&mut self, port: Port, mtu: usize) -> Res<()> {
+        if mtu < MIN_MTU {
+            return Err(Error::InvalidConfig { msg: format!("MTU {} is below {}", mtu, MIN_MTU) });
+        }
+        if let Some(interface) = self.interfaces.iter_mut().find(|i| i.port == port) {
+            interface.mtu = mtu;
+        }
+        Ok(())
+    }
+
+    // the largest datagram reaching dst without being fragmented, as far as we know
+    pub fn get_path_mtu(&self, dst: IpAddr) -> usize {
+        let mtu = self.routing_table
+            .resolve(dst)
+            .and_then(|(port, _)| self.get_interface(port))
+            .map_or(DEFAULT_MTU, |i| i.mtu);
+        self.path_mtu.get(&dst).map_or(mtu, |path_mtu| mtu.min(*path_mtu))
+    }
+
+    // datagrams we originate get their own id so that their fragments can be told apart
+    fn assign_id(&mut self, ip: &mut IP) {
+        if ip.get_id() == 0 && self.find_interface(ip.src).is_some() {
+            ip.set_id(self.next_id);
+            self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+        }
+    }
+
+    fn update_reassembly(&mut self, ctx: &UpdateContext) {
+        for first in self.reassembly.update(ctx.t) {
+            let ip = self.time_exceeded(&first, ICMP_FRAGMENT_REASSEMBLY_EXCEEDED);
+            self.outbox.push_back(ip);
+        }
+    }
+
+    // another station claims the address of an interface. while probing, its probe for the address counts too
+    fn check_conflict(&mut self, arp: &ARP, ctx: &UpdateContext) {
+        let probing = |port: Port| self.probes.iter().any(|p| p.port == port && p.is_probing());
+        let conflict = self.interfaces
+            .iter()
+            .find(|i| {
+                i.mac != arp.sender_mac
+                && (i.ip_addr == arp.sender_ipaddr
+                    || (arp.is_probe() && i.ip_addr == arp.target_ipaddr && probing(i.port)))
+            })
+            .copied();
+        let known = |i: &Interface| self.conflicts.iter().any(|c| c.ip_addr == i.ip_addr && c.mac == arp.sender_mac);
+        if let Some(interface) = conflict.filter(|i| !known(i)) {
+            if crate::output::is_frame_level() {
+                println!("{}: duplicate address {} is used by {}", self.get_name(), interface.ip_addr, arp.sender_mac.value);
+            }
+            self.conflicts.push(ArpConflict { t: ctx.t, ip_addr: interface.ip_addr, mac: arp.sender_mac });
+            // an address found in use while probing is given up before it is announced
+            self.probes.retain(|p| p.port != interface.port || !p.is_probing());
+        }
+    }
+
+    fn update_probes(&mut self, ctx: &UpdateContext) -> Res<()> {
+        let device_mac = self.get_mac();
+        let mut arps = vec![];
+        // serial links have no ARP
+        let base = &self.base;
+        self.probes.retain(|p| base.get_ppp(p.port).is_none());
+        for probe in self.probes.iter_mut() {
+            let mac = self.interfaces.iter().find(|i| i.port == probe.port).map_or(device_mac, |i| i.mac);
+            arps.extend(probe.update(mac, ctx.t).map(|arp| (probe.port, arp)));
+        }
+        self.probes.retain(|p| !p.is_done());
+        // only on the link of the interface. a router does not announce an address on its other links
+        for (port, arp) in arps {
+            let p = NetworkProtocol::ARP(arp);
+            let frame = self.encode(&p)?;
+            self.add_slog(&p, ctx);
+            self.base.send_to(port, frame, ctx);
+        }
+        Ok(())
+    }
+
+    // the next hop of the datagram if its MAC is not known yet
+    fn unresolved_next_hop(&self, ip_addr: IpAddr) -> Option<IpAddr> {
+        if ip_addr.is_multicast() {
+            return None;
+        }
+        let (_, next_hop) = self.routing_table.resolve(ip_addr)?;
+        match self.arp_table.get(&next_hop) {
+            Some(_) => None,
+            None => Some(next_hop),
+        }
+    }
+
+    // broadcast a request from the interface facing the address
+    fn request_arp(&mut self, ip_addr: IpAddr, ctx: &UpdateContext) -> Res<()> {
+        let interface = self.routing_table
+            .resolve(ip_addr)
+            .and_then(|(port, _)| self.get_interface(port))
+            .ok_or(Error::MacNotFailed)?;
+        let arp = ARP::new_request(interface.mac, interface.ip_addr, ip_addr);
+        self.send(NetworkProtocol::ARP(arp), ctx)
+    }
+
+    // retries requests and expires entries. datagrams nobody answered for are reported to their senders
+    fn update_arp(&mut self, ctx: &UpdateContext) -> Res<()> {
+        self.update_probes(ctx)?;
+        let events = self.arp_table.update(ctx.t);
+        for ip_addr in events.requests {
+            self.request_arp(ip_addr, ctx)?;
+        }
+        for p in events.unresolved {
+            let ip = match &p {
+                NetworkProtocol::IP(ip) => ip,
+                _ => continue,
+            };
+            if self.is_for_me(&ip.src) {
+                return Err(Error::IpUnreashcable {
+                    code: 1,
+                    msg: format!("no ARP reply for the next hop to {}", ip.dst),
+                });
+            }
+            if let IpPayload::ICMP(icmp) = &ip.payload {
+                if icmp.is_error() {
+                    // no error about an error
+                    continue;
+                }
+            }
+            let icmp = self.unreachable(p);
+            self.outbox.push_back(icmp);
+        }
+        Ok(())
+    }
+
+    // static route through the next hop
     pub fn add_route_entry(&mut self, nw_part: NetworkPart, ip_addr: IpAddr) -> Res<()> {
-        self.routing_table.insert(nw_part, ip_addr);
+        self.add_route(Route::static_route(nw_part, ip_addr))
+    }
+
+    pub fn add_route(&mut self, route: Route) -> Res<()> {
+        self.routing_table.add(route);
+        Ok(())
+    }
+
+    pub fn remove_route(&mut self, prefix: NetworkPart, next_hop: Option<IpAddr>) -> Res<()> {
+        self.routing_table.remove(prefix, next_hop);
         Ok(())
     }
 
-    fn decode(&self, frame: &EthernetFrame) -> Res<Option<NetworkProtocol>> {
-        if frame.dst != self.get_mac() && !frame.is_bloadcast() { 
+    // "show ip route"
+    pub fn show_ip_route(&self) -> String {
+        format!("{}\n{}", self.get_name(), self.routing_table.dump())
+    }
+
+    // "show ip interface brief"
+    pub fn show_ip_interface(&self) -> String {
+        let mut s = format!("{}\n", self.get_name());
+        for interface in &self.interfaces {
+            s += &format!("{}\n", interface);
+        }
+        s
+    }
+
+    fn decode(&mut self, port: Port, frame: &EthernetFrame) -> Res<Option<NetworkProtocol>> {
+        if !self.accepts(port, frame.dst) {
             return Ok(None)
         }
         let p = match frame.ethertype {
             0x0800 => { 
                 // IPv4
-                let ip = IP::decode(&frame.payload)?;
+                let ip = match IP::decode(&frame.payload) {
+                    Ok(ip) => ip,
+                    Err(_) if IP::has_bad_checksum(&frame.payload) => {
+                        if crate::output::is_frame_level() {
+                            println!("{}: bad header checksum. dropped", self.get_name());
+                        }
+                        self.bad_checksums += 1;
+                        return Ok(None)
+                    }
+                    Err(e) => {
+                        if crate::output::is_frame_level() {
+                            println!("{}: malformed datagram. dropped. {}", self.get_name(), e);
+                        }
+                        self.rx_errors += 1;
+                        return Ok(None)
+                    }
+                };
                 NetworkProtocol::IP(ip)
             }
             0x0806 => { 
                 // ARP 
-                let arp = ARP::decode(&frame.payload)?;
+                let arp = match ARP::decode(&frame.payload) {
+                    Ok(arp) if matches!(arp.opcode, 1 | 2) => arp,
+                    Ok(arp) => {
+                        if crate::output::is_frame_level() {
+                            println!("{}: unknown ARP opcode {}. dropped", self.get_name(), arp.opcode);
+                        }
+                        self.rx_errors += 1;
+                        return Ok(None)
+                    }
+                    Err(e) => {
+                        if crate::output::is_frame_level() {
+                            println!("{}: malformed ARP packet. dropped. {}", self.get_name(), e);
+                        }
+                        self.rx_errors += 1;
+                        return Ok(None)
+                    }
+                };
                 NetworkProtocol::ARP(arp)
             }
             _ => return Err(Error::InvalidBytes { msg: "IP".to_string() })
@@ -131,7 +440,10 @@ impl BaseIpDevice {
     }
 
     fn encode(&self, p: &NetworkProtocol) -> Res<EthernetFrame> {
-        let src_mac = self.base().get_mac();
+        let src_mac = match p {
+            NetworkProtocol::IP(ip) => self.port_mac(self.routing_table.resolve(ip.dst).map(|(port, _)| port)),
+            NetworkProtocol::ARP(arp) => arp.sender_mac,
+        };
         let dst_mac = match p {
             NetworkProtocol::IP(ip) => {
                 self.find_next_mac(ip.dst)?
@@ -156,26 +468,27 @@ impl BaseIpDevice {
         Ok(frame)
     }
 
-    fn handle_arp(&mut self, arp: &ARP) -> Res<Option<NetworkProtocol>> {
-        if self.is_for_me(&arp.target_ipaddr) {
-            match arp.opcode {
-                1 => { // request
-                    let arp = arp.reply(self.get_mac());
-                    let arp = NetworkProtocol::ARP(arp);
-                    Ok(Some(arp))
-                }
-                2 => { // reply
-                    self.add_arp_entry(arp.sender_ipaddr, arp.sender_mac)?;
-                    Ok(None)
-                }
-                _ => panic!("invalid arp opcode"),
+    fn handle_arp(&mut self, arp: &ARP, ctx: &UpdateContext) -> Res<Option<NetworkProtocol>> {
+        self.check_conflict(arp, ctx);
+        if arp.is_probe() {
+            // nothing to learn from
+        } else if self.is_for_me(&arp.target_ipaddr) {
+            // the sender will talk to us. the datagrams waiting for it can go
+            for p in self.arp_table.learn(arp.sender_ipaddr, arp.sender_mac, ctx.t) {
+                self.outbox.push_back(p);
             }
         } else {
-            match arp.opcode {
-                1 => Ok(None),
-                2 => Ok(None),
-                _ => panic!("invalid arp opcode"),
-            }
+            // e.g. a gratuitous ARP. only an entry we have is updated
+            self.arp_table.refresh(arp.sender_ipaddr, arp.sender_mac, ctx.t);
+        }
+        // other opcodes are dropped on decode
+        if self.is_for_me(&arp.target_ipaddr) && arp.opcode == 1 {
+            // answered with the MAC of the interface owning the address
+            let mac = self.find_interface(arp.target_ipaddr).map_or(self.get_mac(), |i| i.mac);
+            let arp = arp.reply(mac);
+            Ok(Some(NetworkProtocol::ARP(arp)))
+        } else {
+            Ok(None)
         }
     }
 
@@ -186,7 +499,7 @@ impl BaseIpDevice {
             // FIXME: print ip address correct
             println!("{}({}): send   : {:}", 
                      self.get_name(), 
-                     self.ip_addr_ports[0].0, p);
+                     self.primary_ip_addr().map_or("-".to_string(), |a| a.to_string()), p);
         }
 
         let log = NetworkLog { t:ctx.t, p: p.clone()} ;
@@ -200,7 +513,7 @@ impl BaseIpDevice {
             // FIXME: print ip address correct
             println!("{}({}): receive: {:}",             
                      self.get_name(), 
-                     self.ip_addr_ports[0].0, p);
+                     self.primary_ip_addr().map_or("-".to_string(), |a| a.to_string()), p);
         }
 
         let log = NetworkLog { t:ctx.t, p: p.clone()} ;
@@ -211,29 +524,99 @@ impl BaseIpDevice {
         &self.rlog
     }
 
-    fn update_table(&mut self) -> Res<()> {
-        for (ip_addr, mac) in &self.arp_table {
-            let nw_part = NetworkPart::new(*ip_addr, self.subnet_mask);
-            if let Some(port) = self.find_port(&nw_part) {
-                self.base.add_forwarding_table(*mac, port);
+    fn update_table(&mut self, ctx: &UpdateContext) -> Res<()> {
+        for (ip_addr, mac) in self.arp_table.get_entries() {
+            if let Some(port) = self.find_connected_port(*ip_addr) {
+                self.base.add_forwarding_table(*mac, port, ctx);
             }
         }
         Ok(())
     }
 
+    // the address of the interface datagrams to dst leave from
+    // 0.0.0.0 if the device has no address at all
+    fn egress_ip_addr(&self, dst: IpAddr) -> IpAddr {
+        self.routing_table
+            .resolve(dst)
+            .and_then(|(port, _)| self.get_ip_addr(port))
+            .or(self.primary_ip_addr())
+            .unwrap_or(IpAddr::new(0))
+    }
+
+    // errors are reported from the interface facing the sender
+    fn error_src(&self, ip: &IP) -> IpAddr {
+        self.egress_ip_addr(ip.src)
+    }
+
+    // the datagram ran out of hops, or its fragments did not come together in time
+    fn time_exceeded(&self, ip: &IP, code: u8) -> NetworkProtocol {
+        let src = self.error_src(ip);
+        NetworkProtocol::IP(IP::new_icmp(src, ip.src, Icmp::error(ICMP_TIME_EXCEEDED, code, ip)))
+    }
+
+    fn fragmentation_needed(&self, ip: &IP, mtu: usize) -> NetworkProtocol {
+        let src = self.error_src(ip);
+        NetworkProtocol::IP(IP::new_icmp(src, ip.src, Icmp::fragmentation_needed(mtu, ip)))
+    }
+
+    // an ICMP message for us
+    pub fn handle_icmp(&mut self, ip: &IP) -> Res<Option<NetworkProtocol>> {
+        let icmp = match &ip.payload {
+            IpPayload::ICMP(icmp) => icmp,
+            IpPayload::Bytes(_) => return Ok(None),
+        };
+        match icmp.ty {
+            ICMP_ECHO_REQUEST => {
+                let src = if ip.dst.is_multicast() { self.egress_ip_addr(ip.src) } else { ip.dst };
+                let ip = IP::new_icmp(src, ip.src, icmp.echo_reply());
+                Ok(Some(NetworkProtocol::IP(ip)))
+            }
+            ICMP_UNREACHABLE if icmp.code == ICMP_FRAGMENTATION_NEEDED => {
+                // 0 comes from a router predating RFC 1191 and tells nothing
+                let mtu = icmp.get_next_hop_mtu();
+                if let Some(dst) = icmp.get_original_dst().filter(|_| mtu != 0) {
+                    let mtu = mtu.max(MIN_MTU);
+                    if crate::output::is_frame_level() {
+                        println!("{}: path MTU to {} is {}. reported by {}", self.get_name(), dst, mtu, ip.src);
+                    }
+                    self.path_mtu.insert(dst, mtu);
+                }
+                Ok(None)
+            }
+            ICMP_UNREACHABLE => {
+                Err(Error::IpUnreashcable { 
+                    code: icmp.code,
+                    msg: "".to_string() 
+                })
+            }
+            ICMP_TIME_EXCEEDED => {
+                if crate::output::is_frame_level() {
+                    println!("{}: time exceeded. reported by {}", self.get_name(), ip.src);
+                }
+                Ok(None)
+            }
+            _ => {
+                if crate::output::is_frame_level() {
+                    println!("{}: ignored {} from {}", self.get_name(), icmp, ip.src);
+                }
+                Ok(None)
+            }
+        }
+    }
+
     fn unreachable(&mut self, p: NetworkProtocol) -> NetworkProtocol {
-        let dst = match p {
-            NetworkProtocol::IP(ip) => ip.src,
+        let original = match p {
+            NetworkProtocol::IP(ip) => ip,
             _ => panic!("unreachable for ARP is not supported"),
         };
-        let src = self.ip_addr_ports[0].0;
-        let ip = IP::new_icmp(src, dst, 3, 1);
+        let src = self.error_src(&original);
+        let ip = IP::new_icmp(src, original.src, Icmp::error(ICMP_UNREACHABLE, ICMP_HOST_UNREACHABLE, &original));
         NetworkProtocol::IP(ip)
     }
 
     pub fn recv(&mut self, ctx: &UpdateContext) -> Res<Option<NetworkProtocol>> {
-        if let Some(frame) = self.recv_frame(ctx) {
-            if let Some(p) = self.decode(&frame)? {        
+        if let Some((port, frame)) = self.recv_frame(ctx) {
+            if let Some(p) = self.decode(port, &frame)? {        
                 self.add_rlog(&p, ctx);
                 return Ok(Some(p))
             }
@@ -241,7 +624,61 @@ impl BaseIpDevice {
         Ok(None)
     }
 
+    // too large for the egress interface. the fragments are sent one by one
+    fn send_fragments(&mut self, ip: IP, mtu: usize, ctx: &UpdateContext) -> Res<()> {
+        match ip.fragment(mtu) {
+            Some(fragments) => {
+                for fragment in fragments {
+                    self.send(NetworkProtocol::IP(fragment), ctx)?;
+                }
+                Ok(())
+            }
+            None if self.find_interface(ip.src).is_some() => {
+                // our own datagram. the error is handed to our applications, not sent
+                let icmp = self.fragmentation_needed(&ip, mtu);
+                self.inbox.push_back(icmp);
+                Ok(())
+            }
+            None => {
+                let icmp = self.fragmentation_needed(&ip, mtu);
+                self.send(icmp, ctx)
+            }
+        }
+    }
+
     pub fn send(&mut self, p: NetworkProtocol, ctx: &UpdateContext)  -> Res<()> {
+        let p = match p {
+            NetworkProtocol::IP(ip) if self.is_tentative(ip.src) => {
+                if crate::output::is_frame_level() {
+                    println!("{}: {} is still being probed. dropped", self.get_name(), ip.src);
+                }
+                return Ok(());
+            }
+            NetworkProtocol::IP(mut ip) => {
+                self.assign_id(&mut ip);
+                let mtu = self.get_path_mtu(ip.dst);
+                if ip.len() > mtu {
+                    return self.send_fragments(ip, mtu, ctx);
+                }
+                NetworkProtocol::IP(ip)
+            }
+            p => p,
+        };
+        if let NetworkProtocol::IP(ip) = &p {
+            if let Some(port) = self.find_ppp_port(ip.dst) {
+                self.add_slog(&p, ctx);
+                let frame = EthernetFrame::new(MAC_BROADCAST, self.get_mac(), 0x0800, p.encode());
+                self.base.send_to(port, frame, ctx);
+                return Ok(());
+            }
+            if let Some(next_hop) = self.unresolved_next_hop(ip.dst) {
+                // held until the next hop answers
+                if self.arp_table.enqueue(next_hop, p, ctx.t) {
+                    self.request_arp(next_hop, ctx)?;
+                }
+                return Ok(());
+            }
+        }
         match self.encode(&p) {
             Ok(frame) => {
                 self.add_slog(&p, ctx);
@@ -270,6 +707,10 @@ impl Device for BaseIpDevice {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
     fn update(&mut self, _ctx: &UpdateContext) -> Res<()> {
         panic!("not implemented")
     }
@@ -288,8 +729,8 @@ pub trait IpDevice {
         self.ip_base_mut().recv(ctx)
     }
 
-    fn base_handle_arp(&mut self, arp: &ARP) -> Res<Option<NetworkProtocol>> {
-        self.ip_base_mut().handle_arp(arp)
+    fn base_handle_arp(&mut self, arp: &ARP, ctx: &UpdateContext) -> Res<Option<NetworkProtocol>> {
+        self.ip_base_mut().handle_arp(arp, ctx)
     }
 
     fn add_arp_entry(&mut self, ip_addr: IpAddr, mac: Mac) -> Res<()> {
@@ -300,14 +741,86 @@ pub trait IpDevice {
         self.ip_base_mut().add_route_entry(nw_part, ip_addr)
     }
 
+    fn add_route(&mut self, route: Route) -> Res<()> {
+        self.ip_base_mut().add_route(route)
+    }
+
+    fn remove_route(&mut self, prefix: NetworkPart, next_hop: Option<IpAddr>) -> Res<()> {
+        self.ip_base_mut().remove_route(prefix, next_hop)
+    }
+
+    fn show_ip_route(&self) -> String {
+        self.ip_base().show_ip_route()
+    }
+
+    fn show_ip_interface(&self) -> String {
+        self.ip_base().show_ip_interface()
+    }
+
+    fn join_group(&mut self, group: IpAddr) {
+        self.ip_base_mut().join_group(group)
+    }
+
+    fn leave_group(&mut self, group: IpAddr) {
+        self.ip_base_mut().leave_group(group)
+    }
+
     fn get_ip_addr(&self, port: Port) -> Option<IpAddr> {
         self.ip_base().get_ip_addr(port)
     }
 
+    fn enable_lldp(&mut self, config: LldpConfig) {
+        self.ip_base_mut().base.enable_lldp(config)
+    }
+
+    fn get_lldp(&self) -> Option<&Lldp> {
+        self.ip_base().base.get_lldp()
+    }
+
+    fn set_ppp(&mut self, port: Port, config: PppConfig) {
+        self.ip_base_mut().base.set_ppp(port, config)
+    }
+
+    fn get_ppp(&self, port: Port) -> Option<&PppLink> {
+        self.ip_base().base.get_ppp(port)
+    }
+
     fn get_arp_table(&self) -> &HashMap<IpAddr, Mac> {
         self.ip_base().get_arp_table()
     }
 
+    fn set_arp_config(&mut self, config: ArpConfig) {
+        self.ip_base_mut().set_arp_config(config)
+    }
+
+    fn set_ip_addr(&mut self, port: Port, ip_addr: IpAddr) {
+        self.ip_base_mut().set_ip_addr(port, ip_addr)
+    }
+
+    fn get_arp_conflicts(&self) -> &Vec<ArpConflict> {
+        self.ip_base().get_arp_conflicts()
+    }
+
+    fn get_bad_checksums(&self) -> usize {
+        self.ip_base().get_bad_checksums()
+    }
+
+    fn get_bad_icmp_checksums(&self) -> usize {
+        self.ip_base().get_bad_icmp_checksums()
+    }
+
+    fn get_rx_errors(&self) -> usize {
+        self.ip_base().get_rx_errors()
+    }
+
+    fn set_mtu(&mut self, port: Port, mtu: usize) -> Res<()> {
+        self.ip_base_mut().set_mtu(port, mtu)
+    }
+
+    fn get_path_mtu(&self, dst: IpAddr) -> usize {
+        self.ip_base().get_path_mtu(dst)
+    }
+
     fn get_rlog(&self) -> &Vec<NetworkLog> {
         self.ip_base().get_rlog()
     }
@@ -315,25 +828,35 @@ pub trait IpDevice {
     fn handle(&mut self, p: &NetworkProtocol, ctx: &UpdateContext) -> Res<Option<NetworkProtocol>> {
         match p {
             NetworkProtocol::IP(ip) => self.handle_ip(ip, ctx),
-            NetworkProtocol::ARP(arp) => self.ip_base_mut().handle_arp(arp),
+            NetworkProtocol::ARP(arp) => self.ip_base_mut().handle_arp(arp, ctx),
         }
     }
 
     fn handle_ip(&mut self, ip: &IP, ctx: &UpdateContext) -> Res<Option<NetworkProtocol>> {
         if self.ip_base().is_for_me(&ip.dst) {
-            match &ip.payload {
-                IpPayload::ICMP { ty: 3, code } => { // unreachable
-                    Err(Error::IpUnreashcable { 
-                        code: *code,
-                        msg: "".to_string() 
-                    })
-                }
-                IpPayload::ICMP { ty, code} => {
-                    panic!("unimplemented ICMP ty={} code={}", ty, code)
+            if ip.is_fragment() {
+                return match self.ip_base_mut().reassembly.add(ip, ctx.t) {
+                    Some(ip) => self.handle_ip(&ip, ctx),
+                    None => Ok(None),
+                };
+            }
+            if ip.get_protocol() == 2 {
+                // IGMP from other members
+                return Ok(None);
+            }
+            if ip.is_damaged_icmp() {
+                if crate::output::is_frame_level() {
+                    println!("{}: bad ICMP checksum. dropped", self.ip_base().get_name());
                 }
+                self.ip_base_mut().bad_icmp_checksums += 1;
+                return Ok(None);
+            }
+            match &ip.payload {
+                IpPayload::ICMP(_) => self.handle_icmp(ip, ctx),
                 IpPayload::Bytes(xs) => {
                     if let Some(payload) = self.handle_ip_reply(&xs, ctx)? {
-                        let ip = IP::new_byte(ip.dst, ip.src, payload);
+                        let src = if ip.dst.is_multicast() { self.ip_base().egress_ip_addr(ip.src) } else { ip.dst };
+                        let ip = IP::new_byte(src, ip.src, payload);
                         let ip = NetworkProtocol::IP(ip);
                         Ok(Some(ip))
                     } else {
@@ -343,25 +866,45 @@ pub trait IpDevice {
                 }
             }
         } else {
-            match &ip.payload {
-                IpPayload::ICMP { ty: _, code: _ } => Ok(None),
-                IpPayload::Bytes(_) => {
-                    let p = NetworkProtocol::IP(ip.clone());
-                    Ok(Some(p))
+            if ip.get_ttl() <= 1 {
+                if let IpPayload::ICMP(icmp) = &ip.payload {
+                    if icmp.is_error() {
+                        // no error about an error
+                        return Ok(None);
+                    }
                 }
+                return Ok(Some(self.ip_base().time_exceeded(ip, ICMP_TTL_EXCEEDED)));
             }
+            let mut ip = ip.clone();
+            ip.set_ttl(ip.get_ttl() - 1);
+            ip.record_options(self.ip_base().egress_ip_addr(ip.dst), ctx.t);
+            Ok(Some(NetworkProtocol::IP(ip)))
         }
     }
 
+    fn handle_icmp(&mut self, ip: &IP, _ctx: &UpdateContext) -> Res<Option<NetworkProtocol>> {
+        self.ip_base_mut().handle_icmp(ip)
+    }
+
     fn handle_ip_reply(&mut self, bytes: &Vec<u8>, ctx: &UpdateContext) -> Res<Option<Vec<u8>>>;
 
     fn base_update(&mut self, ctx: &UpdateContext) -> Res<()> {
+        self.ip_base_mut().update_arp(ctx)?;
+        self.ip_base_mut().update_reassembly(ctx);
+        while let Some(p) = self.ip_base_mut().outbox.pop_front() {
+            self.send(p, ctx)?;
+        }
+        while let Some(p) = self.ip_base_mut().inbox.pop_front() {
+            if let Some(p) = self.handle(&p, ctx)? {
+                self.send(p, ctx)?;
+            }
+        }
         while let Some(p) = self.recv(ctx)? {
             if let Some(p) = self.handle(&p, ctx)? {
                 self.send(p, ctx)?;
             }
         }
-        self.ip_base_mut().update_table()?;
+        self.ip_base_mut().update_table(ctx)?;
         Ok(())
     }
 }
\ No newline at end of file
@@ -1,11 +1,17 @@
 use crate::{physl::{BaseDevice, Device}, types::{Res, Mac, UpdateContext}};
 
 use super::{ip_device::{BaseIpDevice, IpDevice}, network_protocol::NetworkProtocol, ip_addr::{IpAddr, SubnetMask}, NetworkLog};
+use super::ip::{IP, IpPayload};
+use super::icmp::{ICMP_ECHO_REPLY, ICMP_TIME_EXCEEDED, ICMP_UNREACHABLE};
+use super::ping::Ping;
+use super::traceroute::Traceroute;
 
 pub struct IpHost {
     base: BaseIpDevice,
     schedules: Vec<NetworkLog>,
     ip_reply_handler: Box<dyn Fn(&Vec<u8>) -> Vec<u8>>,
+    traceroute: Option<Traceroute>,
+    ping: Option<Ping>,
 }
 
 impl IpHost {
@@ -22,10 +28,44 @@ impl IpHost {
             base,
             schedules: Vec::new(),
             ip_reply_handler,
+            traceroute: None,
+            ping: None,
         };
         Box::new(host)
     }
 
+    pub fn set_traceroute(&mut self, traceroute: Traceroute) {
+        self.traceroute = Some(traceroute);
+    }
+
+    pub fn get_traceroute(&self) -> Option<&Traceroute> {
+        self.traceroute.as_ref()
+    }
+
+    fn update_traceroute(&mut self, ctx: &UpdateContext) -> Res<()> {
+        let Some(src) = self.base.primary_ip_addr() else { return Ok(()) };
+        if let Some(ip) = self.traceroute.as_mut().and_then(|tr| tr.update(src, ctx.t)) {
+            self.send(NetworkProtocol::IP(ip), ctx)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_ping(&mut self, ping: Ping) {
+        self.ping = Some(ping);
+    }
+
+    pub fn get_ping(&self) -> Option<&Ping> {
+        self.ping.as_ref()
+    }
+
+    fn update_ping(&mut self, ctx: &UpdateContext) -> Res<()> {
+        let Some(src) = self.base.primary_ip_addr() else { return Ok(()) };
+        if let Some(ip) = self.ping.as_mut().and_then(|ping| ping.update(src, ctx.t)) {
+            self.send(NetworkProtocol::IP(ip), ctx)?;
+        }
+        Ok(())
+    }
+
     pub fn add_schedule(&mut self, t: usize, p: NetworkProtocol) {
         self.schedules.push(NetworkLog { t, p } );
     }
@@ -51,7 +91,34 @@ impl IpDevice for IpHost {
         &mut self.base
     }
 
-    fn handle_ip_reply(&mut self, bytes: &Vec<u8>, _ctx: &UpdateContext) -> Res<Option<Vec<u8>>> {
+    fn handle_icmp(&mut self, ip: &IP, ctx: &UpdateContext) -> Res<Option<NetworkProtocol>> {
+        let icmp = match &ip.payload {
+            IpPayload::ICMP(icmp) => icmp,
+            IpPayload::Bytes(_) => return self.base.handle_icmp(ip),
+        };
+        if icmp.ty == ICMP_ECHO_REPLY && self.ping.as_mut().is_some_and(|ping| ping.on_reply(icmp, ctx.t)) {
+            return Ok(None);
+        }
+        if let Some(ping) = self.ping.as_mut() {
+            // the error is handled as usual too, e.g. for the path MTU
+            ping.on_error(ip.src, icmp);
+        }
+        // errors about other datagrams are handled as usual
+        let traced = self.traceroute.as_mut().is_some_and(|tr| match icmp.ty {
+            ICMP_TIME_EXCEEDED => tr.on_time_exceeded(ip.src, icmp, ctx.t),
+            ICMP_UNREACHABLE => tr.on_unreachable(ip.src, icmp, ctx.t),
+            _ => false,
+        });
+        if traced {
+            return Ok(None);
+        }
+        self.base.handle_icmp(ip)
+    }
+
+    fn handle_ip_reply(&mut self, bytes: &Vec<u8>, ctx: &UpdateContext) -> Res<Option<Vec<u8>>> {
+        if self.traceroute.as_mut().is_some_and(|tr| tr.on_reply(bytes, ctx.t)) {
+            return Ok(None);
+        }
         let bytes = (self.ip_reply_handler)(bytes);
         Ok(Some(bytes))
     }
@@ -70,8 +137,14 @@ impl Device for IpHost {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
     fn update(&mut self, ctx: &UpdateContext) -> Res<()> {
         self.update_from_schedule(ctx)?;
+        self.update_traceroute(ctx)?;
+        self.update_ping(ctx)?;
         self.base_update(ctx)?;
         Ok(())
     }
@@ -0,0 +1,194 @@
+use crate::types::{Res, Error};
+
+use super::ip_addr::IpAddr;
+
+const OPTION_END: u8 = 0;
+const OPTION_NOP: u8 = 1;
+const OPTION_RECORD_ROUTE: u8 = 7;
+const OPTION_TIMESTAMP: u8 = 68;
+
+// the pointer of record route and timestamp counts from the type byte and starts after the header of the option
+const POINTER_MIN: u8 = 4;
+
+// bit 7 of the type. such options are repeated in every fragment
+const COPIED: u8 = 0x80;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IpOption {
+    // the routers on the way write their addresses into the free slots
+    RecordRoute { pointer: u8, route: Vec<IpAddr> },
+    // timestamps only (flag 0). the routers write the time into the free slots
+    Timestamp { pointer: u8, overflow: u8, flags: u8, stamps: Vec<u32> },
+    Other { ty: u8, data: Vec<u8> },
+}
+
+impl IpOption {
+    pub fn new_record_route(slots: usize) -> IpOption {
+        IpOption::RecordRoute { pointer: POINTER_MIN, route: vec![IpAddr::new(0); slots] }
+    }
+
+    pub fn new_timestamp(slots: usize) -> IpOption {
+        IpOption::Timestamp { pointer: POINTER_MIN + 1, overflow: 0, flags: 0, stamps: vec![0; slots] }
+    }
+
+    pub fn is_copied(&self) -> bool {
+        match self {
+            IpOption::Other { ty, data: _ } => ty & COPIED != 0,
+            _ => false,
+        }
+    }
+
+    // the addresses written so far
+    pub fn get_route(&self) -> Option<&[IpAddr]> {
+        match self {
+            IpOption::RecordRoute { pointer, route } => Some(&route[..(*pointer - POINTER_MIN) as usize / 4]),
+            _ => None,
+        }
+    }
+
+    pub fn get_stamps(&self) -> Option<&[u32]> {
+        match self {
+            IpOption::Timestamp { pointer, stamps, .. } => Some(&stamps[..(*pointer - POINTER_MIN - 1) as usize / 4]),
+            _ => None,
+        }
+    }
+
+    // a router forwards the datagram from the address at time t
+    pub fn record(&mut self, addr: IpAddr, t: usize) {
+        match self {
+            IpOption::RecordRoute { pointer, route } => {
+                let i = (*pointer - POINTER_MIN) as usize / 4;
+                if i < route.len() {
+                    route[i] = addr;
+                    *pointer += 4;
+                }
+            }
+            IpOption::Timestamp { pointer, overflow, flags: 0, stamps } => {
+                let i = (*pointer - POINTER_MIN - 1) as usize / 4;
+                if i < stamps.len() {
+                    stamps[i] = t as u32;
+                    *pointer += 4;
+                } else if *overflow < 0x0F {
+                    *overflow += 1;
+                }
+            }
+            // addresses with timestamps are not supported
+            IpOption::Timestamp { .. } | IpOption::Other { .. } => {}
+        }
+    }
+
+    // the options of a header, without the padding
+    pub fn decode_all(xs: &[u8]) -> Res<Vec<IpOption>> {
+        let mut options = vec![];
+        let mut i = 0;
+        while i < xs.len() {
+            match xs[i] {
+                OPTION_END => break,
+                OPTION_NOP => i += 1,
+                ty => {
+                    let len = *xs.get(i + 1).ok_or(Error::InvalidBytes { msg: format!("IP option {} without length", ty) })? as usize;
+                    if len < 2 || i + len > xs.len() {
+                        return Err(Error::InvalidBytes { msg: format!("IP option {} with length {}", ty, len) });
+                    }
+                    options.push(IpOption::decode(ty, &xs[i + 2..i + len])?);
+                    i += len;
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    fn decode(ty: u8, xs: &[u8]) -> Res<IpOption> {
+        let words = |xs: &[u8]| -> Vec<u32> {
+            xs.chunks_exact(4).map(|w| u32::from_be_bytes([w[0], w[1], w[2], w[3]])).collect()
+        };
+        // the pointer points at a slot or just behind the last one
+        let valid = |pointer_min: u8, header_len: usize| {
+            xs.len() >= header_len
+                && (xs.len() - header_len).is_multiple_of(4)
+                && xs[0] >= pointer_min
+                && (xs[0] - pointer_min).is_multiple_of(4)
+                && (xs[0] - pointer_min) as usize <= xs.len() - header_len
+        };
+        match ty {
+            OPTION_RECORD_ROUTE if valid(POINTER_MIN, 1) => {
+                let route = words(&xs[1..]).into_iter().map(IpAddr::new).collect();
+                Ok(IpOption::RecordRoute { pointer: xs[0], route })
+            }
+            OPTION_TIMESTAMP if valid(POINTER_MIN + 1, 2) => {
+                Ok(IpOption::Timestamp { pointer: xs[0], overflow: xs[1] >> 4, flags: xs[1] & 0x0F, stamps: words(&xs[2..]) })
+            }
+            OPTION_RECORD_ROUTE | OPTION_TIMESTAMP => {
+                Err(Error::InvalidBytes { msg: format!("IP option {} with {} bytes of data", ty, xs.len()) })
+            }
+            ty => Ok(IpOption::Other { ty, data: xs.to_vec() }),
+        }
+    }
+
+    // padded to a multiple of 4 bytes
+    pub fn encode_all(options: &[IpOption]) -> Vec<u8> {
+        let mut xs: Vec<u8> = options.iter().flat_map(|option| option.encode()).collect();
+        while !xs.len().is_multiple_of(4) {
+            xs.push(OPTION_END);
+        }
+        xs
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let (ty, data) = match self {
+            IpOption::RecordRoute { pointer, route } => {
+                let mut data = vec![*pointer];
+                for addr in route {
+                    data.extend_from_slice(&addr.value.to_be_bytes());
+                }
+                (OPTION_RECORD_ROUTE, data)
+            }
+            IpOption::Timestamp { pointer, overflow, flags, stamps } => {
+                let mut data = vec![*pointer, overflow << 4 | flags];
+                for stamp in stamps {
+                    data.extend_from_slice(&stamp.to_be_bytes());
+                }
+                (OPTION_TIMESTAMP, data)
+            }
+            IpOption::Other { ty, data } => (*ty, data.clone()),
+        };
+        let mut xs = vec![ty, 2 + data.len() as u8];
+        xs.extend(data);
+        xs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_option() {
+        let mut rr = IpOption::new_record_route(2);
+        let mut ts = IpOption::new_timestamp(1);
+        for (i, addr) in [0x0a02_0001, 0x0a03_0003, 0x0a04_0001].iter().enumerate() {
+            rr.record(IpAddr::new(*addr), 100 * i);
+            ts.record(IpAddr::new(*addr), 100 * i);
+        }
+        assert_eq!(Some(&[IpAddr::new(0x0a02_0001), IpAddr::new(0x0a03_0003)][..]), rr.get_route());
+        assert_eq!(Some(&[0][..]), ts.get_stamps());
+
+        let options = vec![rr, IpOption::Other { ty: 0x94, data: vec![0, 0] }, ts];
+        let xs = IpOption::encode_all(&options);
+        // 11 + 4 + 8 bytes and a byte of padding
+        assert_eq!(24, xs.len());
+        let decoded = IpOption::decode_all(&xs).unwrap();
+        assert_eq!(options, decoded);
+        assert!(matches!(decoded[2], IpOption::Timestamp { overflow: 2, .. }));
+        assert!(decoded[1].is_copied() && !decoded[0].is_copied());
+
+        let mut ys = vec![OPTION_NOP];
+        ys.extend(&xs);
+        assert_eq!(options, IpOption::decode_all(&ys).unwrap());
+        assert!(IpOption::decode_all(&xs[..10]).is_err());
+        assert!(IpOption::decode_all(&[OPTION_RECORD_ROUTE]).is_err());
+        // the pointer is behind the slots
+        assert!(IpOption::decode_all(&[OPTION_RECORD_ROUTE, 7, 12, 0, 0, 0, 0]).is_err());
+        assert!(IpOption::decode_all(&[OPTION_RECORD_ROUTE, 7, 8, 0, 0, 0, 0]).is_ok());
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use super::icmp::{Icmp, ICMP_ECHO_REQUEST, ICMP_FRAGMENTATION_NEEDED, ICMP_TIME_EXCEEDED, ICMP_UNREACHABLE};
+use super::ip::IP;
+use super::ip_addr::IpAddr;
+
+// the identifier of our echo requests. replies carrying another one are not ours
+const PING_IDENTIFIER: u16 = 0x5049;
+const PING_DATA: [u8; 4] = [0x70, 0x69, 0x6e, 0x67];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PingStats {
+    pub sent: usize,
+    pub received: usize,
+    pub min_rtt: Option<usize>, // ticks. None if nothing came back
+    pub avg_rtt: Option<usize>,
+    pub max_rtt: Option<usize>,
+}
+
+impl PingStats {
+    pub fn loss_percent(&self) -> usize {
+        (100 * (self.sent - self.received)).checked_div(self.sent).unwrap_or(0)
+    }
+}
+
+// a request answered by an ICMP error instead of a reply. it is counted as lost
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PingError {
+    pub seq: u16,
+    pub from: IpAddr,
+    pub reason: String,
+}
+
+// sends count echo requests, one every interval ticks. a reply later than timeout is counted as lost
+pub struct Ping {
+    dst: IpAddr,
+    count: u16,
+    interval: usize,
+    timeout: usize,
+    next_seq: u16,
+    next_t: Option<usize>,
+    outstanding: HashMap<u16, usize>, // sequence -> when it was sent
+    rtts: Vec<usize>,
+    errors: Vec<PingError>,
+    data: Vec<u8>,
+    dont_fragment: bool,
+}
+
+impl Ping {
+    pub fn new(dst: IpAddr, count: u16, interval: usize, timeout: usize) -> Ping {
+        Ping {
+            dst,
+            count,
+            interval,
+            timeout,
+            next_seq: 0,
+            next_t: None,
+            outstanding: HashMap::new(),
+            rtts: Vec::new(),
+            errors: Vec::new(),
+            data: PING_DATA.to_vec(),
+            dont_fragment: false,
+        }
+    }
+
+    // the pattern is repeated up to len bytes
+    pub fn set_data_len(&mut self, len: usize) {
+        self.data = PING_DATA.iter().copied().cycle().take(len).collect();
+    }
+
+    pub fn set_dont_fragment(&mut self, dont_fragment: bool) {
+        self.dont_fragment = dont_fragment;
+    }
+
+    pub fn is_done(&self, t: usize) -> bool {
+        self.next_seq >= self.count
+            && self.outstanding.values().all(|t_sent| t > t_sent + self.timeout)
+    }
+
+    // the echo request to send, if any
+    pub fn update(&mut self, src: IpAddr, t: usize) -> Option<IP> {
+        if self.next_seq >= self.count || self.next_t.is_some_and(|next_t| t < next_t) {
+            return None;
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.next_t = Some(t + self.interval);
+        self.outstanding.insert(seq, t);
+        let icmp = Icmp::echo_request(PING_IDENTIFIER, seq, self.data.clone());
+        let mut ip = IP::new_icmp(src, self.dst, icmp);
+        ip.set_dont_fragment(self.dont_fragment);
+        Some(ip)
+    }
+
+    // true if the reply answers one of our requests in time
+    pub fn on_reply(&mut self, icmp: &Icmp, t: usize) -> bool {
+        if icmp.get_identifier() != PING_IDENTIFIER {
+            return false;
+        }
+        let Some(t_sent) = self.outstanding.remove(&icmp.get_sequence()) else {
+            return false;
+        };
+        let rtt = t - t_sent;
+        if rtt <= self.timeout {
+            self.rtts.push(rtt);
+        }
+        true
+    }
+
+    // true if the error is about one of our requests. from is who reported it
+    pub fn on_error(&mut self, from: IpAddr, icmp: &Icmp) -> bool {
+        if !icmp.is_error() || icmp.get_original_dst() != Some(self.dst) {
+            return false;
+        }
+        // the quoted echo request: type, code, checksum, identifier and sequence
+        let Some(xs) = icmp.get_original_payload().filter(|xs| xs.len() >= 8) else {
+            return false;
+        };
+        if xs[0] != ICMP_ECHO_REQUEST || u16::from_be_bytes([xs[4], xs[5]]) != PING_IDENTIFIER {
+            return false;
+        }
+        let seq = u16::from_be_bytes([xs[6], xs[7]]);
+        if self.outstanding.remove(&seq).is_none() {
+            return false;
+        }
+        let reason = match (icmp.ty, icmp.code) {
+            (ICMP_UNREACHABLE, ICMP_FRAGMENTATION_NEEDED) => format!("frag needed (mtu {})", icmp.get_next_hop_mtu()),
+            (ICMP_UNREACHABLE, code) => format!("destination unreachable (code {})", code),
+            (ICMP_TIME_EXCEEDED, _) => "time exceeded".to_string(),
+            (ty, _) => format!("ICMP type {}", ty),
+        };
+        self.errors.push(PingError { seq, from, reason });
+        true
+    }
+
+    pub fn get_errors(&self) -> &Vec<PingError> {
+        &self.errors
+    }
+
+    pub fn get_stats(&self) -> PingStats {
+        let received = self.rtts.len();
+        PingStats {
+            sent: self.next_seq as usize,
+            received,
+            min_rtt: self.rtts.iter().min().copied(),
+            avg_rtt: self.rtts.iter().sum::<usize>().checked_div(received),
+            max_rtt: self.rtts.iter().max().copied(),
+        }
+    }
+
+    pub fn dump(&self) -> String {
+        let stats = self.get_stats();
+        let mut s = String::new();
+        for e in &self.errors {
+            s += &format!("From {} icmp_seq={} {}\n", e.from, e.seq, e.reason);
+        }
+        s += &format!("--- {} ping statistics ---\n", self.dst);
+        s += &format!("{} packets transmitted, {} received, {}% packet loss\n",
+                      stats.sent, stats.received, stats.loss_percent());
+        if let (Some(min), Some(avg), Some(max)) = (stats.min_rtt, stats.avg_rtt, stats.max_rtt) {
+            s += &format!("rtt min/avg/max = {}/{}/{} ticks\n", min, avg, max);
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ip::IpPayload;
+    use super::*;
+
+    fn request(ip: IP) -> Icmp {
+        match ip.payload {
+            IpPayload::ICMP(icmp) => icmp,
+            _ => panic!("not ICMP"),
+        }
+    }
+
+    #[test]
+    fn test_ping() {
+        let src = IpAddr::new(0x0a00_0001);
+        let dst = IpAddr::new(0x0a00_0002);
+        let mut ping = Ping::new(dst, 3, 100, 150);
+        ping.set_data_len(10);
+        ping.set_dont_fragment(true);
+        let ip = ping.update(src, 0).unwrap();
+        assert_eq!((38, true), (ip.len(), ip.is_dont_fragment()));
+        let r0 = request(ip);
+        assert!(ping.update(src, 50).is_none());
+        assert!(ping.update(src, 100).is_some()); // never answered
+        assert!(ping.on_reply(&r0.echo_reply(), 40));
+        assert!(!ping.on_reply(&r0.echo_reply(), 45)); // duplicate
+        let r2 = request(ping.update(src, 200).unwrap());
+        assert!(ping.update(src, 300).is_none());
+        assert!(ping.on_reply(&r2.echo_reply(), 260));
+        assert!(!ping.is_done(250));
+        assert!(ping.is_done(251));
+        let stats = ping.get_stats();
+        assert_eq!(PingStats { sent: 3, received: 2, min_rtt: Some(40), avg_rtt: Some(50), max_rtt: Some(60) }, stats);
+        assert_eq!(33, stats.loss_percent());
+        assert!(ping.dump().contains("rtt min/avg/max = 40/50/60 ticks"));
+
+        // the second request does not fit the path
+        let router = IpAddr::new(0x0a00_0003);
+        let mut ping = Ping::new(dst, 2, 100, 150);
+        ping.set_dont_fragment(true);
+        let ip0 = ping.update(src, 0).unwrap();
+        let ip1 = ping.update(src, 100).unwrap();
+        let error = Icmp::fragmentation_needed(28, &ip1);
+        assert!(!ping.on_error(router, &Icmp::echo_request(1, 0, vec![])));
+        assert!(ping.on_error(router, &error));
+        assert!(!ping.on_error(router, &error)); // duplicate
+        assert!(ping.on_reply(&request(ip0).echo_reply(), 40));
+        assert!(ping.is_done(101));
+        assert_eq!((2, 1), (ping.get_stats().sent, ping.get_stats().received));
+        assert_eq!(vec![PingError { seq: 1, from: router, reason: "frag needed (mtu 28)".to_string() }], *ping.get_errors());
+        assert!(ping.dump().contains("From 10.0.0.3 icmp_seq=1 frag needed (mtu 28)\n"));
+    }
+}
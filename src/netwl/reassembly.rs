@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use super::ip::IP;
+use super::ip_addr::IpAddr;
+
+pub const REASSEMBLY_TIMEOUT: usize = 3000;
+
+// fragments of one datagram are told apart by these
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Key {
+    src: IpAddr,
+    dst: IpAddr,
+    protocol: u8,
+    id: u16,
+}
+
+struct Buffer {
+    first: Option<IP>,        // the fragment at offset 0. its header becomes the header of the datagram
+    data: Vec<Option<u8>>,    // None where nothing arrived yet
+    total_len: Option<usize>, // known once the last fragment arrived
+    t_first: usize,
+}
+
+impl Buffer {
+    fn is_complete(&self) -> bool {
+        match self.total_len {
+            Some(len) => self.first.is_some() && self.data.len() == len && self.data.iter().all(|x| x.is_some()),
+            None => false,
+        }
+    }
+}
+
+// collects fragments into datagrams. bytes covered by several fragments keep the first copy
+pub struct Reassembly {
+    timeout: usize,
+    buffers: HashMap<Key, Buffer>,
+}
+
+impl Reassembly {
+    pub fn new(timeout: usize) -> Reassembly {
+        Reassembly {
+            timeout,
+            buffers: HashMap::new(),
+        }
+    }
+
+    // the datagram if the fragment completes it
+    pub fn add(&mut self, fragment: &IP, t: usize) -> Option<IP> {
+        let key = Key {
+            src: fragment.src,
+            dst: fragment.dst,
+            protocol: fragment.get_protocol(),
+            id: fragment.get_id(),
+        };
+        let buffer = self.buffers.entry(key).or_insert(Buffer {
+            first: None,
+            data: Vec::new(),
+            total_len: None,
+            t_first: t,
+        });
+        let offset = fragment.get_fragment_offset();
+        let bytes = fragment.payload_as_bytes();
+        let end = offset + bytes.len();
+        if buffer.data.len() < end {
+            buffer.data.resize(end, None);
+        }
+        for (i, x) in bytes.into_iter().enumerate() {
+            buffer.data[offset + i].get_or_insert(x);
+        }
+        if offset == 0 && buffer.first.is_none() {
+            buffer.first = Some(fragment.clone());
+        }
+        if !fragment.has_more_fragments() {
+            buffer.total_len = Some(end);
+        }
+        if let Some(len) = buffer.total_len {
+            // bytes beyond the last fragment are nonsense
+            buffer.data.truncate(len);
+        }
+        if !buffer.is_complete() {
+            return None;
+        }
+        let buffer = self.buffers.remove(&key).unwrap();
+        let bytes = buffer.data.into_iter().flatten().collect();
+        Some(IP::reassemble(buffer.first.as_ref().unwrap(), bytes))
+    }
+
+    // drop datagrams not completed in time. returns their first fragments, if they arrived
+    pub fn update(&mut self, t: usize) -> Vec<IP> {
+        let timeout = self.timeout;
+        let expired: Vec<Key> = self.buffers
+            .iter()
+            .filter(|(_, buffer)| t >= buffer.t_first + timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|key| self.buffers.remove(&key).unwrap().first)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reassembly() {
+        let src = IpAddr::new(0x0a00_0001);
+        let dst = IpAddr::new(0x0a00_0002);
+        let mut ip = IP::new_byte(src, dst, (0..100).collect());
+        ip.set_id(1);
+        let fragments = ip.fragment(60).unwrap();
+        let mut reassembly = Reassembly::new(100);
+
+        // out of order and overlapping
+        assert!(reassembly.add(&fragments[2], 0).is_none());
+        let overlap = fragments[0].fragment(36).unwrap();
+        assert!(reassembly.add(&overlap[1], 1).is_none());
+        assert!(reassembly.add(&fragments[1], 2).is_none());
+        assert_eq!(Some(ip.clone()), reassembly.add(&fragments[0], 3));
+
+        // the last fragment never comes
+        ip.set_id(2);
+        let fragments = ip.fragment(60).unwrap();
+        assert!(reassembly.add(&fragments[0], 10).is_none());
+        assert!(reassembly.add(&fragments[1], 11).is_none());
+        assert!(reassembly.update(109).is_empty());
+        assert_eq!(vec![fragments[0].clone()], reassembly.update(110));
+        assert!(reassembly.add(&fragments[2], 120).is_none());
+    }
+}
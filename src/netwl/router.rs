@@ -1,6 +1,6 @@
 use crate::{physl::{BaseDevice, Device}, types::{Res, Mac, UpdateContext}};
 
-use super::{ip_device::{BaseIpDevice, IpDevice}, ip_addr::{IpAddr, SubnetMask}};
+use super::{ip_device::{BaseIpDevice, IpDevice}, ip_addr::{IpAddr, SubnetMask}, interface::Interface};
 
 pub struct Router {
     base: BaseIpDevice,
@@ -17,6 +17,12 @@ impl Router {
         };
         Box::new(host)
     }
+
+    // a router whose ports have their own address, prefix and MAC
+    pub fn build_interfaces(mac: Mac, name: &str, interfaces: Vec<Interface>) -> Box<Router> {
+        let base = BaseIpDevice::with_interfaces(mac, name, interfaces);
+        Box::new(Router { base })
+    }
 }
 
 impl IpDevice for Router {
@@ -46,6 +52,10 @@ impl Device for Router {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
     fn update(&mut self, ctx: &UpdateContext) -> Res<()> {
         self.base_update(ctx)?;
         Ok(())
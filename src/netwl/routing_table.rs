@@ -0,0 +1,168 @@
+use crate::types::Port;
+
+use super::ip_addr::{IpAddr, NetworkPart, SubnetMask};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteSource {
+    Connected,
+    Static,
+}
+
+impl RouteSource {
+    // administrative distance
+    pub fn default_distance(&self) -> u8 {
+        match self {
+            RouteSource::Connected => 0,
+            RouteSource::Static => 1,
+        }
+    }
+
+    fn code(&self) -> char {
+        match self {
+            RouteSource::Connected => 'C',
+            RouteSource::Static => 'S',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Route {
+    pub prefix: NetworkPart,
+    pub next_hop: Option<IpAddr>, // None if the destination is on the link
+    pub port: Option<Port>,       // None if found through the next hop
+    pub source: RouteSource,
+    pub distance: u8,
+    pub metric: u32,
+}
+
+impl Route {
+    pub fn connected(prefix: NetworkPart, port: Port) -> Route {
+        Route {
+            prefix,
+            next_hop: None,
+            port: Some(port),
+            source: RouteSource::Connected,
+            distance: RouteSource::Connected.default_distance(),
+            metric: 0,
+        }
+    }
+
+    pub fn static_route(prefix: NetworkPart, next_hop: IpAddr) -> Route {
+        Route {
+            prefix,
+            next_hop: Some(next_hop),
+            port: None,
+            source: RouteSource::Static,
+            distance: RouteSource::Static.default_distance(),
+            metric: 0,
+        }
+    }
+
+    // 0.0.0.0/0
+    pub fn default_route(next_hop: IpAddr) -> Route {
+        Route::static_route(NetworkPart::new(IpAddr::new(0), SubnetMask::new(0)), next_hop)
+    }
+}
+
+#[derive(Default)]
+pub struct RoutingTable {
+    routes: Vec<Route>,
+}
+
+impl RoutingTable {
+    pub fn new() -> RoutingTable {
+        RoutingTable { routes: Vec::new() }
+    }
+
+    // a route to the same prefix through the same next hop and port is replaced
+    pub fn add(&mut self, route: Route) {
+        self.routes.retain(|r| !(r.prefix == route.prefix && r.next_hop == route.next_hop && r.port == route.port));
+        self.routes.push(route);
+    }
+
+    pub fn remove(&mut self, prefix: NetworkPart, next_hop: Option<IpAddr>) {
+        self.routes.retain(|r| !(r.prefix == prefix && r.next_hop == next_hop));
+    }
+
+    // the longest prefix wins. among equal prefixes the lowest distance, then the lowest metric
+    pub fn lookup(&self, ip_addr: IpAddr) -> Option<&Route> {
+        self.routes
+            .iter()
+            .filter(|r| r.prefix.contains(ip_addr))
+            .min_by_key(|r| (u8::MAX - r.prefix.get_prefix(), r.distance, r.metric))
+    }
+
+    // the egress port and the address to resolve with ARP
+    pub fn resolve(&self, ip_addr: IpAddr) -> Option<(Port, IpAddr)> {
+        let route = self.lookup(ip_addr)?;
+        let next_hop = route.next_hop.unwrap_or(ip_addr);
+        let port = match route.port {
+            Some(port) => port,
+            None => {
+                // recursive lookup. the next hop must be on a connected network
+                let r = self.lookup(next_hop)?;
+                if r.next_hop.is_some() {
+                    return None;
+                }
+                r.port?
+            }
+        };
+        Some((port, next_hop))
+    }
+
+    // "show ip route"
+    pub fn dump(&self) -> String {
+        let mut routes: Vec<&Route> = self.routes.iter().collect();
+        routes.sort_by_key(|r| (r.prefix.get_value(), r.prefix.get_prefix(), r.distance, r.metric));
+        let mut s = String::new();
+        s += "Codes: C - connected, S - static, * - candidate default\n";
+        for r in routes {
+            let default = if r.prefix.get_prefix() == 0 { '*' } else { ' ' };
+            let mut line = format!("{}{}   {}", r.source.code(), default, r.prefix);
+            if let Some(next_hop) = r.next_hop {
+                line += &format!(" [{}/{}] via {}", r.distance, r.metric, next_hop);
+            } else {
+                line += " is directly connected";
+            }
+            if let Some(port) = r.port {
+                line += &format!(", port {}", port.value);
+            }
+            s += &line;
+            s += "\n";
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefix(value: u32, len: u8) -> NetworkPart {
+        NetworkPart::new(IpAddr::new(value), SubnetMask::new(len))
+    }
+
+    #[test]
+    fn test_routing_table() {
+        let mut table = RoutingTable::new();
+        table.add(Route::connected(prefix(0x0a01_0000, 24), Port::new(0)));
+        table.add(Route::default_route(IpAddr::new(0x0a01_0001)));
+        table.add(Route::static_route(prefix(0x0a00_0000, 8), IpAddr::new(0x0a01_0002)));
+        table.add(Route::static_route(prefix(0x0a03_0000, 16), IpAddr::new(0x0a01_0003)));
+        // a floating static route backs up the /16
+        let mut backup = Route::static_route(prefix(0x0a03_0000, 16), IpAddr::new(0x0a01_0004));
+        backup.distance = 200;
+        table.add(backup);
+
+        let via = |x: u32| table.resolve(IpAddr::new(x)).map(|(_, next_hop)| next_hop.value);
+        assert_eq!(Some(0x0a01_0003), via(0x0a03_0005));
+        assert_eq!(Some(0x0a01_0002), via(0x0a04_0005));
+        assert_eq!(Some(0x0a01_0001), via(0xC0A8_0001));
+        assert_eq!(Some(0x0a01_0007), via(0x0a01_0007));
+        assert_eq!(Some((Port::new(0), IpAddr::new(0x0a01_0003))), table.resolve(IpAddr::new(0x0a03_0005)));
+
+        table.remove(prefix(0x0a03_0000, 16), Some(IpAddr::new(0x0a01_0003)));
+        assert_eq!(Some(0x0a01_0004), table.resolve(IpAddr::new(0x0a03_0005)).map(|(_, next_hop)| next_hop.value));
+        assert!(table.dump().contains("S*   0.0.0.0/0 [1/0] via 10.1.0.1"));
+    }
+}
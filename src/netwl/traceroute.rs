@@ -0,0 +1,166 @@
+use super::icmp::Icmp;
+use super::ip::IP;
+use super::ip_addr::IpAddr;
+
+// the payload of a probe. the destination echoes it back
+const PROBE_MARK: u8 = 0x54;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hop {
+    pub ttl: u8,
+    pub addr: Option<IpAddr>, // None if nobody answered in time
+    pub rtt: Option<usize>,   // ticks
+}
+
+// one probe per hop. the next probe leaves when the previous one is answered or timed out
+pub struct Traceroute {
+    dst: IpAddr,
+    max_hops: u8,
+    timeout: usize,
+    ttl: u8,
+    t_sent: Option<usize>,
+    hops: Vec<Hop>,
+    done: bool,
+}
+
+impl Traceroute {
+    pub fn new(dst: IpAddr, max_hops: u8, timeout: usize) -> Traceroute {
+        Traceroute {
+            dst,
+            max_hops,
+            timeout,
+            ttl: 1,
+            t_sent: None,
+            hops: Vec::new(),
+            done: false,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    pub fn get_hops(&self) -> &Vec<Hop> {
+        &self.hops
+    }
+
+    fn probe_payload(&self) -> Vec<u8> {
+        vec![PROBE_MARK, self.ttl]
+    }
+
+    fn record(&mut self, addr: Option<IpAddr>, t: usize) {
+        let rtt = addr.and(self.t_sent.map(|t_sent| t - t_sent));
+        self.hops.push(Hop { ttl: self.ttl, addr, rtt });
+        self.t_sent = None;
+        if self.ttl >= self.max_hops {
+            self.done = true;
+        } else {
+            self.ttl += 1;
+        }
+    }
+
+    // true if the error quotes the probe in flight. a late answer to an earlier probe is not
+    fn is_current(&self, icmp: &Icmp) -> bool {
+        self.t_sent.is_some()
+            && icmp.get_original_dst() == Some(self.dst)
+            && icmp.get_original_payload().is_some_and(|xs| xs.starts_with(&self.probe_payload()))
+    }
+
+    // the probe to send, if any
+    pub fn update(&mut self, src: IpAddr, t: usize) -> Option<IP> {
+        if let Some(t_sent) = self.t_sent {
+            if t < t_sent + self.timeout {
+                return None;
+            }
+            self.record(None, t);
+        }
+        if self.done {
+            return None;
+        }
+        let mut ip = IP::new_byte(src, self.dst, self.probe_payload());
+        ip.set_ttl(self.ttl);
+        self.t_sent = Some(t);
+        Some(ip)
+    }
+
+    // true if the error is about the current probe
+    pub fn on_time_exceeded(&mut self, from: IpAddr, icmp: &Icmp, t: usize) -> bool {
+        if !self.is_current(icmp) {
+            return false;
+        }
+        self.record(Some(from), t);
+        true
+    }
+
+    // the destination or a router on the way gave an answer which ends the trace
+    pub fn on_unreachable(&mut self, from: IpAddr, icmp: &Icmp, t: usize) -> bool {
+        if !self.is_current(icmp) {
+            return false;
+        }
+        self.record(Some(from), t);
+        self.done = true;
+        true
+    }
+
+    // true if the bytes are the echo of the current probe
+    pub fn on_reply(&mut self, bytes: &[u8], t: usize) -> bool {
+        if self.t_sent.is_none() || bytes != self.probe_payload() {
+            return false;
+        }
+        self.record(Some(self.dst), t);
+        self.done = true;
+        true
+    }
+
+    pub fn dump(&self) -> String {
+        let mut s = format!("traceroute to {}, {} hops max\n", self.dst, self.max_hops);
+        for hop in &self.hops {
+            match (hop.addr, hop.rtt) {
+                (Some(addr), Some(rtt)) => s += &format!("{:>2}  {}  {} ticks\n", hop.ttl, addr, rtt),
+                _ => s += &format!("{:>2}  *\n", hop.ttl),
+            }
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::icmp::ICMP_TIME_EXCEEDED;
+
+    #[test]
+    fn test_traceroute() {
+        let src = IpAddr::new(0x0a01_0001);
+        let dst = IpAddr::new(0x0a03_0002);
+        let router = IpAddr::new(0x0a01_0003);
+        let mut tr = Traceroute::new(dst, 3, 100);
+        let probe = tr.update(src, 0).unwrap();
+        assert_eq!(1, probe.get_ttl());
+        assert!(tr.update(src, 10).is_none());
+        assert!(tr.on_time_exceeded(router, &Icmp::error(ICMP_TIME_EXCEEDED, 0, &probe), 20));
+        let probe = tr.update(src, 20).unwrap();
+        assert_eq!(2, probe.get_ttl());
+        // the second hop stays silent. its late answer is not taken for the third hop
+        let probe3 = tr.update(src, 120).unwrap();
+        assert_eq!(3, probe3.get_ttl());
+        assert!(!tr.on_time_exceeded(router, &Icmp::error(ICMP_TIME_EXCEEDED, 0, &probe), 130));
+        assert!(!tr.on_reply(&[PROBE_MARK, 2], 150));
+        assert!(tr.on_reply(&[PROBE_MARK, 3], 150));
+        assert!(tr.is_done());
+        assert_eq!(vec![
+            Hop { ttl: 1, addr: Some(IpAddr::new(0x0a01_0003)), rtt: Some(20) },
+            Hop { ttl: 2, addr: None, rtt: None },
+            Hop { ttl: 3, addr: Some(dst), rtt: Some(30) },
+        ], *tr.get_hops());
+        assert!(tr.dump().contains(" 2  *\n"));
+
+        // the last hop the ttl can count to
+        let mut tr = Traceroute::new(dst, 255, 100);
+        for t in 0..=255 {
+            tr.update(src, 100 * t);
+        }
+        assert!(tr.is_done());
+        assert_eq!(255, tr.get_hops().len());
+    }
+}
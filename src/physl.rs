@@ -2,6 +2,9 @@ pub mod device;
 pub mod repeater;
 pub mod byte_host;
 pub mod network;
+pub mod uart;
+pub mod line_code;
+pub mod duplex;
 
 use super::types::*;
 
@@ -9,6 +12,9 @@ pub use device::*;
 pub use repeater::*;
 pub use byte_host::*;
 pub use network::*;
+pub use uart::*;
+pub use line_code::*;
+pub use duplex::*;
 
 pub fn run_sample() -> Res<()> {
     println!("experimental sample run");
@@ -39,6 +45,61 @@ pub fn run_sample() -> Res<()> {
     Ok(())
 }
 
+// host1 types a message on a serial line. with different settings at the two ends
+// the receiver sees garbage and counts framing and parity errors
+pub fn run_sample_uart(tx: UartConfig, rx: UartConfig) -> Res<(Vec<u8>, UartStats)> {
+    println!("run experimental uart sample. tx: {:?}, rx: {:?}", tx, rx);
+    let mac1 = Mac::new(24);
+    let mac2 = Mac::new(25);
+    let schedules = b"Hello"
+        .iter()
+        .enumerate()
+        .map(|(i, x)| ByteLog::new(i, Port::new(0), *x))
+        .collect();
+    let host1 = Box::new(ByteHost::new(mac1, "host1", schedules));
+    let host2 = Box::new(ByteHost::new(mac2, "host2", vec![]));
+
+    let mut nw = Network::new(vec![host1, host2], vec![]);
+    nw.connect_both(mac1, Port::new(0), mac2, Port::new(0))?;
+    nw.set_uart(mac1, Port::new(0), tx)?;
+    nw.set_uart(mac2, Port::new(0), rx)?;
+    nw.run(200)?;
+
+    let stats = nw.get_uart_stats(mac2, Port::new(0))?;
+    let d = nw.get_device(mac2)?;
+    let d = d.as_any().downcast_ref::<ByteHost>().unwrap();
+    let xs: Vec<u8> = d.get_rlogs().iter().map(|log| log.get_x()).collect();
+    println!("received: {:?} ({:?})", String::from_utf8_lossy(&xs), stats);
+    Ok((xs, stats))
+}
+
+// host1 sends a message over a line code. noise inverts every flip_period-th symbol
+pub fn run_sample_line_code(code: LineCode, flip_period: Option<usize>) -> Res<(Vec<u8>, LineStats)> {
+    println!("run experimental line code sample. {:?}, {} symbols per byte", code, code.symbols_per_byte());
+    let mac1 = Mac::new(24);
+    let mac2 = Mac::new(25);
+    let schedules = b"Hello"
+        .iter()
+        .enumerate()
+        .map(|(i, x)| ByteLog::new(i, Port::new(0), *x))
+        .collect();
+    let host1 = Box::new(ByteHost::new(mac1, "host1", schedules));
+    let host2 = Box::new(ByteHost::new(mac2, "host2", vec![]));
+
+    let mut nw = Network::new(vec![host1, host2], vec![]);
+    nw.connect_both(mac1, Port::new(0), mac2, Port::new(0))?;
+    nw.set_line_code(mac1, Port::new(0), code)?;
+    nw.set_symbol_flip(mac1, Port::new(0), flip_period)?;
+    nw.run(10)?;
+
+    let stats = nw.get_line_stats(mac2, Port::new(0))?;
+    let d = nw.get_device(mac2)?;
+    let d = d.as_any().downcast_ref::<ByteHost>().unwrap();
+    let xs: Vec<u8> = d.get_rlogs().iter().map(|log| log.get_x()).collect();
+    println!("received: {:?} ({:?})", String::from_utf8_lossy(&xs), stats);
+    Ok((xs, stats))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -47,4 +108,85 @@ mod tests {
     fn test_2byte_host() {
         run_sample().unwrap();
     }
+
+    #[test]
+    fn test_uart() {
+        let config = UartConfig::default();
+        let (xs, stats) = run_sample_uart(config, config).unwrap();
+        assert_eq!(b"Hello".to_vec(), xs);
+        assert_eq!(5, stats.bytes);
+
+        // the stop bit is sampled in the middle of the data
+        let (xs, stats) = run_sample_uart(UartConfig { baud: 4800, ..config }, config).unwrap();
+        assert_ne!(b"Hello".to_vec(), xs);
+        assert!(stats.framing_errors > 0);
+
+        let (xs, stats) = run_sample_uart(config, UartConfig { parity: Parity::Even, ..config }).unwrap();
+        assert_ne!(b"Hello".to_vec(), xs);
+        assert!(stats.framing_errors + stats.parity_errors > 0);
+
+        // rejected before the run
+        assert!(run_sample_uart(UartConfig { data_bits: 16, ..config }, config).is_err());
+        assert!(run_sample_uart(config, UartConfig { baud: 0, ..config }).is_err());
+    }
+
+    #[test]
+    fn test_link_up() {
+        let mac1 = Mac::new(24);
+        let mac2 = Mac::new(25);
+        let schedules = (0..3).map(|i| ByteLog::new(20 * i, Port::new(0), i as u8)).collect();
+        let host1 = Box::new(ByteHost::new(mac1, "host1", schedules));
+        let host2 = Box::new(ByteHost::new(mac2, "host2", vec![]));
+        let mut nw = Network::new(vec![host1, host2], vec![]);
+        nw.connect_both(mac1, Port::new(0), mac2, Port::new(0)).unwrap();
+
+        // negotiating does not bring up a link shut down by the administrator
+        nw.set_link_up(mac1, Port::new(0), false).unwrap();
+        nw.set_port_config(mac1, Port::new(0), PortConfig::default()).unwrap();
+        nw.run(10).unwrap();
+        nw.set_link_up(mac1, Port::new(0), true).unwrap();
+        // the speeds do not match
+        let forced = PortConfig { autoneg: false, ..PortConfig::default() };
+        nw.set_port_config(mac1, Port::new(0), PortConfig { speed: 10, ..forced }).unwrap();
+        nw.set_port_config(mac2, Port::new(0), forced).unwrap();
+        nw.run(30).unwrap();
+        nw.set_port_config(mac1, Port::new(0), forced).unwrap();
+        nw.run(50).unwrap();
+
+        let d = nw.get_device(mac2).unwrap();
+        let d = d.as_any().downcast_ref::<ByteHost>().unwrap();
+        let xs: Vec<u8> = d.get_rlogs().iter().map(|log| log.get_x()).collect();
+        assert_eq!(vec![2], xs);
+    }
+
+    #[test]
+    fn test_line_code() {
+        for code in [LineCode::Manchester, LineCode::FourBFiveB, LineCode::Nrzi] {
+            let (xs, stats) = run_sample_line_code(code, None).unwrap();
+            assert_eq!(b"Hello".to_vec(), xs);
+            assert_eq!(5 * code.symbols_per_byte(), stats.symbols);
+        }
+
+        // the broken bytes are detected and dropped
+        let (xs, stats) = run_sample_line_code(LineCode::Manchester, Some(23)).unwrap();
+        assert!(xs.len() < 5);
+        assert!(stats.invalid_symbols > 0);
+
+        // the broken bytes are delivered
+        let (xs, stats) = run_sample_line_code(LineCode::Nrzi, Some(11)).unwrap();
+        assert_eq!(5, xs.len());
+        assert_ne!(b"Hello".to_vec(), xs);
+        assert_eq!(0, stats.invalid_symbols);
+
+        // a cable is either a serial line or carries a line code
+        let macs: Vec<Mac> = (24..28).map(Mac::new).collect();
+        let hosts: Vec<Box<dyn Device>> = macs.iter().map(|mac| Box::new(ByteHost::new(*mac, "host", vec![])) as Box<dyn Device>).collect();
+        let mut nw = Network::new(hosts, vec![]);
+        nw.connect_both(macs[0], Port::new(0), macs[1], Port::new(0)).unwrap();
+        nw.set_uart(macs[0], Port::new(0), UartConfig::default()).unwrap();
+        assert!(nw.set_line_code(macs[1], Port::new(0), LineCode::Manchester).is_err());
+        nw.connect_both(macs[2], Port::new(0), macs[3], Port::new(0)).unwrap();
+        nw.set_line_code(macs[2], Port::new(0), LineCode::Manchester).unwrap();
+        assert!(nw.set_uart(macs[3], Port::new(0), UartConfig::default()).is_err());
+    }
 }
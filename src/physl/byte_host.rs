@@ -35,6 +35,10 @@ impl Device for ByteHost {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
     fn update(&mut self, ctx: &UpdateContext) -> Res<()> {
         while let Some((port, x)) = self.base.recv() {
             self.rlogs.push(ByteLog { t: ctx.t, port, x });
@@ -59,4 +63,8 @@ impl ByteLog {
     pub fn new(t: usize, port: Port, x: u8) -> ByteLog {
         ByteLog { t, port, x }
     }
+
+    pub fn get_x(&self) -> u8 {
+        self.x
+    }
 }
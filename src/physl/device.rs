@@ -1,4 +1,4 @@
-use std::{collections::VecDeque, any::Any};
+use std::{collections::{VecDeque, HashSet}, any::Any};
 use super::super::types::*;
 
 pub trait Device {
@@ -23,11 +23,21 @@ pub trait Device {
     }
 
     fn pop_send(&mut self) -> Option<(Port, u8)> {
-        self.base_mut().send_buf.pop_front()
+        self.base_mut().pop_send()
+    }
+
+    fn peek_send(&self) -> Option<(Port, bool)> {
+        self.base().peek_send()
+    }
+
+    fn abort_frame(&mut self, retransmit: bool) {
+        self.base_mut().abort_frame(retransmit)
     }
 
     fn as_any(&self) -> &dyn Any;
 
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
     fn update(&mut self, _ctx: &UpdateContext) -> Res<()>;
 }
 
@@ -37,6 +47,11 @@ pub struct BaseDevice {
     num_ports: usize,
     recv_buf: VecDeque<(Port, u8)>,
     send_buf: VecDeque<(Port, u8)>,
+    frame_ends: VecDeque<(usize, bool)>, // end of each queued frame in bytes sent so far, and if it is a control frame
+    frame_start: usize,
+    sent: usize,
+    current: Vec<(Port, u8)>, // bytes of the frame on the wire
+    paused: HashSet<Port>,
 }
 
 impl BaseDevice {
@@ -47,6 +62,11 @@ impl BaseDevice {
             num_ports,
             recv_buf: VecDeque::new(),
             send_buf: VecDeque::new(),
+            frame_ends: VecDeque::new(),
+            frame_start: 0,
+            sent: 0,
+            current: Vec::new(),
+            paused: HashSet::new(),
         }
     }
 
@@ -69,5 +89,107 @@ impl BaseDevice {
     pub fn send(&mut self, x: (Port, u8)) {
         self.send_buf.push_back(x)
     }
+
+    pub fn send_frame(&mut self, port: Port, bytes: &[u8]) {
+        for x in bytes {
+            self.send_buf.push_back((port, *x));
+        }
+        self.frame_ends.push_back((self.sent + self.send_buf.len(), false));
+    }
+
+    // control frames overtake the queued frames, but never split the frame on the wire
+    pub fn send_control_frame(&mut self, port: Port, bytes: &[u8]) {
+        let at = match self.frame_ends.front() {
+            Some((end, _)) if self.sent > self.frame_start => end - self.sent,
+            _ => 0,
+        };
+        for (i, x) in bytes.iter().enumerate() {
+            self.send_buf.insert(at + i, (port, *x));
+        }
+        let end = self.sent + at;
+        let idx = self.frame_ends.iter().position(|(e, _)| *e > end).unwrap_or(self.frame_ends.len());
+        for e in self.frame_ends.iter_mut().skip(idx) {
+            e.0 += bytes.len();
+        }
+        self.frame_ends.insert(idx, (end + bytes.len(), true));
+    }
+
+    // a paused port finishes the current frame, then holds the whole queue
+    pub fn pop_send(&mut self) -> Option<(Port, u8)> {
+        let (port, _) = *self.send_buf.front()?;
+        let is_control = self.frame_ends.front().is_some_and(|(_, control)| *control);
+        if self.sent == self.frame_start && !is_control && self.paused.contains(&port) {
+            return None;
+        }
+        if self.sent == self.frame_start {
+            self.current.clear();
+        }
+        let x = self.send_buf.pop_front()?;
+        self.current.push(x);
+        self.sent += 1;
+        if self.frame_ends.front().is_some_and(|(end, _)| *end == self.sent) {
+            self.frame_ends.pop_front();
+            self.frame_start = self.sent;
+        }
+        Some(x)
+    }
+
+    // the port of the next byte and if it starts a frame. None if pop_send would return None
+    pub fn peek_send(&self) -> Option<(Port, bool)> {
+        let (port, _) = *self.send_buf.front()?;
+        let is_control = self.frame_ends.front().is_some_and(|(_, control)| *control);
+        let start = self.sent == self.frame_start;
+        if start && !is_control && self.paused.contains(&port) {
+            return None;
+        }
+        Some((port, start))
+    }
+
+    // stop sending the frame on the wire after a collision. the whole frame is queued again to retransmit it
+    pub fn abort_frame(&mut self, retransmit: bool) {
+        if self.sent == self.frame_start {
+            return;
+        }
+        let Some((end, control)) = self.frame_ends.pop_front() else { return };
+        let rest: Vec<(Port, u8)> = self.send_buf.drain(..end - self.sent).collect();
+        let mut frame = std::mem::take(&mut self.current);
+        let removed = rest.len();
+        for e in self.frame_ends.iter_mut() {
+            e.0 -= removed;
+        }
+        self.frame_start = self.sent;
+        if retransmit {
+            frame.extend(rest);
+            for e in self.frame_ends.iter_mut() {
+                e.0 += frame.len();
+            }
+            self.frame_ends.push_front((self.sent + frame.len(), control));
+            for x in frame.into_iter().rev() {
+                self.send_buf.push_front(x);
+            }
+        }
+    }
+
+    // marks the end of a frame sent byte by byte
+    pub fn end_frame(&mut self) {
+        if self.send_buf.is_empty() {
+            self.frame_start = self.sent;
+        } else {
+            self.frame_ends.push_back((self.sent + self.send_buf.len(), false));
+        }
+    }
+
+    // bytes waiting for transmission
+    pub fn get_send_len(&self) -> usize {
+        self.send_buf.len()
+    }
+
+    pub fn set_paused(&mut self, port: Port, paused: bool) {
+        if paused {
+            self.paused.insert(port);
+        } else {
+            self.paused.remove(&port);
+        }
+    }
 }
 
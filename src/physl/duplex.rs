@@ -0,0 +1,175 @@
+// a slot is 512 bit times. a collision after the first slot of a frame is late
+pub const SLOT_BYTES: usize = 64;
+const ATTEMPT_LIMIT: usize = 16;
+const BACKOFF_LIMIT: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplex {
+    Full,
+    Half, // CSMA/CD. the port cannot send while it is receiving
+}
+
+// with autonegotiation speed and duplex are the best the port advertises, otherwise they are forced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortConfig {
+    pub autoneg: bool,
+    pub speed: usize, // Mbit/s
+    pub duplex: Duplex,
+}
+
+impl Default for PortConfig {
+    fn default() -> PortConfig {
+        PortConfig {
+            autoneg: true,
+            speed: 100,
+            duplex: Duplex::Full,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhyLink {
+    pub speed: usize,
+    pub duplex: Duplex,
+}
+
+// the links of both ends, or None if they cannot agree on a speed.
+// an autonegotiating port facing a forced port detects the speed but has to assume half duplex
+pub fn negotiate(a: PortConfig, b: PortConfig) -> Option<(PhyLink, PhyLink)> {
+    let forced = |c: PortConfig| PhyLink { speed: c.speed, duplex: c.duplex };
+    let parallel = |c: PortConfig| PhyLink { speed: c.speed, duplex: Duplex::Half };
+    match (a.autoneg, b.autoneg) {
+        (true, true) => {
+            let duplex = if a.duplex == Duplex::Full && b.duplex == Duplex::Full { Duplex::Full } else { Duplex::Half };
+            let link = PhyLink { speed: a.speed.min(b.speed), duplex };
+            Some((link, link))
+        }
+        (true, false) if b.speed <= a.speed => Some((parallel(b), forced(b))),
+        (false, true) if a.speed <= b.speed => Some((forced(a), parallel(a))),
+        (false, false) if a.speed == b.speed => Some((forced(a), forced(b))),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PhyStats {
+    pub collisions: usize,
+    pub late_collisions: usize,
+    pub excessive_collisions: usize, // frames given up after 16 attempts
+}
+
+// what the network does with the frame of a port after a collision
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionAction {
+    Retransmit,
+    Abort,
+    Ignore, // a full duplex port does not notice
+}
+
+// the physical layer of a port
+pub struct Phy {
+    config: PortConfig,
+    link: Option<PhyLink>,
+    tx_bytes: usize, // of the frame on the wire
+    attempts: usize,
+    backoff_until: usize,
+    seed: u64,
+    stats: PhyStats,
+}
+
+impl Phy {
+    pub fn new(config: PortConfig, seed: u64) -> Phy {
+        Phy {
+            config,
+            link: None,
+            tx_bytes: 0,
+            attempts: 0,
+            backoff_until: 0,
+            seed,
+            stats: PhyStats::default(),
+        }
+    }
+
+    pub fn get_config(&self) -> PortConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: PortConfig) {
+        self.config = config;
+    }
+
+    pub fn get_link(&self) -> Option<PhyLink> {
+        self.link
+    }
+
+    pub fn set_link(&mut self, link: Option<PhyLink>) {
+        self.link = link;
+    }
+
+    pub fn get_stats(&self) -> PhyStats {
+        self.stats
+    }
+
+    pub fn is_half_duplex(&self) -> bool {
+        self.link.is_some_and(|link| link.duplex == Duplex::Half)
+    }
+
+    // a half duplex port waits while the peer is sending and while it backs off
+    pub fn defers(&self, carrier: bool, t: usize) -> bool {
+        self.is_half_duplex() && (carrier || t < self.backoff_until)
+    }
+
+    pub fn transmit(&mut self, frame_start: bool) {
+        if frame_start {
+            self.tx_bytes = 0;
+        }
+        self.tx_bytes += 1;
+        if self.tx_bytes == SLOT_BYTES {
+            // the frame has acquired the wire
+            self.attempts = 0;
+        }
+    }
+
+    pub fn collide(&mut self, t: usize) -> CollisionAction {
+        if !self.is_half_duplex() {
+            return CollisionAction::Ignore;
+        }
+        if self.tx_bytes > SLOT_BYTES {
+            self.stats.late_collisions += 1;
+            return CollisionAction::Abort;
+        }
+        self.stats.collisions += 1;
+        self.attempts += 1;
+        if self.attempts >= ATTEMPT_LIMIT {
+            self.stats.excessive_collisions += 1;
+            self.attempts = 0;
+            return CollisionAction::Abort;
+        }
+        // truncated binary exponential backoff
+        self.seed = self.seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let slots = (self.seed >> 33) as usize % (1 << self.attempts.min(BACKOFF_LIMIT));
+        self.backoff_until = t + 1 + slots * SLOT_BYTES;
+        CollisionAction::Retransmit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        let auto = PortConfig::default();
+        let full = PortConfig { autoneg: false, ..auto };
+        let half10 = PortConfig { autoneg: false, speed: 10, duplex: Duplex::Half };
+
+        let (a, b) = negotiate(auto, auto).unwrap();
+        assert_eq!((Duplex::Full, Duplex::Full), (a.duplex, b.duplex));
+        // the classic duplex mismatch
+        let (a, b) = negotiate(full, auto).unwrap();
+        assert_eq!((Duplex::Full, Duplex::Half), (a.duplex, b.duplex));
+        let (a, _) = negotiate(auto, half10).unwrap();
+        assert_eq!(PhyLink { speed: 10, duplex: Duplex::Half }, a);
+        assert!(negotiate(full, half10).is_none());
+    }
+}
@@ -0,0 +1,163 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineCode {
+    Manchester, // a bit is two half-bit symbols. 0 is high-low, 1 is low-high (IEEE 802.3)
+    FourBFiveB, // a nibble is a code group of five symbols, the lower nibble first
+    Nrzi,       // 1 is a transition, 0 is none. errors cannot be detected
+}
+
+// the 4B5B code groups of the data nibbles
+const CODE_GROUPS: [u8; 16] = [
+    0b11110, 0b01001, 0b10100, 0b10101, 0b01010, 0b01011, 0b01110, 0b01111,
+    0b10010, 0b10011, 0b10110, 0b10111, 0b11010, 0b11011, 0b11100, 0b11101,
+];
+
+impl LineCode {
+    // symbols carrying a byte
+    pub fn symbols_per_byte(&self) -> usize {
+        match self {
+            LineCode::Manchester => 16,
+            LineCode::FourBFiveB => 10,
+            LineCode::Nrzi => 8,
+        }
+    }
+}
+
+fn bits_msb_first(x: u8, n: usize) -> impl Iterator<Item = bool> {
+    (0..n).rev().map(move |i| (x >> i) & 1 == 1)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineStats {
+    pub symbols: usize,
+    pub invalid_symbols: usize,
+    pub bytes: usize,
+}
+
+// one direction of a cable carrying symbols instead of bytes
+pub struct CodedLine {
+    code: LineCode,
+    tx_level: bool, // NRZI state of the transmitter
+    rx_level: bool, // and of the receiver
+    flip_period: Option<usize>,
+    stats: LineStats,
+}
+
+impl CodedLine {
+    pub fn new(code: LineCode) -> CodedLine {
+        CodedLine {
+            code,
+            tx_level: false,
+            rx_level: false,
+            flip_period: None,
+            stats: LineStats::default(),
+        }
+    }
+
+    pub fn get_stats(&self) -> LineStats {
+        self.stats
+    }
+
+    // noise. every period-th symbol is inverted on the wire
+    pub fn set_flip_period(&mut self, period: Option<usize>) {
+        self.flip_period = period;
+    }
+
+    pub fn encode(&mut self, x: u8) -> Vec<bool> {
+        match self.code {
+            LineCode::Manchester => bits_msb_first(x, 8).flat_map(|bit| [!bit, bit]).collect(),
+            LineCode::FourBFiveB => {
+                let mut symbols: Vec<bool> = bits_msb_first(CODE_GROUPS[(x & 0x0F) as usize], 5).collect();
+                symbols.extend(bits_msb_first(CODE_GROUPS[(x >> 4) as usize], 5));
+                symbols
+            }
+            LineCode::Nrzi => bits_msb_first(x, 8)
+                .map(|bit| {
+                    self.tx_level ^= bit;
+                    self.tx_level
+                })
+                .collect(),
+        }
+    }
+
+    // None if a symbol is invalid
+    pub fn decode(&mut self, symbols: &[bool]) -> Option<u8> {
+        let mut invalid = 0;
+        let x = match self.code {
+            LineCode::Manchester => symbols.chunks(2).fold(0u8, |x, pair| {
+                if pair[0] == pair[1] {
+                    invalid += 2;
+                }
+                x << 1 | pair[1] as u8
+            }),
+            LineCode::FourBFiveB => {
+                let mut nibbles = symbols.chunks(5).map(|group| {
+                    let group = group.iter().fold(0u8, |g, s| g << 1 | *s as u8);
+                    CODE_GROUPS.iter().position(|g| *g == group).unwrap_or_else(|| {
+                        invalid += 5;
+                        0
+                    }) as u8
+                });
+                let lo = nibbles.next().unwrap_or(0);
+                let hi = nibbles.next().unwrap_or(0);
+                hi << 4 | lo
+            }
+            LineCode::Nrzi => symbols.iter().fold(0u8, |x, level| {
+                let bit = *level != self.rx_level;
+                self.rx_level = *level;
+                x << 1 | bit as u8
+            }),
+        };
+        self.stats.invalid_symbols += invalid;
+        if invalid > 0 {
+            return None;
+        }
+        self.stats.bytes += 1;
+        Some(x)
+    }
+
+    // a byte through the encoder, the noise and the decoder
+    pub fn transfer(&mut self, x: u8) -> Option<u8> {
+        let mut symbols = self.encode(x);
+        for s in symbols.iter_mut() {
+            self.stats.symbols += 1;
+            if self.flip_period.is_some_and(|period| self.stats.symbols.is_multiple_of(period)) {
+                *s = !*s;
+            }
+        }
+        self.decode(&symbols)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_code() {
+        for code in [LineCode::Manchester, LineCode::FourBFiveB, LineCode::Nrzi] {
+            let mut line = CodedLine::new(code);
+            for x in [0x00, 0x5A, 0xFF] {
+                assert_eq!(code.symbols_per_byte(), CodedLine::new(code).encode(x).len());
+                assert_eq!(Some(x), line.transfer(x));
+            }
+        }
+        assert_eq!(vec![true, false, false, true], CodedLine::new(LineCode::Manchester).encode(0x40)[0..4]);
+
+        // a flipped half-bit leaves a pair without transition
+        let mut line = CodedLine::new(LineCode::Manchester);
+        line.set_flip_period(Some(3));
+        assert_eq!(None, line.transfer(0x00));
+        assert!(line.get_stats().invalid_symbols > 0);
+
+        // 0 is 11110, the flip turns it into the idle code group 11111
+        let mut line = CodedLine::new(LineCode::FourBFiveB);
+        line.set_flip_period(Some(5));
+        assert_eq!(None, line.transfer(0x00));
+
+        // NRZI cannot tell. the flip corrupts two bits
+        let mut line = CodedLine::new(LineCode::Nrzi);
+        line.set_flip_period(Some(5));
+        assert_eq!(Some(0b0000_1100), line.transfer(0x00));
+        assert_eq!(0, line.get_stats().invalid_symbols);
+    }
+}
@@ -1,16 +1,33 @@
 use super::super::types::*;
 use super::device::*;
+use super::uart::*;
+use super::line_code::*;
+use super::duplex::*;
 
 pub struct Connection {
     pub mac0: Mac,
     pub port0: Port,
     pub mac1: Mac,
     pub port1: Port,
+    pub up: bool,                        // administrative state. see is_up
+    pub uart_config: Option<UartConfig>, // the serial settings of port0
+    pub uart: Option<UartLine>,          // bits from port0 to port1
+    pub line: Option<CodedLine>,         // symbols from port0 to port1
+    pub phy: Option<Phy>,                // speed and duplex of port0
+    pub last_tx: Option<usize>,          // port0 sent a byte
+}
+
+impl Connection {
+    // administratively up and, with a PHY, a link was negotiated
+    pub fn is_up(&self) -> bool {
+        self.up && self.phy.as_ref().is_none_or(|phy| phy.get_link().is_some())
+    }
 }
 
 pub struct Network {
     devices: Vec<Box<dyn Device>>,
     connections: Vec<Connection>,
+    t: usize,
 }
 
 impl Network {
@@ -19,6 +36,7 @@ impl Network {
         Network {
             devices,
             connections: medias,
+            t: 0,
         }
     }
 
@@ -65,6 +83,12 @@ impl Network {
             port0,
             mac1,
             port1,
+            up: true,
+            uart_config: None,
+            uart: None,
+            line: None,
+            phy: None,
+            last_tx: None,
         });
         Ok(())
     }
@@ -75,6 +99,134 @@ impl Network {
         Ok(())
     }
 
+    // bytes sent on a link which is down are lost
+    pub fn set_link_up(&mut self, mac: Mac, port: Port, up: bool) -> Res<()> {
+        let (peer_mac, peer_port) = {
+            let c = self.find_connection(mac, port)?;
+            (c.mac1, c.port1)
+        };
+        for c in &mut self.connections {
+            if (c.mac0 == mac && c.port0 == port) || (c.mac0 == peer_mac && c.port0 == peer_port) {
+                c.up = up;
+            }
+        }
+        Ok(())
+    }
+
+    // make the cable a serial line. each end has its own settings, an end not configured uses 9600 8N1
+    pub fn set_uart(&mut self, mac: Mac, port: Port, config: UartConfig) -> Res<()> {
+        config.validate()?;
+        let (peer_mac, peer_port) = self.get_peer(mac, port)?;
+        if self.find_connection(mac, port)?.line.is_some() {
+            return Err(Error::NetworkConnectFailed { mac0: mac, mac1: peer_mac, msg: "the cable already has a line code".to_string() });
+        }
+        let peer_config = self.find_connection(peer_mac, peer_port)?.uart_config.unwrap_or_default();
+        for c in &mut self.connections {
+            if c.mac0 == mac && c.port0 == port {
+                c.uart_config = Some(config);
+                c.uart = Some(UartLine::new(config, peer_config));
+            } else if c.mac0 == peer_mac && c.port0 == peer_port {
+                c.uart = Some(UartLine::new(peer_config, config));
+            }
+        }
+        Ok(())
+    }
+
+    // what the receiver of the port made of the serial line
+    pub fn get_uart_stats(&self, mac: Mac, port: Port) -> Res<UartStats> {
+        let (peer_mac, peer_port) = self.get_peer(mac, port)?;
+        let c = self.find_connection(peer_mac, peer_port)?;
+        Ok(c.uart.as_ref().map(|uart| uart.get_stats()).unwrap_or_default())
+    }
+
+    // both directions of the cable carry symbols of the line code. a serial line has no line code
+    pub fn set_line_code(&mut self, mac: Mac, port: Port, code: LineCode) -> Res<()> {
+        let (peer_mac, peer_port) = self.get_peer(mac, port)?;
+        if self.find_connection(mac, port)?.uart.is_some() {
+            return Err(Error::NetworkConnectFailed { mac0: mac, mac1: peer_mac, msg: "the cable is a serial line".to_string() });
+        }
+        for c in &mut self.connections {
+            if (c.mac0 == mac && c.port0 == port) || (c.mac0 == peer_mac && c.port0 == peer_port) {
+                c.line = Some(CodedLine::new(code));
+            }
+        }
+        Ok(())
+    }
+
+    // invert every period-th symbol sent from the port
+    pub fn set_symbol_flip(&mut self, mac: Mac, port: Port, period: Option<usize>) -> Res<()> {
+        let c = self.connections
+            .iter_mut()
+            .find(|c| c.mac0 == mac && c.port0 == port)
+            .ok_or(Error::ConnectionNotFound { mac, port })?;
+        match &mut c.line {
+            Some(line) => {
+                line.set_flip_period(period);
+                Ok(())
+            }
+            None => Err(Error::NetworkConnectFailed { mac0: c.mac0, mac1: c.mac1, msg: "no line code on the cable".to_string() }),
+        }
+    }
+
+    // the symbols decoded by the receiver of the port
+    pub fn get_line_stats(&self, mac: Mac, port: Port) -> Res<LineStats> {
+        let (peer_mac, peer_port) = self.get_peer(mac, port)?;
+        let c = self.find_connection(peer_mac, peer_port)?;
+        Ok(c.line.as_ref().map(|line| line.get_stats()).unwrap_or_default())
+    }
+
+    // configure speed and duplex of the port and negotiate the link with the peer.
+    // a peer not configured autonegotiates. the cable is down if the ends cannot agree
+    pub fn set_port_config(&mut self, mac: Mac, port: Port, config: PortConfig) -> Res<()> {
+        let (peer_mac, peer_port) = self.get_peer(mac, port)?;
+        let peer_config = self.find_connection(peer_mac, peer_port)?.phy.as_ref().map_or(PortConfig::default(), |phy| phy.get_config());
+        let links = negotiate(config, peer_config);
+        for c in &mut self.connections {
+            let (config, link) = if c.mac0 == mac && c.port0 == port {
+                (config, links.map(|(link, _)| link))
+            } else if c.mac0 == peer_mac && c.port0 == peer_port {
+                (peer_config, links.map(|(_, link)| link))
+            } else {
+                continue;
+            };
+            // the counters of an existing PHY are kept
+            let seed = c.mac0.value ^ (c.port0.value as u64) << 48;
+            let phy = c.phy.get_or_insert_with(|| Phy::new(config, seed));
+            phy.set_config(config);
+            phy.set_link(link);
+        }
+        Ok(())
+    }
+
+    pub fn get_phy(&self, mac: Mac, port: Port) -> Res<Option<&Phy>> {
+        Ok(self.find_connection(mac, port)?.phy.as_ref())
+    }
+
+    fn find_connection_mut(&mut self, mac: Mac, port: Port) -> Res<&mut Connection> {
+        self.connections
+            .iter_mut()
+            .find(|c| c.mac0 == mac && c.port0 == port)
+            .ok_or(Error::ConnectionNotFound { mac, port })
+    }
+
+    // a half duplex port does not start a frame while the peer is sending or while it backs off
+    fn defers(&self, mac: Mac, port: Port, t: usize) -> bool {
+        let Ok(c) = self.find_connection(mac, port) else { return false };
+        let Some(phy) = &c.phy else { return false };
+        let carrier = self.find_connection(c.mac1, c.port1).is_ok_and(|peer| t > 0 && peer.last_tx == Some(t - 1));
+        phy.defers(carrier, t)
+    }
+
+    // the device and port at the other end of the cable
+    pub fn get_peer(&self, mac: Mac, port: Port) -> Res<(Mac, Port)> {
+        let c = self.find_connection(mac, port)?;
+        Ok((c.mac1, c.port1))
+    }
+
+    pub fn get_t(&self) -> usize {
+        self.t
+    }
+
     pub fn get_device(&mut self, mac: Mac) -> Res<&mut Box<dyn Device>> {
         self.devices
             .iter_mut()
@@ -82,11 +234,10 @@ impl Network {
             .ok_or(Error::DeviceNotFound { mac })
     }
 
-    fn find_connection(&self, mac: Mac, port: Port) -> Res<(Mac, Port)> {
+    fn find_connection(&self, mac: Mac, port: Port) -> Res<&Connection> {
         self.connections
             .iter()
             .find(|c| c.mac0 == mac && c.port0 == port)
-            .map(|c| (c.mac1, c.port1))
             .ok_or(Error::ConnectionNotFound { mac, port })
     }
 
@@ -95,20 +246,73 @@ impl Network {
         if disp {
             print!("{:>2}: ", t);
         }
+        let mut txs = vec![];
         for idx in 0..self.devices.len() {
-            let d = &mut self.devices[idx];
-            if let Some((src_port, x)) = d.pop_send() {
-                let src_mac = d.get_mac();
-                let (dst_mac, dst_port) = self.find_connection(src_mac, src_port)?;
+            let src_mac = self.devices[idx].get_mac();
+            let Some((src_port, start)) = self.devices[idx].peek_send() else { continue };
+            if start && self.defers(src_mac, src_port, t) {
+                continue;
+            }
+            if let Some((src_port, x)) = self.devices[idx].pop_send() {
+                txs.push((src_mac, src_port, x, start));
+            }
+        }
+        for &(src_mac, src_port, x, start) in &txs {
+            let c = self.find_connection_mut(src_mac, src_port)?;
+            let (dst_mac, dst_port) = (c.mac1, c.port1);
+            if !c.is_up() {
+                if disp {
+                    print!("{:}:{:} -> (link down)     ", src_mac.value, src_port.value);
+                }
+                continue;
+            }
+            c.last_tx = Some(t);
+            if let Some(phy) = &mut c.phy {
+                phy.transmit(start);
+            }
+            // both ends send at once. a half duplex end sees a collision and the bytes are lost
+            let both = txs.iter().any(|(mac, port, _, _)| *mac == dst_mac && *port == dst_port);
+            let half = |c: &Connection| c.phy.as_ref().is_some_and(|phy| phy.is_half_duplex());
+            if both && (half(self.find_connection(src_mac, src_port)?) || half(self.find_connection(dst_mac, dst_port)?)) {
+                let action = self.find_connection_mut(src_mac, src_port)?.phy.as_mut().map_or(CollisionAction::Ignore, |phy| phy.collide(t));
                 if disp {
-                    print!(
-                        "{:}:{:} -> {:}:{:} : 0x{:0>2X}     ",
-                        src_mac.value, src_port.value, dst_mac.value, dst_port.value, x
-                    );
+                    print!("{:}:{:} -> collision({:?})     ", src_mac.value, src_port.value, action);
+                }
+                match action {
+                    CollisionAction::Retransmit => self.get_device(src_mac)?.abort_frame(true),
+                    CollisionAction::Abort => self.get_device(src_mac)?.abort_frame(false),
+                    CollisionAction::Ignore => {}
                 }
+                continue;
+            }
+            if disp {
+                print!(
+                    "{:}:{:} -> {:}:{:} : 0x{:0>2X}     ",
+                    src_mac.value, src_port.value, dst_mac.value, dst_port.value, x
+                );
+            }
+            let c = self.find_connection_mut(src_mac, src_port)?;
+            if let Some(uart) = &mut c.uart {
+                uart.send(t, x);
+            } else if let Some(line) = &mut c.line {
+                // a byte with an invalid symbol is lost
+                if let Some(x) = line.transfer(x) {
+                    self.get_device(dst_mac)?.push_recv(dst_port, x);
+                }
+            } else {
                 self.get_device(dst_mac)?.push_recv(dst_port, x);
             }
         }
+        // serial lines deliver a character once its stop bits have been sampled
+        let mut received = vec![];
+        for c in &mut self.connections {
+            if let Some(uart) = &mut c.uart {
+                received.extend(uart.recv(t).into_iter().map(|x| (c.mac1, c.port1, x)));
+            }
+        }
+        for (mac, port, x) in received {
+            self.get_device(mac)?.push_recv(port, x);
+        }
         for d in &mut self.devices {
             let ctx = UpdateContext { t };
             d.update(&ctx)?;
@@ -119,15 +323,19 @@ impl Network {
         Ok(())
     }
 
+    // run until time maxt. calling it again resumes from the last time
     pub fn run(&mut self, maxt: usize) -> Res<()> {
-        if crate::output::is_byte_level() {
-            println!(" t: src -> dst : x");
-        } else if crate::output::is_frame_level() {
-            println!(" t: device    : action : frame");
+        if self.t == 0 {
+            if crate::output::is_byte_level() {
+                println!(" t: src -> dst : x");
+            } else if crate::output::is_frame_level() {
+                println!(" t: device    : action : frame");
+            }
         }
         
-        for t in 0..maxt {
+        for t in self.t..maxt {
             self.update(t)?;
+            self.t = t + 1;
         }
         Ok(())
     }
@@ -27,6 +27,10 @@ impl Device for Repeater {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
     fn update(&mut self, _ctx: &UpdateContext) -> Res<()> {
         while let Some((p, x)) = self.base.recv() {
             self.base.send((Port::new(1 - p.value), x));
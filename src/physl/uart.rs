@@ -0,0 +1,243 @@
+use std::collections::VecDeque;
+
+use crate::types::{Error, Res};
+
+// a tick of the simulator on a serial line. a bit at 9600 baud lasts a tick
+pub const UART_TICKS_PER_SECOND: f64 = 9600.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UartConfig {
+    pub baud: usize,
+    pub data_bits: usize, // 5 to 8
+    pub parity: Parity,
+    pub stop_bits: usize, // 1 or 2
+}
+
+impl Default for UartConfig {
+    // 9600 8N1
+    fn default() -> UartConfig {
+        UartConfig {
+            baud: 9600,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+        }
+    }
+}
+
+impl UartConfig {
+    pub fn validate(&self) -> Res<()> {
+        let msg = if self.baud == 0 {
+            "baud must not be 0".to_string()
+        } else if !(5..=8).contains(&self.data_bits) {
+            format!("{} data bits. 5 to 8 are supported", self.data_bits)
+        } else if !(1..=2).contains(&self.stop_bits) {
+            format!("{} stop bits. 1 or 2 are supported", self.stop_bits)
+        } else {
+            return Ok(());
+        };
+        Err(Error::InvalidConfig { msg })
+    }
+
+    fn bit_time(&self) -> f64 {
+        UART_TICKS_PER_SECOND / self.baud as f64
+    }
+
+    // true when the parity bit is set for the data
+    fn parity_bit(&self, x: u8) -> bool {
+        let ones = x.count_ones() % 2 == 1;
+        match self.parity {
+            Parity::Even => ones,
+            _ => !ones,
+        }
+    }
+
+    // the levels on the line. idle and stop bits are high, the start bit is low, data is LSB first
+    pub fn encode(&self, x: u8) -> Vec<bool> {
+        let x = x & ((1u16 << self.data_bits) - 1) as u8;
+        let mut bits = vec![false];
+        bits.extend((0..self.data_bits).map(|i| (x >> i) & 1 == 1));
+        if self.parity != Parity::None {
+            bits.push(self.parity_bit(x));
+        }
+        bits.extend(std::iter::repeat_n(true, self.stop_bits));
+        bits
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UartStats {
+    pub bytes: usize,
+    pub framing_errors: usize, // a stop bit was low
+    pub parity_errors: usize,
+}
+
+// a character on the line
+struct UartFrame {
+    t_start: f64,
+    bit_time: f64,
+    bits: Vec<bool>,
+}
+
+impl UartFrame {
+    fn t_end(&self) -> f64 {
+        self.t_start + self.bit_time * self.bits.len() as f64
+    }
+}
+
+// one direction of a serial cable. the transmitter and the receiver use the settings of their own port
+pub struct UartLine {
+    tx: UartConfig,
+    rx: UartConfig,
+    frames: VecDeque<UartFrame>,
+    t_search: f64, // the receiver looks for a start bit from here
+    stats: UartStats,
+}
+
+impl UartLine {
+    pub fn new(tx: UartConfig, rx: UartConfig) -> UartLine {
+        UartLine {
+            tx,
+            rx,
+            frames: VecDeque::new(),
+            t_search: 0.0,
+            stats: UartStats::default(),
+        }
+    }
+
+    pub fn get_stats(&self) -> UartStats {
+        self.stats
+    }
+
+    // characters wait in the transmit FIFO while the line is busy
+    pub fn send(&mut self, t: usize, x: u8) {
+        let t_start = self.frames.back().map_or(t as f64, |f| f.t_end().max(t as f64));
+        self.frames.push_back(UartFrame { t_start, bit_time: self.tx.bit_time(), bits: self.tx.encode(x) });
+    }
+
+    fn level(&self, t: f64) -> bool {
+        for f in &self.frames {
+            if f.t_start <= t && t < f.t_end() {
+                return f.bits[((t - f.t_start) / f.bit_time) as usize];
+            }
+        }
+        true
+    }
+
+    // the first high to low transition at or after t
+    fn falling_edge(&self, t: f64) -> Option<f64> {
+        let mut prev_high = true;
+        let mut prev_end = f64::NEG_INFINITY;
+        for f in &self.frames {
+            if f.t_start > prev_end {
+                prev_high = true; // the line was idle between the characters
+            }
+            for (i, bit) in f.bits.iter().enumerate() {
+                let t_bit = f.t_start + f.bit_time * i as f64;
+                if prev_high && !*bit && t_bit >= t {
+                    return Some(t_bit);
+                }
+                prev_high = *bit;
+            }
+            prev_end = f.t_end();
+        }
+        None
+    }
+
+    // characters the receiver has sampled by the end of tick t
+    pub fn recv(&mut self, t: usize) -> Vec<u8> {
+        let now = (t + 1) as f64;
+        let mut xs = vec![];
+        while let Some(t_edge) = self.falling_edge(self.t_search) {
+            let rx = self.rx;
+            let samples = 1 + rx.data_bits + (rx.parity != Parity::None) as usize + rx.stop_bits;
+            let sample = |k: usize| t_edge + rx.bit_time() * (k as f64 + 0.5);
+            if sample(samples - 1) >= now {
+                break;
+            }
+            if self.level(sample(0)) {
+                // a glitch, not a start bit
+                self.t_search = sample(0);
+                continue;
+            }
+            let mut x = 0u8;
+            for i in 0..rx.data_bits {
+                if self.level(sample(1 + i)) {
+                    x |= 1 << i;
+                }
+            }
+            let mut k = 1 + rx.data_bits;
+            let parity_ok = if rx.parity == Parity::None {
+                true
+            } else {
+                k += 1;
+                self.level(sample(k - 1)) == rx.parity_bit(x)
+            };
+            let framing_ok = (k..samples).all(|k| self.level(sample(k)));
+            self.t_search = sample(samples - 1);
+            if !framing_ok {
+                self.stats.framing_errors += 1;
+            } else if !parity_ok {
+                self.stats.parity_errors += 1;
+            } else {
+                self.stats.bytes += 1;
+                xs.push(x);
+            }
+        }
+        while self.frames.front().is_some_and(|f| f.t_end() <= self.t_search) {
+            self.frames.pop_front();
+        }
+        xs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(tx: UartConfig, rx: UartConfig, xs: &[u8]) -> (Vec<u8>, UartStats) {
+        let mut line = UartLine::new(tx, rx);
+        let mut ys = vec![];
+        for (t, x) in xs.iter().enumerate() {
+            line.send(t, *x);
+        }
+        for t in 0..1000 {
+            ys.extend(line.recv(t));
+        }
+        (ys, line.get_stats())
+    }
+
+    #[test]
+    fn test_uart() {
+        assert_eq!(vec![false, true, false, false, false, false, false, true, false, true],
+                   UartConfig::default().encode(0x41));
+        assert!(UartConfig::default().validate().is_ok());
+        assert!(UartConfig { data_bits: 9, ..UartConfig::default() }.validate().is_err());
+        assert!(UartConfig { data_bits: 4, ..UartConfig::default() }.validate().is_err());
+        assert!(UartConfig { baud: 0, ..UartConfig::default() }.validate().is_err());
+        assert!(UartConfig { stop_bits: 0, ..UartConfig::default() }.validate().is_err());
+
+        let config = UartConfig { parity: Parity::Odd, stop_bits: 2, ..UartConfig::default() };
+        let (ys, stats) = transfer(config, config, b"Hi!");
+        assert_eq!(b"Hi!".to_vec(), ys);
+        assert_eq!(3, stats.bytes);
+
+        let even = UartConfig { parity: Parity::Even, ..UartConfig::default() };
+        let odd = UartConfig { parity: Parity::Odd, ..UartConfig::default() };
+        let (ys, stats) = transfer(even, odd, b"Hi!");
+        assert!(ys.is_empty());
+        assert_eq!(3, stats.parity_errors);
+
+        // at twice the speed the receiver reads the first half of each character
+        let slow = UartConfig { baud: 4800, ..UartConfig::default() };
+        let (_, stats) = transfer(slow, UartConfig::default(), &[0x00]);
+        assert_eq!(1, stats.framing_errors);
+    }
+}
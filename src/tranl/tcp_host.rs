@@ -206,7 +206,7 @@ impl TcpHost {
             // FIXME: print ip address correct
             println!("{}({}): receive: {:}",
                      self.get_name(), 
-                     self.ip_base.ip_addr_ports[0].0,
+                     self.get_ip_addr(),
                      &tcp);
         }
 
@@ -220,7 +220,7 @@ impl TcpHost {
             // FIXME: print ip address correct
             println!("{}({}): send   : {:}",            
                      self.get_name(), 
-                     self.ip_base.ip_addr_ports[0].0,
+                     self.get_ip_addr(),
                      &tcp);
         }
 
@@ -268,6 +268,10 @@ impl Device for TcpHost {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
     fn update(&mut self, ctx: &UpdateContext) -> Res<()> {
         if let Some(tcp) = self.consume_inst(&ctx) {
             self.send(tcp, ctx)?;
@@ -5,13 +5,22 @@ pub struct UpdateContext {
     pub t: usize,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct Mac { pub value: u64 }
 
 impl Mac {
     pub fn new(value: u64) -> Mac {
         Mac { value }
     }
+
+    // group bit. the least significant bit of the first octet
+    pub fn is_multicast(&self) -> bool {
+        (self.value >> 40) & 1 == 1
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        self.value == 0xFFFF_FFFF_FFFF
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -46,6 +55,7 @@ pub enum Error {
     MacNotFailed,
     IpUnreashcable { code: u8, msg: String },
     InvalidTcpReceived { msg: String },
+    InvalidConfig { msg: String },
 }
 
 impl fmt::Display for Error {
@@ -75,6 +85,8 @@ impl fmt::Display for Error {
                 write!(f, "IP unreachable error. code={}, msg={}", code, msg ),
             Error::InvalidTcpReceived { msg } =>
                 write!(f, "Invalid TCP received. {}", msg),
+            Error::InvalidConfig { msg } =>
+                write!(f, "Invalid config. {}", msg),
         }
     }
 }
@@ -19,6 +19,20 @@ pub fn read_6bytes(xs: &Vec<u8>, offset: usize) -> u64 {
         | (xs[offset + 5] as u64)
 }
 
+// internet checksum. one's complement of the one's complement sum of 16 bit words
+pub fn checksum(xs: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    for chunk in xs.chunks(2) {
+        let hi = chunk[0] as u32;
+        let lo = if chunk.len() > 1 { chunk[1] as u32 } else { 0 };
+        sum += hi << 8 | lo;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
 pub fn split_2bytes(x: u16) -> [u8; 2] {
     [(x >> 8) as u8, (x & 0xFF) as u8]
 }